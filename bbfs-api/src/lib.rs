@@ -1,23 +1,344 @@
 use std::error::Error;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+pub mod link;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod walk;
+
+#[cfg(feature = "async")]
+pub use nonblocking::{AsyncBbClient, Blocking};
+pub use walk::{find, walk, Walk, WalkEntry, WalkError};
 
 pub trait BbClient: Sync {
     type Item: Clone + Send + Sync;
 
     #[cfg(unix)]
-    type Error: Error + Into<nix::errno::Errno> + Send + Sync;
+    type Error: Error + From<Unsupported> + Into<nix::errno::Errno> + Send + Sync;
     #[cfg(windows)]
-    type Error: Error + Into<winapi::shared::ntdef::NTSTATUS> + Send + Sync;
+    type Error: Error + From<Unsupported> + Into<winapi::shared::ntdef::NTSTATUS> + Send + Sync;
 
     fn get_root(&self) -> Result<Self::Item, Self::Error>;
-    fn get_children(&self, path: Vec<&Self::Item>) -> Result<Vec<Self::Item>, Self::Error>;
+    /// Items carry everything needed to list their own children, so a directory can be listed
+    /// without knowing how it was reached.
+    fn get_children(&self, item: &Self::Item) -> Result<Vec<Self::Item>, Self::Error>;
     fn get_size(&self, item: &Self::Item) -> Result<usize, Self::Error>;
     fn get_contents(&self, item: &Self::Item) -> Result<Vec<u8>, Self::Error>;
     fn get_type(&self, item: &Self::Item) -> ItemType;
     fn get_name(&self, item: &Self::Item) -> Result<String, Self::Error>;
+    /// Identifies the item, staying the same whenever and however often it's listed.
+    fn get_id(&self, item: &Self::Item) -> String;
+    /// Creates a file in a directory whose capabilities allow it, such as to submit it to an
+    /// assignment. The directory should be listed again after this or any of the changes below,
+    /// since they can change more than the one item. Like `get_capabilities`, these only need to
+    /// be overridden by clients that can make changes.
+    fn create_file(
+        &self,
+        _dir: &Self::Item,
+        _name: &str,
+        _contents: &[u8],
+    ) -> Result<(), Self::Error> {
+        Err(Unsupported.into())
+    }
+
+    fn create_folder(&self, _dir: &Self::Item, _name: &str) -> Result<(), Self::Error> {
+        Err(Unsupported.into())
+    }
+
+    /// Renames an item in place, leaving it in the same directory.
+    fn rename(&self, _item: &Self::Item, _name: &str) -> Result<(), Self::Error> {
+        Err(Unsupported.into())
+    }
+
+    /// Deletes an item along with everything in it.
+    fn delete(&self, _item: &Self::Item) -> Result<(), Self::Error> {
+        Err(Unsupported.into())
+    }
+
+    /// Replaces the description of an item, written the way the client shows descriptions.
+    fn set_description(&self, _item: &Self::Item, _description: &str) -> Result<(), Self::Error> {
+        Err(Unsupported.into())
+    }
+
+    /// Clients that can cheaply tell whether a file has changed should override this to include
+    /// an ETag or modification time.
+    fn get_revision(&self, item: &Self::Item) -> Result<Revision, Self::Error> {
+        Ok(Revision {
+            size: self.get_size(item)?,
+            ..Default::default()
+        })
+    }
+
+    /// The web page or file that the item was found at, if it has one.
+    fn get_url(&self, _item: &Self::Item) -> Option<String> {
+        None
+    }
+
+    /// Where a link leads once it's opened. Clients whose links only lead somewhere useful after
+    /// a request or two should override this, so that the request is only made when it's opened.
+    fn get_link_target(&self, item: &Self::Item) -> Option<String> {
+        self.get_url(item)
+    }
+
+    /// Reads up to `size` bytes of a file from `offset` on. Clients that can fetch part of a file
+    /// without downloading all of it should override this.
+    fn get_range(
+        &self,
+        item: &Self::Item,
+        offset: u64,
+        size: usize,
+    ) -> Result<Vec<u8>, Self::Error> {
+        Ok(range_of(&self.get_contents(item)?, offset, size).to_vec())
+    }
+
+    /// Whether the file is too large to be downloaded whole, such as a video, so frontends should
+    /// only read it with `get_range` and not cache or copy it.
+    fn is_streamed(&self, _item: &Self::Item) -> bool {
+        false
+    }
+
+    /// What can be changed about the item. Clients that can change anything should override
+    /// this, since nothing can by default.
+    fn get_capabilities(&self, _item: &Self::Item) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Identifies what a file is downloaded from, so that files with the same key have the same
+    /// contents, such as one file attached in several places. Clients that can tell should
+    /// override this.
+    fn get_content_key(&self, _item: &Self::Item) -> Option<String> {
+        None
+    }
+
+    /// Everything known about the item. Clients that know more than its size and URL should
+    /// override this.
+    fn get_metadata(&self, item: &Self::Item) -> Result<Metadata, Self::Error> {
+        Ok(Metadata {
+            url: self.get_url(item),
+            size: match self.get_type(item) {
+                ItemType::File => Some(self.get_size(item)?),
+                ItemType::Directory | ItemType::Link => None,
+            },
+            ..Default::default()
+        })
+    }
+
+    /// How many bytes of file contents the client is keeping, in memory or on disk.
+    fn get_cache_size(&self) -> u64 {
+        0
+    }
+}
+
+/// Allows a client to be shared between a frontend and background tasks.
+impl<Client: BbClient + Send> BbClient for Arc<Client> {
+    type Item = Client::Item;
+    type Error = Client::Error;
+
+    fn get_root(&self) -> Result<Self::Item, Self::Error> {
+        (**self).get_root()
+    }
+
+    fn get_children(&self, item: &Self::Item) -> Result<Vec<Self::Item>, Self::Error> {
+        (**self).get_children(item)
+    }
+
+    fn get_size(&self, item: &Self::Item) -> Result<usize, Self::Error> {
+        (**self).get_size(item)
+    }
+
+    fn get_contents(&self, item: &Self::Item) -> Result<Vec<u8>, Self::Error> {
+        (**self).get_contents(item)
+    }
+
+    fn get_type(&self, item: &Self::Item) -> ItemType {
+        (**self).get_type(item)
+    }
+
+    fn get_name(&self, item: &Self::Item) -> Result<String, Self::Error> {
+        (**self).get_name(item)
+    }
+
+    fn get_id(&self, item: &Self::Item) -> String {
+        (**self).get_id(item)
+    }
+
+    fn create_file(
+        &self,
+        dir: &Self::Item,
+        name: &str,
+        contents: &[u8],
+    ) -> Result<(), Self::Error> {
+        (**self).create_file(dir, name, contents)
+    }
+
+    fn create_folder(&self, dir: &Self::Item, name: &str) -> Result<(), Self::Error> {
+        (**self).create_folder(dir, name)
+    }
+
+    fn rename(&self, item: &Self::Item, name: &str) -> Result<(), Self::Error> {
+        (**self).rename(item, name)
+    }
+
+    fn delete(&self, item: &Self::Item) -> Result<(), Self::Error> {
+        (**self).delete(item)
+    }
+
+    fn set_description(&self, item: &Self::Item, description: &str) -> Result<(), Self::Error> {
+        (**self).set_description(item, description)
+    }
+
+    fn get_revision(&self, item: &Self::Item) -> Result<Revision, Self::Error> {
+        (**self).get_revision(item)
+    }
+
+    fn get_url(&self, item: &Self::Item) -> Option<String> {
+        (**self).get_url(item)
+    }
+
+    fn get_link_target(&self, item: &Self::Item) -> Option<String> {
+        (**self).get_link_target(item)
+    }
+
+    fn get_range(
+        &self,
+        item: &Self::Item,
+        offset: u64,
+        size: usize,
+    ) -> Result<Vec<u8>, Self::Error> {
+        (**self).get_range(item, offset, size)
+    }
+
+    fn is_streamed(&self, item: &Self::Item) -> bool {
+        (**self).is_streamed(item)
+    }
+
+    fn get_capabilities(&self, item: &Self::Item) -> Capabilities {
+        (**self).get_capabilities(item)
+    }
+
+    fn get_content_key(&self, item: &Self::Item) -> Option<String> {
+        (**self).get_content_key(item)
+    }
+
+    fn get_metadata(&self, item: &Self::Item) -> Result<Metadata, Self::Error> {
+        (**self).get_metadata(item)
+    }
+
+    fn get_cache_size(&self) -> u64 {
+        (**self).get_cache_size()
+    }
 }
 
-#[derive(PartialEq)]
+/// The part of a file's `contents` that reading up to `size` bytes from `offset` on gets, which
+/// is cut short at the end of the file.
+pub fn range_of(contents: &[u8], offset: u64, size: usize) -> &[u8] {
+    let start = usize::try_from(offset).map_or(contents.len(), |offset| offset.min(contents.len()));
+    let end = start.saturating_add(size).min(contents.len());
+    &contents[start..end]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ItemType {
     File,
     Directory,
+    /// A web page, whose address is the item's URL. It has no contents of its own, so frontends
+    /// choose how to show it, such as with a [link file](link) or a symlink.
+    Link,
+}
+
+/// The error that clients give for changes they can't make, which is what the methods making
+/// changes return unless they're overridden.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Unsupported;
+
+impl Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "this can't be changed through the client")
+    }
+}
+
+impl Error for Unsupported {}
+
+/// What can be changed about an item, each through the client method of the same name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Files can be created in the directory
+    pub create_file: bool,
+    /// Folders can be created in the directory
+    pub create_folder: bool,
+    pub rename: bool,
+    pub delete: bool,
+    /// The item's description can be replaced, which for a file showing a description means
+    /// that it can be written to
+    pub set_description: bool,
+}
+
+/// Identifies a version of a file's contents, so that unchanged files don't need to be fetched
+/// again.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Revision {
+    pub size: usize,
+    pub etag: Option<String>,
+    /// The raw `Last-Modified` header, since it only needs to be compared
+    pub last_modified: Option<String>,
+    /// When a locally cached copy of the contents was last confirmed to be this revision, if
+    /// there is one
+    pub validated_at: Option<SystemTime>,
+}
+
+/// Everything known about an item, including where it came from on Blackboard so that it can be
+/// traced back from outside the filesystem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    /// The web page or file that the item was found at, which is where a link points
+    pub url: Option<String>,
+    /// Blackboard's ID for the content item
+    pub content_id: Option<String>,
+    /// Blackboard's ID for the course the item is in
+    pub course_id: Option<String>,
+    pub description: Option<String>,
+    pub mime_type: Option<String>,
+    /// The size of the contents in bytes, if the item is a file
+    pub size: Option<usize>,
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+    /// Whether the item is hidden from students, which only staff can see
+    pub hidden: bool,
+    /// Whether the item can be opened, rather than only being listed
+    pub available: bool,
+    /// When the item is due, if it's an assignment
+    pub due: Option<SystemTime>,
+    /// How the item launches the external tool it opens, if it opens one through Blackboard
+    pub lti_launch: Option<LtiLaunch>,
+}
+
+/// An LTI launch, which is how Blackboard opens external tools such as Gradescope.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LtiLaunch {
+    /// The Blackboard page that launches the tool
+    pub url: String,
+    /// The parameters sent to the tool, such as the IDs of the course and the resource. Empty
+    /// until the tool has been launched.
+    pub parameters: Vec<(String, String)>,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self {
+            url: None,
+            content_id: None,
+            course_id: None,
+            description: None,
+            mime_type: None,
+            size: None,
+            created: None,
+            modified: None,
+            hidden: false,
+            available: true,
+            due: None,
+            lti_launch: None,
+        }
+    }
 }
@@ -0,0 +1,89 @@
+//! Files that open a web page when opened from a file manager, for frontends that show
+//! [`ItemType::Link`](crate::ItemType::Link) items as files.
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// How a frontend shows links to web pages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LinkStyle {
+    /// A file that opens the page when it's opened from a file manager, like a `.desktop` file
+    #[default]
+    File,
+    /// A symlink whose target is the page's URL, which is easier for scripts to read
+    Symlink,
+}
+
+impl FromStr for LinkStyle {
+    type Err = String;
+
+    fn from_str(style: &str) -> Result<Self, Self::Err> {
+        match style {
+            "file" => Ok(Self::File),
+            "symlink" => Ok(Self::Symlink),
+            _ => Err(format!(
+                "unknown link style {style:?}, expected file or symlink"
+            )),
+        }
+    }
+}
+
+impl Display for LinkStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::File => "file",
+            Self::Symlink => "symlink",
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn link_file(url: &str) -> String {
+    format!(
+        "\
+[Desktop Entry]
+Encoding=UTF-8
+Type=Link
+URL={url}
+Icon=text-html
+",
+    )
+}
+
+#[cfg(target_os = "linux")]
+pub const LINK_FILE_EXT: &str = "desktop";
+
+#[cfg(target_os = "linux")]
+pub const LINK_FILE_MIME_TYPE: &str = "application/x-desktop";
+
+#[cfg(target_os = "macos")]
+pub fn link_file(url: &str) -> String {
+    format!("{{ URL = \"{url}\"; }}")
+}
+
+#[cfg(target_os = "macos")]
+pub const LINK_FILE_EXT: &str = "webloc";
+
+#[cfg(target_os = "macos")]
+pub const LINK_FILE_MIME_TYPE: &str = "application/x-webloc";
+
+#[cfg(target_os = "windows")]
+pub fn link_file(url: &str) -> String {
+    format!(
+        "\
+[InternetShortcut]
+URL={url}
+"
+    )
+}
+
+#[cfg(target_os = "windows")]
+pub const LINK_FILE_EXT: &str = "url";
+
+#[cfg(target_os = "windows")]
+pub const LINK_FILE_MIME_TYPE: &str = "application/x-mswinurl";
+
+/// The name of the link file for a link named `name`.
+pub fn link_file_name(name: &str) -> String {
+    format!("{name}.{LINK_FILE_EXT}")
+}
@@ -0,0 +1,198 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::runtime::Runtime;
+
+use crate::{BbClient, Capabilities, ItemType, Metadata, Revision, Unsupported};
+
+/// The async counterpart to [`BbClient`], for clients that can make many requests at once. Each
+/// method does what the [`BbClient`] method of the same name does, but none are defaulted, since
+/// a client worth making async knows more than the defaults do.
+///
+/// Methods that never need to contact Blackboard stay synchronous.
+#[async_trait]
+pub trait AsyncBbClient: Send + Sync {
+    type Item: Clone + Send + Sync;
+
+    #[cfg(unix)]
+    type Error: Error + From<Unsupported> + Into<nix::errno::Errno> + Send + Sync;
+    #[cfg(windows)]
+    type Error: Error + From<Unsupported> + Into<winapi::shared::ntdef::NTSTATUS> + Send + Sync;
+
+    async fn get_root(&self) -> Result<Self::Item, Self::Error>;
+    async fn get_children(&self, item: &Self::Item) -> Result<Vec<Self::Item>, Self::Error>;
+    async fn get_size(&self, item: &Self::Item) -> Result<usize, Self::Error>;
+    async fn get_contents(&self, item: &Self::Item) -> Result<Vec<u8>, Self::Error>;
+    fn get_type(&self, item: &Self::Item) -> ItemType;
+    async fn get_name(&self, item: &Self::Item) -> Result<String, Self::Error>;
+    fn get_id(&self, item: &Self::Item) -> String;
+    async fn create_file(
+        &self,
+        dir: &Self::Item,
+        name: &str,
+        contents: &[u8],
+    ) -> Result<(), Self::Error>;
+    async fn create_folder(&self, dir: &Self::Item, name: &str) -> Result<(), Self::Error>;
+    async fn rename(&self, item: &Self::Item, name: &str) -> Result<(), Self::Error>;
+    async fn delete(&self, item: &Self::Item) -> Result<(), Self::Error>;
+    async fn set_description(
+        &self,
+        item: &Self::Item,
+        description: &str,
+    ) -> Result<(), Self::Error>;
+    async fn get_revision(&self, item: &Self::Item) -> Result<Revision, Self::Error>;
+    fn get_url(&self, item: &Self::Item) -> Option<String>;
+    async fn get_link_target(&self, item: &Self::Item) -> Option<String>;
+    async fn get_range(
+        &self,
+        item: &Self::Item,
+        offset: u64,
+        size: usize,
+    ) -> Result<Vec<u8>, Self::Error>;
+    fn is_streamed(&self, item: &Self::Item) -> bool;
+    fn get_capabilities(&self, item: &Self::Item) -> Capabilities;
+    async fn get_content_key(&self, item: &Self::Item) -> Option<String>;
+    async fn get_metadata(&self, item: &Self::Item) -> Result<Metadata, Self::Error>;
+    fn get_cache_size(&self) -> u64;
+}
+
+/// Runs an [`AsyncBbClient`] on a runtime of its own, so that it can be used by frontends that
+/// expect a [`BbClient`].
+pub struct Blocking<Client> {
+    client: Arc<Client>,
+    runtime: Arc<Runtime>,
+}
+
+impl<Client> Clone for Blocking<Client> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            runtime: self.runtime.clone(),
+        }
+    }
+}
+
+impl<Client: AsyncBbClient> Blocking<Client> {
+    pub fn new(client: Client) -> std::io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("bbfs-client")
+            .build()?;
+        Ok(Self {
+            client: Arc::new(client),
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// The runtime the client runs on, for spawning other tasks that use it.
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+}
+
+impl<Client: AsyncBbClient> BbClient for Blocking<Client> {
+    type Item = Client::Item;
+    type Error = Client::Error;
+
+    fn get_root(&self) -> Result<Self::Item, Self::Error> {
+        self.runtime.block_on(self.client.get_root())
+    }
+
+    fn get_children(&self, item: &Self::Item) -> Result<Vec<Self::Item>, Self::Error> {
+        self.runtime.block_on(self.client.get_children(item))
+    }
+
+    fn get_size(&self, item: &Self::Item) -> Result<usize, Self::Error> {
+        self.runtime.block_on(self.client.get_size(item))
+    }
+
+    fn get_contents(&self, item: &Self::Item) -> Result<Vec<u8>, Self::Error> {
+        self.runtime.block_on(self.client.get_contents(item))
+    }
+
+    fn get_type(&self, item: &Self::Item) -> ItemType {
+        self.client.get_type(item)
+    }
+
+    fn get_name(&self, item: &Self::Item) -> Result<String, Self::Error> {
+        self.runtime.block_on(self.client.get_name(item))
+    }
+
+    fn get_id(&self, item: &Self::Item) -> String {
+        self.client.get_id(item)
+    }
+
+    fn create_file(
+        &self,
+        dir: &Self::Item,
+        name: &str,
+        contents: &[u8],
+    ) -> Result<(), Self::Error> {
+        self.runtime
+            .block_on(self.client.create_file(dir, name, contents))
+    }
+
+    fn create_folder(&self, dir: &Self::Item, name: &str) -> Result<(), Self::Error> {
+        self.runtime.block_on(self.client.create_folder(dir, name))
+    }
+
+    fn rename(&self, item: &Self::Item, name: &str) -> Result<(), Self::Error> {
+        self.runtime.block_on(self.client.rename(item, name))
+    }
+
+    fn delete(&self, item: &Self::Item) -> Result<(), Self::Error> {
+        self.runtime.block_on(self.client.delete(item))
+    }
+
+    fn set_description(&self, item: &Self::Item, description: &str) -> Result<(), Self::Error> {
+        self.runtime
+            .block_on(self.client.set_description(item, description))
+    }
+
+    fn get_revision(&self, item: &Self::Item) -> Result<Revision, Self::Error> {
+        self.runtime.block_on(self.client.get_revision(item))
+    }
+
+    fn get_url(&self, item: &Self::Item) -> Option<String> {
+        self.client.get_url(item)
+    }
+
+    fn get_link_target(&self, item: &Self::Item) -> Option<String> {
+        self.runtime.block_on(self.client.get_link_target(item))
+    }
+
+    fn get_range(
+        &self,
+        item: &Self::Item,
+        offset: u64,
+        size: usize,
+    ) -> Result<Vec<u8>, Self::Error> {
+        self.runtime
+            .block_on(self.client.get_range(item, offset, size))
+    }
+
+    fn is_streamed(&self, item: &Self::Item) -> bool {
+        self.client.is_streamed(item)
+    }
+
+    fn get_capabilities(&self, item: &Self::Item) -> Capabilities {
+        self.client.get_capabilities(item)
+    }
+
+    fn get_content_key(&self, item: &Self::Item) -> Option<String> {
+        self.runtime.block_on(self.client.get_content_key(item))
+    }
+
+    fn get_metadata(&self, item: &Self::Item) -> Result<Metadata, Self::Error> {
+        self.runtime.block_on(self.client.get_metadata(item))
+    }
+
+    fn get_cache_size(&self) -> u64 {
+        self.client.get_cache_size()
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{BbClient, ItemType};
+
+/// An item found while walking a tree.
+pub struct WalkEntry<Item> {
+    /// The names leading to the item, relative to where the walk started
+    pub path: Vec<String>,
+    pub item: Item,
+    pub ty: ItemType,
+}
+
+/// A directory that couldn't be listed or an item that couldn't be named while walking a tree.
+pub struct WalkError<Error> {
+    /// The names leading to the directory or item, relative to where the walk started
+    pub path: Vec<String>,
+    pub error: Error,
+}
+
+/// Finds the item at `path` (a list of names relative to the root).
+pub fn find<Client: BbClient>(
+    client: &Client,
+    path: &[&str],
+) -> Result<Option<Client::Item>, Client::Error> {
+    let mut item = client.get_root()?;
+    for name in path {
+        let children = client.get_children(&item)?;
+        let mut child = None;
+        for child_item in children {
+            if client.get_name(&child_item)? == *name {
+                child = Some(child_item);
+                break;
+            }
+        }
+        match child {
+            Some(child) => item = child,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(item))
+}
+
+type WalkResult<Client> =
+    Result<WalkEntry<<Client as BbClient>::Item>, WalkError<<Client as BbClient>::Error>>;
+
+/// A depth-first walk over every item below a directory.
+///
+/// Errors are yielded in place of the items they affect and the rest of the tree is still walked,
+/// so a single inaccessible folder doesn't stop a walk over a whole course.
+pub struct Walk<'c, Client: BbClient> {
+    client: &'c Client,
+    /// Directories still to be listed, along with their path
+    pending: Vec<(Client::Item, Vec<String>)>,
+    entries: Vec<WalkResult<Client>>,
+}
+
+/// Walks everything below the directory `item`.
+pub fn walk<Client: BbClient>(client: &Client, item: Client::Item) -> Walk<'_, Client> {
+    Walk {
+        client,
+        pending: vec![(item, vec![])],
+        entries: vec![],
+    }
+}
+
+impl<'c, Client: BbClient> Walk<'c, Client> {
+    fn list(&mut self, directory: Client::Item, path: Vec<String>) {
+        let children = match self.client.get_children(&directory) {
+            Ok(children) => children,
+            Err(error) => {
+                self.entries.push(Err(WalkError { path, error }));
+                return;
+            }
+        };
+
+        let mut entries = vec![];
+        let mut directories = vec![];
+        for item in children {
+            let name = match self.client.get_name(&item) {
+                Ok(name) => name,
+                Err(error) => {
+                    entries.push(Err(WalkError {
+                        path: path.clone(),
+                        error,
+                    }));
+                    continue;
+                }
+            };
+            let mut child_path = path.clone();
+            child_path.push(name);
+
+            let ty = self.client.get_type(&item);
+            if ty == ItemType::Directory {
+                directories.push((item.clone(), child_path.clone()));
+            }
+            entries.push(Ok(WalkEntry {
+                path: child_path,
+                item,
+                ty,
+            }));
+        }
+
+        // Both stacks are popped from the end, so reverse them to walk in listing order
+        self.entries.extend(entries.into_iter().rev());
+        self.pending.extend(directories.into_iter().rev());
+    }
+}
+
+impl<'c, Client: BbClient> Iterator for Walk<'c, Client> {
+    type Item = WalkResult<Client>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.entries.is_empty() {
+            let (directory, path) = self.pending.pop()?;
+            self.list(directory, path);
+        }
+        self.entries.pop()
+    }
+}
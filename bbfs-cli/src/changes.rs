@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+use argh::FromArgs;
+use bbfs_api::{BbClient, ItemType, WalkError};
+use bbfs_scrape::{Announcement, BbScrapeClient, Item};
+use serde::{Deserialize, Serialize};
+
+/// The change report shown as WHATS_NEW.md at the root of the filesystem.
+pub const REPORT_FILE: &str = "WHATS_NEW.md";
+
+#[derive(FromArgs)]
+/// Show what was added, removed or modified on Blackboard since the last check
+#[argh(subcommand, name = "changes")]
+pub struct ChangesCommand {
+    /// only check these courses, given as their paths within the filesystem (e.g. COMP3506)
+    #[argh(positional)]
+    courses: Vec<String>,
+}
+
+/// What was known about an item the last time its course was checked.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemRecord {
+    /// The Blackboard URL identifying the item, if it has one
+    pub id: Option<String>,
+    pub directory: bool,
+    pub size: Option<usize>,
+    pub last_modified: Option<String>,
+}
+
+/// Every item within a course, by its path relative to the course.
+pub type CourseRecord = BTreeMap<String, ItemRecord>;
+
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub added: Vec<(String, ItemRecord)>,
+    pub removed: Vec<(String, ItemRecord)>,
+    pub modified: Vec<(String, ItemRecord)>,
+    /// The titles of announcements posted since the last check
+    pub announcements: Vec<String>,
+    /// Set when the course had never been checked before, so there was nothing to compare to
+    pub first_check: bool,
+}
+
+impl Changes {
+    fn between(previous: Option<CourseRecord>, current: &CourseRecord) -> Self {
+        let Some(previous) = previous else {
+            return Self {
+                first_check: true,
+                ..Default::default()
+            };
+        };
+
+        let mut changes = Self::default();
+        for (path, record) in current {
+            match previous.get(path) {
+                None => changes.added.push((path.clone(), record.clone())),
+                Some(previous_record) if previous_record != record => {
+                    changes.modified.push((path.clone(), record.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        for (path, record) in previous {
+            if !current.contains_key(&path) {
+                changes.removed.push((path, record));
+            }
+        }
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+            && self.announcements.is_empty()
+    }
+}
+
+/// Records the tree of each course between checks, to find out what changed in the meantime.
+pub struct ChangeTracker {
+    dir: PathBuf,
+}
+
+impl ChangeTracker {
+    pub fn open(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .map_err(|err| anyhow!("failed to create {}: {err}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    pub fn report_path(&self) -> PathBuf {
+        self.dir.join(REPORT_FILE)
+    }
+
+    fn record_path(&self, course: &str, kind: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.{kind}.json", course.replace(['/', '\\'], "--")))
+    }
+
+    /// Walks the course at `item`, recording its tree and returning how it
+    /// changed since it was last checked.
+    ///
+    /// If any part of the course couldn't be walked, nothing is recorded, since missing items
+    /// would otherwise be reported as removed.
+    pub fn check<Client: BbClient>(
+        &self,
+        client: &Client,
+        course: &str,
+        item: Client::Item,
+    ) -> anyhow::Result<Changes> {
+        let mut current = CourseRecord::new();
+        for entry in bbfs_api::walk(client, item) {
+            let entry = entry.map_err(|WalkError { path, error }| {
+                anyhow!("failed to check {course}/{}: {error}", path.join("/"))
+            })?;
+            let (size, last_modified) = match entry.ty {
+                // Finding out the size of a streamed file is a request of its own, and new ones
+                // are noticed by their paths anyway
+                ItemType::File if client.is_streamed(&entry.item) => (None, None),
+                ItemType::File => {
+                    let revision = client
+                        .get_revision(&entry.item)
+                        .map_err(|err| anyhow!("failed to check {course}: {err}"))?;
+                    (Some(revision.size), revision.last_modified)
+                }
+                ItemType::Directory | ItemType::Link => (None, None),
+            };
+            current.insert(
+                entry.path.join("/"),
+                ItemRecord {
+                    id: client.get_url(&entry.item),
+                    directory: entry.ty == ItemType::Directory,
+                    size,
+                    last_modified,
+                },
+            );
+        }
+
+        let record_path = self.record_path(course, "tree");
+        let previous = fs::read(&record_path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok());
+        let changes = Changes::between(previous, &current);
+        fs::write(&record_path, serde_json::to_vec_pretty(&current)?)
+            .map_err(|err| anyhow!("failed to save {}: {err}", record_path.display()))?;
+        Ok(changes)
+    }
+
+    /// Records the announcements of a course, returning the titles of those that weren't there at
+    /// the last check, or `None` if the course's announcements had never been checked.
+    pub fn check_announcements(
+        &self,
+        course: &str,
+        announcements: &[Announcement],
+    ) -> anyhow::Result<Option<Vec<String>>> {
+        let record_path = self.record_path(course, "announcements");
+        let previous: Option<Vec<String>> = fs::read(&record_path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok());
+        let current: Vec<_> = announcements
+            .iter()
+            .map(|announcement| announcement.id.clone())
+            .collect();
+        fs::write(&record_path, serde_json::to_vec_pretty(&current)?)
+            .map_err(|err| anyhow!("failed to save {}: {err}", record_path.display()))?;
+
+        Ok(previous.map(|previous| {
+            announcements
+                .iter()
+                .filter(|announcement| !previous.contains(&announcement.id))
+                .map(|announcement| announcement.title.clone())
+                .collect()
+        }))
+    }
+
+    /// The report shown as WHATS_NEW.md, which is empty until anything has been checked.
+    pub fn report(&self) -> String {
+        fs::read_to_string(self.report_path()).unwrap_or_default()
+    }
+
+    /// Saves the report shown as WHATS_NEW.md.
+    pub fn save_report(&self, report: &str) -> anyhow::Result<()> {
+        fs::write(self.report_path(), report)
+            .map_err(|err| anyhow!("failed to save {REPORT_FILE}: {err}"))
+    }
+}
+
+fn describe(path: &str, record: &ItemRecord) -> String {
+    if record.directory {
+        format!("{path}/")
+    } else {
+        path.into()
+    }
+}
+
+/// The heading of the change report, which is followed by a section for each course.
+const REPORT_HEADING: &str = "# What's new on Blackboard\n";
+
+/// Renders the changes found in each course as Markdown.
+pub fn render_report(courses: &[(String, anyhow::Result<Changes>)]) -> String {
+    let checked = httpdate::fmt_http_date(SystemTime::now());
+    let mut report = REPORT_HEADING.to_string();
+    for (course, changes) in courses {
+        let _ = write!(report, "\n## {course}\n\nChecked {checked}.\n\n");
+        let changes = match changes {
+            Ok(changes) => changes,
+            Err(err) => {
+                let _ = writeln!(report, "Couldn't be checked: {err}");
+                continue;
+            }
+        };
+        if changes.first_check {
+            let _ = writeln!(report, "First check, so there's nothing to compare to yet.");
+            continue;
+        }
+        if changes.is_empty() {
+            let _ = writeln!(report, "Nothing has changed.");
+            continue;
+        }
+        if !changes.announcements.is_empty() {
+            let _ = write!(report, "### Announcements\n\n");
+            for title in &changes.announcements {
+                let _ = writeln!(report, "- {title}");
+            }
+            report.push('\n');
+        }
+        for (heading, items) in [
+            ("Added", &changes.added),
+            ("Modified", &changes.modified),
+            ("Removed", &changes.removed),
+        ] {
+            if items.is_empty() {
+                continue;
+            }
+            let _ = write!(report, "### {heading}\n\n");
+            for (path, record) in items {
+                let _ = writeln!(report, "- {}", describe(path, record));
+            }
+            report.push('\n');
+        }
+    }
+    report
+}
+
+/// Each course's section of a report, by the course's name.
+fn report_sections(report: &str) -> Vec<(&str, &str)> {
+    (report.split("\n## ").skip(1))
+        .map(|section| (section.split('\n').next().unwrap_or_default(), section))
+        .collect()
+}
+
+/// Replaces the sections of `previous` with those of the courses in `report`, so that courses
+/// that weren't checked this time keep what was found at their last check.
+fn merge_reports(previous: &str, report: &str) -> String {
+    let mut checked = report_sections(report);
+    let mut merged = REPORT_HEADING.to_string();
+    for (course, section) in report_sections(previous) {
+        let section = match checked.iter().position(|(name, _)| *name == course) {
+            Some(index) => checked.remove(index).1,
+            None => section,
+        };
+        let _ = write!(merged, "\n## {section}");
+    }
+    for (_, section) in checked {
+        let _ = write!(merged, "\n## {section}");
+    }
+    merged
+}
+
+fn check_course(
+    client: &BbScrapeClient,
+    tracker: &ChangeTracker,
+    course: &str,
+    item: Item,
+) -> anyhow::Result<Changes> {
+    let announcements = client
+        .get_announcements(&item)
+        .map_err(|err| anyhow!("failed to get announcements of {course}: {err}"))?;
+    let mut changes = tracker.check(client, course, item)?;
+    if let Some(new_announcements) = tracker.check_announcements(course, &announcements)? {
+        changes.announcements = new_announcements;
+    }
+    Ok(changes)
+}
+
+/// Checks every course selected by `filter` (or every course if it's empty) for changes, and
+/// updates their sections of WHATS_NEW.md with the result.
+pub fn check_courses(
+    client: &BbScrapeClient,
+    tracker: &ChangeTracker,
+    filter: &[String],
+) -> anyhow::Result<Vec<(String, anyhow::Result<Changes>)>> {
+    let courses = client
+        .courses()
+        .map_err(|err| anyhow!("failed to list courses: {err}"))?;
+
+    let mut results = vec![];
+    for (path, item) in courses {
+        let course = path.join("/");
+        if !filter.is_empty() && !filter.contains(&course) {
+            continue;
+        }
+        let changes = check_course(client, tracker, &course, item);
+        results.push((course, changes));
+    }
+
+    let report = render_report(&results);
+    let report = match filter.is_empty() {
+        true => report,
+        false => merge_reports(&tracker.report(), &report),
+    };
+    tracker.save_report(&report)?;
+    Ok(results)
+}
+
+pub fn changes(
+    client: &BbScrapeClient,
+    tracker: &ChangeTracker,
+    command: &ChangesCommand,
+) -> anyhow::Result<()> {
+    let results = check_courses(client, tracker, &command.courses)?;
+    print!("{}", render_report(&results));
+    Ok(())
+}
+
+/// Where change tracking records are kept within the data directory.
+pub fn tracker_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("changes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checking_some_courses_keeps_the_others_in_the_report() {
+        let previous = render_report(&[
+            ("COMP3506".into(), Ok(Changes::default())),
+            ("MATH1051".into(), Err(anyhow!("offline"))),
+        ]);
+        let checked = render_report(&[
+            ("MATH1051".into(), Ok(Changes::default())),
+            ("STAT1201".into(), Ok(Changes::default())),
+        ]);
+        let merged = merge_reports(&previous, &checked);
+
+        let courses: Vec<_> = report_sections(&merged)
+            .into_iter()
+            .map(|(course, _)| course)
+            .collect();
+        assert_eq!(courses, ["COMP3506", "MATH1051", "STAT1201"]);
+        assert!(merged.starts_with(REPORT_HEADING));
+        assert!(!merged.contains("offline"));
+        assert_eq!(merged.matches("Nothing has changed.").count(), 3);
+    }
+}
@@ -0,0 +1,214 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+use argh::FromArgs;
+use bbfs_api::link::{link_file, link_file_name};
+use bbfs_api::{BbClient, ItemType, WalkError};
+use flate2::write::GzEncoder;
+use serde::Serialize;
+
+use crate::sync::join_path;
+
+/// Lists where everything in the archive came from, stored at the root of the archive.
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(FromArgs)]
+/// Export a course as a .zip or .tar.gz archive
+#[argh(subcommand, name = "export")]
+pub struct ExportCommand {
+    /// the archive to write, ending in .zip, .tar.gz or .tgz
+    #[argh(option, short = 'o')]
+    output: PathBuf,
+    /// the course to export, as its path within the filesystem (e.g. COMP3506)
+    #[argh(positional)]
+    course: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum EntryKind {
+    File,
+    Directory,
+    /// A link file that opens the entry's URL
+    Link,
+}
+
+#[derive(Serialize)]
+struct ManifestEntry {
+    path: String,
+    kind: EntryKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Manifest {
+    course: String,
+    url: Option<String>,
+    entries: Vec<ManifestEntry>,
+}
+
+enum Archive {
+    Zip(zip::ZipWriter<File>),
+    TarGz(tar::Builder<GzEncoder<File>>),
+}
+
+impl Archive {
+    fn create(path: &Path) -> anyhow::Result<Self> {
+        let name = path.to_string_lossy();
+        if name.ends_with(".zip") {
+            Ok(Self::Zip(zip::ZipWriter::new(File::create(path)?)))
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Ok(Self::TarGz(tar::Builder::new(GzEncoder::new(
+                File::create(path)?,
+                flate2::Compression::default(),
+            ))))
+        } else {
+            Err(anyhow!("expected the output to end in .zip, .tar.gz or .tgz"))
+        }
+    }
+
+    fn tar_header(entry_type: tar::EntryType, mode: u32, size: usize) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_size(size as u64);
+        header.set_mtime(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+        );
+        header
+    }
+
+    fn add_directory(&mut self, path: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Zip(zip) => zip.add_directory(path, zip::write::FileOptions::default())?,
+            Self::TarGz(tar) => {
+                let mut header = Self::tar_header(tar::EntryType::Directory, 0o755, 0);
+                tar.append_data(&mut header, format!("{path}/"), std::io::empty())?
+            }
+        }
+        Ok(())
+    }
+
+    fn add_file(&mut self, path: &str, contents: &[u8]) -> anyhow::Result<()> {
+        match self {
+            Self::Zip(zip) => {
+                zip.start_file(
+                    path,
+                    zip::write::FileOptions::default()
+                        .compression_method(zip::CompressionMethod::Deflated),
+                )?;
+                zip.write_all(contents)?;
+            }
+            Self::TarGz(tar) => {
+                let mut header = Self::tar_header(tar::EntryType::Regular, 0o644, contents.len());
+                tar.append_data(&mut header, path, contents)?
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        match self {
+            Self::Zip(mut zip) => {
+                zip.finish()?;
+            }
+            Self::TarGz(tar) => {
+                tar.into_inner()?.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn export<Client: BbClient>(client: &Client, command: &ExportCommand) -> anyhow::Result<()> {
+    let path: Vec<_> = command
+        .course
+        .split('/')
+        .filter(|name| !name.is_empty())
+        .collect();
+    let course = bbfs_api::find(client, &path)
+        .map_err(|err| anyhow!("failed to find {}: {err}", command.course))?
+        .ok_or_else(|| anyhow!("no course at {}", command.course))?;
+    if client.get_type(&course) != ItemType::Directory {
+        return Err(anyhow!("{} is not a directory", command.course));
+    }
+
+    // Everything is placed within a directory named after the course
+    let course_name = client
+        .get_name(&course)
+        .map_err(|err| anyhow!("failed to get name of {}: {err}", command.course))?;
+    let mut manifest = Manifest {
+        course: course_name.clone(),
+        url: client.get_url(&course),
+        entries: vec![],
+    };
+
+    let mut archive = Archive::create(&command.output)?;
+    // Titles come from Blackboard, so they're kept from escaping the archive when it's extracted
+    let course_dir = join_path(std::slice::from_ref(&course_name));
+    archive.add_directory(&course_dir)?;
+
+    let mut failed = 0;
+    for entry in bbfs_api::walk(client, course) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(WalkError { path, error }) => {
+                eprintln!("! {course_dir}/{}: {error}", join_path(&path));
+                failed += 1;
+                continue;
+            }
+        };
+
+        let mut path = format!("{course_dir}/{}", join_path(&entry.path));
+        let added = match entry.ty {
+            ItemType::Directory => archive.add_directory(&path).map(|()| EntryKind::Directory),
+            // Streamed files such as lecture recordings are too large to archive
+            ItemType::File if client.is_streamed(&entry.item) => continue,
+            ItemType::File => client
+                .get_contents(&entry.item)
+                .map_err(|err| anyhow!("{err}"))
+                .and_then(|contents| archive.add_file(&path, &contents))
+                .map(|()| EntryKind::File),
+            ItemType::Link => {
+                path = link_file_name(&path);
+                let url = client.get_url(&entry.item).unwrap_or_default();
+                archive
+                    .add_file(&path, link_file(&url).as_bytes())
+                    .map(|()| EntryKind::Link)
+            }
+        };
+        let kind = match added {
+            Ok(kind) => kind,
+            Err(err) => {
+                eprintln!("! {path}: {err}");
+                failed += 1;
+                continue;
+            }
+        };
+        println!("{path}");
+        manifest.entries.push(ManifestEntry {
+            path,
+            kind,
+            url: client.get_url(&entry.item),
+        });
+    }
+
+    archive.add_file(MANIFEST_FILE, &serde_json::to_vec_pretty(&manifest)?)?;
+    archive.finish()?;
+
+    if failed > 0 {
+        Err(anyhow!(
+            "{failed} items could not be exported and are missing from {}",
+            command.output.display()
+        ))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
+
+/// The name of the log file written by the daemon within the data directory.
+pub const LOG_FILE: &str = "bbfs.log";
+
+/// How large the log file may grow before it's rotated.
+const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+/// How many rotated log files are kept alongside the current one.
+const ROTATED_LOGS_KEPT: usize = 3;
+
+/// A log file that's moved aside to `<name>.1` (shifting older logs along) once it reaches
+/// `MAX_LOG_SIZE`.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.into(),
+            file,
+            size,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{index}"));
+        self.path.with_file_name(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for index in (1..ROTATED_LOGS_KEPT).rev() {
+            let _ = fs::rename(self.rotated_path(index), self.rotated_path(index + 1));
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+        *self = Self::open(&self.path)?;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > MAX_LOG_SIZE {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Opens the file the daemon's stderr is sent to, which catches panics and anything printed by
+/// libraries. The daemon writes to it directly rather than through `RotatingFile`, so it's only
+/// rotated when the daemon starts.
+pub fn open_stderr_log(path: &Path) -> io::Result<File> {
+    let mut log = RotatingFile::open(path)?;
+    if log.size > MAX_LOG_SIZE {
+        log.rotate()?;
+    }
+    Ok(log.file)
+}
+
+/// Where logs are written, which is stderr until [`LogWriter::to_file`] is called.
+#[derive(Clone, Default)]
+pub struct LogWriter(Arc<Mutex<Option<RotatingFile>>>);
+
+impl LogWriter {
+    /// Writes logs to `path` from now on, with size-based rotation.
+    pub fn to_file(&self, path: &Path) -> anyhow::Result<()> {
+        let file = RotatingFile::open(path)
+            .map_err(|err| anyhow!("failed to open {}: {err}", path.display()))?;
+        *self.0.lock().unwrap() = Some(file);
+        Ok(())
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut *self.0.lock().unwrap() {
+            Some(file) => file.write(buf),
+            None => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut *self.0.lock().unwrap() {
+            Some(file) => file.flush(),
+            None => io::stderr().flush(),
+        }
+    }
+}
+
+/// Sets up logging for the whole process. `filter` takes a level or `tracing` filter directives
+/// (e.g. `bbfs_scrape=debug`), falling back to `RUST_LOG` and then `info`. Logs go to stderr
+/// until the returned writer is pointed at a file, and are only coloured if `ansi` is set.
+pub fn init(filter: Option<&str>, ansi: bool) -> anyhow::Result<LogWriter> {
+    let filter = match filter {
+        Some(filter) => EnvFilter::try_new(filter)
+            .map_err(|err| anyhow!("invalid log level {filter}: {err}"))?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    let writer = LogWriter::default();
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(BoxMakeWriter::new({
+            let writer = writer.clone();
+            move || writer.clone()
+        }))
+        .with_ansi(ansi)
+        // Closing a span logs how long the HTTP request or filesystem operation took
+        .with_span_events(FmtSpan::CLOSE)
+        .init();
+    Ok(writer)
+}
@@ -1,17 +1,30 @@
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use std::{fs::File, path::Path};
 
 use anyhow::anyhow;
 use argh::FromArgs;
-use cookie_monster::{is_cookie_valid, CookieMonster, HeadlessCookieMonster, WebViewCookieMonster};
+use bbfs_api::link::LinkStyle;
+use cookie_monster::{
+    is_cookie_valid, AuthError, CookieMonster, CredentialSources, HeadlessCookieMonster,
+    WebViewCookieMonster,
+};
 use etcetera::BaseStrategy;
+use tracing::warn;
 
 #[cfg(windows)]
 use bbfs_dokan::Bbfs;
 #[cfg(unix)]
 use bbfs_fuse::Bbfs;
-use bbfs_scrape::BbScrapeClient;
+use bbfs_scrape::{BbScrapeClient, CourseNameTemplate, DescriptionFormat, RequestPolicy, Snapshot};
+
+mod changes;
+mod export;
+mod logging;
+mod notifications;
+mod sync;
 
 #[derive(FromArgs)]
 /// A CLI tool to authenticate to and mount BlackboardFS
@@ -25,19 +38,96 @@ struct BbfsCli {
     /// uses headless auth flow
     #[argh(switch)]
     headless: bool,
+    /// a file of username, password and totp_secret lines to sign in with headlessly, for
+    /// whatever BBFS_USERNAME, BBFS_PASSWORD and BBFS_TOTP_SECRET don't give
+    #[argh(option)]
+    credentials_file: Option<PathBuf>,
+    /// a command that prints the password to sign in with headlessly on its first line, followed
+    /// by lines like the credentials file's, such as `pass show uq`
+    #[argh(option)]
+    credentials_command: Option<String>,
+    /// seconds to wait for signing in headlessly, including a Duo push (default: 300)
+    #[argh(option, default = "300")]
+    auth_timeout: u64,
+    /// serves the last known tree and downloaded files without contacting Blackboard
+    #[argh(switch)]
+    offline: bool,
+    /// template for course directory names using {{code}}, {{id}}, {{display_name}}, {{term}} and
+    /// {{year}}, with / separating directories (default: {{code}})
+    #[argh(option, default = "CourseNameTemplate::default()")]
+    course_name: CourseNameTemplate,
+    /// the format to show the descriptions of content items in: md, html or txt (default: md)
+    #[argh(option, default = "DescriptionFormat::default()")]
+    description_format: DescriptionFormat,
+    /// shows a desktop notification when files, folders or announcements appear in a course
+    #[argh(switch)]
+    notify: bool,
+    /// how often to check courses for changes in the background, in minutes, or 0 to never check
+    /// (default: 30 with --notify, otherwise never)
+    #[argh(option)]
+    refresh_interval: Option<u64>,
+    /// seconds to wait to connect to Blackboard and for each read once connected (default: 5)
+    #[argh(option, default = "5")]
+    timeout: u64,
+    /// times to retry a request to Blackboard that failed in a way that might be temporary
+    /// (default: 3)
+    #[argh(option, default = "3")]
+    retries: u32,
+    /// the most requests to Blackboard in progress at once (default: 4)
+    #[argh(option, default = "4")]
+    max_concurrent_requests: usize,
+    /// the most requests to Blackboard started each second, or 0 for no limit (default: 10)
+    #[argh(option, default = "10")]
+    max_requests_per_second: u32,
+    /// fetches the sizes of a folder's files at once, and lists subfolders and downloads small
+    /// files in the background, whenever a folder is opened
+    #[argh(switch)]
+    prefetch: bool,
+    /// the largest file to download in the background when prefetching, in KiB (default: 1024)
+    #[argh(option, default = "1024")]
+    prefetch_max_size: usize,
+    /// how to show links to web pages: file, for files that open them from a file manager, or
+    /// symlink, for symlinks to their URLs (default: file)
+    #[argh(option, default = "LinkStyle::default()")]
+    link_style: LinkStyle,
+    /// shows copies of a file attached in several places as symlinks to the first copy found
+    #[argh(switch)]
+    symlink_duplicates: bool,
+    /// the level to log at, or filter directives such as bbfs_scrape=debug (default: info, or
+    /// RUST_LOG if set)
+    #[argh(option)]
+    log_level: Option<String>,
     /// the path to mount the Blackboard filesystem at
     #[argh(positional)]
-    mount_point: PathBuf,
+    mount_point: Option<PathBuf>,
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Changes(changes::ChangesCommand),
+    Export(export::ExportCommand),
+    Mute(notifications::MuteCommand),
+    Sync(sync::SyncCommand),
+    Unmute(notifications::UnmuteCommand),
 }
 
 fn exit_error<E: std::fmt::Display>(e: E) -> ! {
+    exit_with(1, e)
+}
+
+fn exit_with<E: std::fmt::Display>(code: i32, e: E) -> ! {
     eprintln!("Error: {e}");
-    std::process::exit(1);
+    std::process::exit(code);
 }
 
 impl BbfsCli {
     fn normalized_mount_point(&self) -> PathBuf {
         self.mount_point
+            .as_ref()
+            .unwrap_or_else(|| exit_error("expected a mount point or a command"))
             .canonicalize()
             .unwrap_or_else(|e| exit_error(e))
     }
@@ -45,25 +135,128 @@ impl BbfsCli {
 
 fn main() -> anyhow::Result<()> {
     let args: BbfsCli = argh::from_env();
-    let mount_point = args.normalized_mount_point();
     let data_dir = get_data_dir();
 
-    let cookies = if args.headless {
-        authenticate(HeadlessCookieMonster, &data_dir)
-    } else {
-        authenticate(WebViewCookieMonster, &data_dir)
+    // The daemon logs to a file once it's started, so its logs aren't coloured
+    let logs = logging::init(
+        args.log_level.as_deref(),
+        args.command.is_some() || args.monitor,
+    )?;
+
+    match &args.command {
+        Some(Command::Changes(command)) => {
+            let tracker = changes::ChangeTracker::open(changes::tracker_dir(&data_dir))?;
+            changes::changes(&create_client(&args, &data_dir)?, &tracker, command)
+        }
+        Some(Command::Export(command)) => {
+            export::export(&create_client(&args, &data_dir)?, command)
+        }
+        Some(Command::Mute(command)) => notifications::mute(&data_dir, command),
+        Some(Command::Sync(command)) => sync::sync(&create_client(&args, &data_dir)?, command),
+        Some(Command::Unmute(command)) => notifications::unmute(&data_dir, command),
+        None => mount(&args, &data_dir, &logs),
     }
-    .map_err(|err| anyhow!("failed to authenticate {err}"))?;
+}
+
+fn mount(args: &BbfsCli, data_dir: &Path, logs: &logging::LogWriter) -> anyhow::Result<()> {
+    let mount_point = args.normalized_mount_point();
+    // Only the mounted filesystem shows the change report, so that it isn't synced or exported
+    let client = Arc::new(
+        create_client(args, data_dir)?
+            .with_whats_new(changes::tracker_dir(data_dir).join(changes::REPORT_FILE))
+            .with_metadata_prefetch(args.prefetch),
+    );
 
     if !args.monitor {
-        daemonize(&data_dir);
+        daemonize(data_dir);
+        // The daemon has no terminal to log to, so it logs to a file instead
+        logs.to_file(&data_dir.join(logging::LOG_FILE))?;
     }
 
-    let client = BbScrapeClient::new(cookies, args.all);
-    let fs = Bbfs::new(client).map_err(|_| anyhow!("failed to initialize Blackboard fs driver"))?;
-    fs.mount(&mount_point)?;
+    // Only check in the background when asked to, since it goes through every course
+    let refresh_interval = args.refresh_interval.or(args.notify.then_some(30));
+    if let Some(minutes @ 1..) = refresh_interval {
+        notifications::spawn_refresher(
+            client.clone(),
+            data_dir.to_path_buf(),
+            Duration::from_secs(minutes * 60),
+            args.notify,
+        )?;
+    } else if args.notify {
+        warn!("notifications are disabled since the refresh interval is 0");
+    }
+
+    let fs = Bbfs::new(client.clone())
+        .map_err(|_| anyhow!("failed to initialize Blackboard fs driver"))?;
+    // Windows can't show symlinks or prefetch yet
+    #[cfg(unix)]
+    let fs = {
+        let mut fs = fs.with_link_style(args.link_style);
+        if args.symlink_duplicates {
+            fs = fs.with_symlinked_duplicates();
+        }
+        if args.prefetch {
+            fs = fs.with_prefetch(bbfs_fuse::PrefetchOptions {
+                workers: args.max_concurrent_requests,
+                max_file_size: args.prefetch_max_size * 1024,
+            });
+        }
+        fs
+    };
+    let mounted = fs.mount(&mount_point);
+    // The refresher still holds the client, so the snapshot isn't written out by dropping it
+    client.flush_snapshot();
+    mounted
+}
+
+fn create_client(args: &BbfsCli, data_dir: &Path) -> anyhow::Result<BbScrapeClient> {
+    let snapshot_dir = data_dir.join("snapshot");
+
+    let mut offline = args.offline;
+    let cookies = if offline {
+        String::new()
+    } else {
+        let cookies = if args.headless {
+            let cookie_monster = HeadlessCookieMonster::new()
+                .with_credentials(CredentialSources {
+                    file: args.credentials_file.clone(),
+                    command: args.credentials_command.clone(),
+                })
+                .with_timeout(Duration::from_secs(args.auth_timeout));
+            authenticate(cookie_monster, data_dir)
+        } else {
+            authenticate(WebViewCookieMonster, data_dir)
+        };
+        match cookies {
+            Ok(cookies) => cookies,
+            // Going offline wouldn't fix these, and scripts can tell them apart by the exit code
+            Err(err) if err.is::<AuthError>() => {
+                let code = err.downcast_ref::<AuthError>().unwrap().exit_code();
+                exit_with(code, format!("failed to authenticate: {err}"))
+            }
+            Err(err) if Snapshot::exists(&snapshot_dir) => {
+                warn!("failed to authenticate ({err}), falling back to offline mode");
+                offline = true;
+                String::new()
+            }
+            Err(err) => return Err(anyhow!("failed to authenticate {err}")),
+        }
+    };
 
-    Ok(())
+    let snapshot = Snapshot::open(&snapshot_dir)
+        .map_err(|err| anyhow!("failed to open snapshot of Blackboard: {err}"))?;
+    Ok(BbScrapeClient::new(cookies, args.all)
+        .with_request_policy(RequestPolicy {
+            timeout: Duration::from_secs(args.timeout),
+            retries: args.retries,
+            max_concurrent_requests: args.max_concurrent_requests,
+            max_requests_per_second: Some(args.max_requests_per_second).filter(|rate| *rate > 0),
+            ..Default::default()
+        })
+        .with_course_name_template(args.course_name.clone())
+        .with_description_format(args.description_format)
+        .with_snapshot(snapshot)
+        .with_offline(offline))
 }
 
 fn get_data_dir() -> PathBuf {
@@ -77,11 +270,10 @@ fn get_data_dir() -> PathBuf {
 #[cfg(unix)]
 fn daemonize(data_dir: &Path) {
     use daemonize_me::Daemon;
-    let stdout =
-        File::create(data_dir.join("stdout.log")).expect("failed to create stdout log file");
-    let stderr =
-        File::create(data_dir.join("stderr.log")).expect("failed to create stderr log file");
-    Daemon::new().stdout(stdout).stderr(stderr).start().unwrap();
+    // Everything is logged through `tracing`, so only panics and libraries write to stderr
+    let stderr = logging::open_stderr_log(&data_dir.join("stderr.log"))
+        .expect("failed to open stderr log file");
+    Daemon::new().stderr(stderr).start().unwrap();
 }
 
 #[cfg(not(unix))]
@@ -105,7 +297,7 @@ fn authenticate<Monster: CookieMonster>(
     // Attempt to cache the cookie and warn if that fails
     File::create(&cookie_cache_file)
         .and_then(|mut file| file.write_all(cookie.as_bytes()))
-        .map_err(|_| eprintln!("failed to cache cookie"))
+        .map_err(|err| warn!("failed to cache cookie: {err}"))
         .ok();
 
     Ok(cookie)
@@ -0,0 +1,328 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use argh::FromArgs;
+use bbfs_scrape::BbScrapeClient;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::changes::{self, ChangeTracker, Changes};
+
+/// The most items listed in the body of a single notification.
+const MAX_LISTED_ITEMS: usize = 5;
+
+#[derive(FromArgs)]
+/// Stop showing desktop notifications about a course
+#[argh(subcommand, name = "mute")]
+pub struct MuteCommand {
+    /// the course to mute, as its path within the filesystem (e.g. COMP3506)
+    #[argh(positional)]
+    course: String,
+}
+
+#[derive(FromArgs)]
+/// Show desktop notifications about a course again
+#[argh(subcommand, name = "unmute")]
+pub struct UnmuteCommand {
+    /// the course to unmute, as its path within the filesystem (e.g. COMP3506)
+    #[argh(positional)]
+    course: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Settings {
+    muted: BTreeSet<String>,
+}
+
+impl Settings {
+    fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join("notifications.json")
+    }
+
+    fn load(data_dir: &Path) -> anyhow::Result<Self> {
+        match fs::read(Self::path(data_dir)) {
+            Ok(json) => serde_json::from_slice(&json)
+                .map_err(|err| anyhow!("failed to parse notification settings: {err}")),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    fn save(&self, data_dir: &Path) -> anyhow::Result<()> {
+        fs::write(Self::path(data_dir), serde_json::to_vec_pretty(self)?)
+            .map_err(|err| anyhow!("failed to save notification settings: {err}"))
+    }
+}
+
+pub fn mute(data_dir: &Path, command: &MuteCommand) -> anyhow::Result<()> {
+    let mut settings = Settings::load(data_dir)?;
+    settings.muted.insert(command.course.clone());
+    settings.save(data_dir)
+}
+
+pub fn unmute(data_dir: &Path, command: &UnmuteCommand) -> anyhow::Result<()> {
+    let mut settings = Settings::load(data_dir)?;
+    if !settings.muted.remove(&command.course) {
+        return Err(anyhow!("{} isn't muted", command.course));
+    }
+    settings.save(data_dir)
+}
+
+/// Summarises what appeared in a course, or returns `None` if there's nothing worth a
+/// notification.
+fn describe(course: &str, changes: &Changes) -> Option<(String, String)> {
+    if changes.first_check {
+        return None;
+    }
+
+    let mut lines: Vec<_> = changes
+        .announcements
+        .iter()
+        .map(|title| format!("Announcement: {title}"))
+        .collect();
+    lines.extend(changes.added.iter().map(|(path, record)| {
+        if record.directory {
+            format!("{path}/")
+        } else {
+            path.clone()
+        }
+    }));
+    if lines.is_empty() {
+        return None;
+    }
+
+    let summary = match lines.len() {
+        1 => format!("New in {course}"),
+        count => format!("{count} new items in {course}"),
+    };
+    let mut body = lines[..lines.len().min(MAX_LISTED_ITEMS)].join("\n");
+    if lines.len() > MAX_LISTED_ITEMS {
+        body.push_str(&format!("\nand {} more", lines.len() - MAX_LISTED_ITEMS));
+    }
+    Some((summary, body))
+}
+
+/// Shows desktop notifications, keeping one connection to the notification daemon open between
+/// them.
+#[cfg(target_os = "linux")]
+struct Notifier {
+    connection: zbus::blocking::Connection,
+}
+
+#[cfg(target_os = "linux")]
+impl Notifier {
+    fn connect() -> anyhow::Result<Self> {
+        Ok(Self {
+            connection: zbus::blocking::Connection::session()?,
+        })
+    }
+
+    fn notify(&self, summary: &str, body: &str) -> anyhow::Result<()> {
+        use std::collections::HashMap;
+
+        self.connection.call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                "BlackboardFS",
+                0u32,
+                "",
+                summary,
+                body,
+                Vec::<&str>::new(),
+                HashMap::<&str, zbus::zvariant::Value>::new(),
+                -1i32,
+            ),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct Notifier;
+
+#[cfg(not(target_os = "linux"))]
+impl Notifier {
+    fn connect() -> anyhow::Result<Self> {
+        Err(anyhow!("desktop notifications are only supported on Linux"))
+    }
+
+    fn notify(&self, _summary: &str, _body: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+fn refresh(
+    client: &BbScrapeClient,
+    tracker: &ChangeTracker,
+    data_dir: &Path,
+    notifier: Option<&Notifier>,
+) {
+    let results = match changes::check_courses(client, tracker, &[]) {
+        Ok(results) => results,
+        Err(err) => {
+            error!("failed to refresh courses: {err}");
+            return;
+        }
+    };
+    let Some(notifier) = notifier else {
+        return;
+    };
+
+    let settings = Settings::load(data_dir).unwrap_or_else(|err| {
+        warn!("{err}");
+        Settings::default()
+    });
+    for (course, changes) in results {
+        let changes = match changes {
+            Ok(changes) => changes,
+            Err(err) => {
+                warn!("{err}");
+                continue;
+            }
+        };
+        if settings.muted.contains(&course) {
+            continue;
+        }
+        if let Some((summary, body)) = describe(&course, &changes) {
+            if let Err(err) = notifier.notify(&summary, &body) {
+                warn!("failed to send notification: {err}");
+            }
+        }
+    }
+}
+
+/// Periodically checks every course for changes in the background, keeping WHATS_NEW.md up to
+/// date and, if `notify_enabled` is set, showing a desktop notification for new content.
+pub fn spawn_refresher(
+    client: Arc<BbScrapeClient>,
+    data_dir: PathBuf,
+    interval: Duration,
+    notify_enabled: bool,
+) -> anyhow::Result<()> {
+    let tracker = ChangeTracker::open(changes::tracker_dir(&data_dir))?;
+    let notifier = match notify_enabled.then(Notifier::connect).transpose() {
+        Ok(notifier) => notifier,
+        Err(err) => {
+            warn!("desktop notifications are disabled: {err}");
+            None
+        }
+    };
+    thread::Builder::new()
+        .name("refresher".into())
+        .spawn(move || loop {
+            if !client.is_offline() {
+                refresh(&client, &tracker, &data_dir, notifier.as_ref());
+            }
+            thread::sleep(interval);
+        })?;
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::os::unix::net::UnixStream;
+    use std::sync::mpsc;
+
+    use zbus::blocking::ConnectionBuilder;
+    use zbus::{dbus_interface, zvariant::Value, Guid};
+
+    use super::*;
+    use crate::changes::ItemRecord;
+
+    /// Stands in for the desktop's notification daemon, passing on what it's asked to show.
+    struct FakeDaemon {
+        shown: mpsc::Sender<(String, String)>,
+    }
+
+    #[dbus_interface(name = "org.freedesktop.Notifications")]
+    impl FakeDaemon {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: &str,
+            _replaces_id: u32,
+            _app_icon: &str,
+            summary: &str,
+            body: &str,
+            _actions: Vec<&str>,
+            _hints: HashMap<&str, Value<'_>>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            self.shown.send((summary.into(), body.into())).unwrap();
+            1
+        }
+    }
+
+    /// A notifier connected straight to a fake daemon, rather than through the session bus.
+    fn connect_to_fake_daemon() -> (Notifier, mpsc::Receiver<(String, String)>) {
+        let (shown, received) = mpsc::channel();
+        let (daemon_stream, notifier_stream) = UnixStream::pair().unwrap();
+        // Both ends have to be built at once for the handshake to finish
+        let daemon = thread::spawn(move || {
+            let guid = Guid::generate();
+            ConnectionBuilder::unix_stream(daemon_stream)
+                .server(&guid)
+                .p2p()
+                .serve_at("/org/freedesktop/Notifications", FakeDaemon { shown })
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let connection = ConnectionBuilder::unix_stream(notifier_stream)
+            .p2p()
+            .build()
+            .unwrap();
+        // Dropping the daemon's connection would stop it serving
+        std::mem::forget(daemon.join().unwrap());
+        (Notifier { connection }, received)
+    }
+
+    fn changes(added: &[&str]) -> Changes {
+        let record = ItemRecord {
+            id: None,
+            directory: false,
+            size: None,
+            last_modified: None,
+        };
+        Changes {
+            added: added
+                .iter()
+                .map(|path| (path.to_string(), record.clone()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn notifications_reach_the_daemon_over_one_connection() {
+        let (notifier, shown) = connect_to_fake_daemon();
+        let (summary, body) = describe("COMP3506", &changes(&["Week 1/slides.pdf"])).unwrap();
+        notifier.notify(&summary, &body).unwrap();
+        let (summary, body) = describe("COMP3506", &changes(&["a.pdf", "b.pdf"])).unwrap();
+        notifier.notify(&summary, &body).unwrap();
+
+        assert_eq!(
+            shown.recv().unwrap(),
+            ("New in COMP3506".into(), "Week 1/slides.pdf".into())
+        );
+        assert_eq!(
+            shown.recv().unwrap(),
+            ("2 new items in COMP3506".into(), "a.pdf\nb.pdf".into())
+        );
+    }
+
+    #[test]
+    fn nothing_is_shown_for_the_first_check() {
+        let mut changes = changes(&["a.pdf"]);
+        changes.first_check = true;
+        assert_eq!(describe("COMP3506", &changes), None);
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use argh::FromArgs;
+use bbfs_api::link::{link_file, link_file_name};
+use bbfs_api::{BbClient, ItemType, Revision, WalkError};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+/// Records what was downloaded by previous syncs, relative to the sync directory.
+const MANIFEST_FILE: &str = ".bbfs-sync.json";
+
+#[derive(FromArgs)]
+/// Download Blackboard into a normal directory, only fetching files that changed since the last
+/// sync
+#[argh(subcommand, name = "sync")]
+pub struct SyncCommand {
+    /// only sync files whose path (relative to the directory) matches one of these globs
+    #[argh(option, short = 'i')]
+    include: Vec<String>,
+    /// skip files whose path (relative to the directory) matches one of these globs
+    #[argh(option, short = 'x')]
+    exclude: Vec<String>,
+    /// print what would change without touching the directory
+    #[argh(switch, short = 'n')]
+    dry_run: bool,
+    /// the directory to sync Blackboard into
+    #[argh(positional)]
+    dir: PathBuf,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct SyncedFile {
+    size: usize,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl From<Revision> for SyncedFile {
+    fn from(revision: Revision) -> Self {
+        Self {
+            size: revision.size,
+            etag: revision.etag,
+            last_modified: revision.last_modified,
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Manifest {
+    files: BTreeMap<String, SyncedFile>,
+}
+
+impl Manifest {
+    fn load(dir: &Path) -> anyhow::Result<Self> {
+        match fs::read(dir.join(MANIFEST_FILE)) {
+            Ok(json) => serde_json::from_slice(&json)
+                .map_err(|err| anyhow!("failed to parse {MANIFEST_FILE}: {err}")),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        fs::write(dir.join(MANIFEST_FILE), serde_json::to_vec_pretty(self)?)
+            .map_err(|err| anyhow!("failed to save {MANIFEST_FILE}: {err}"))
+    }
+}
+
+struct Filter {
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+impl Filter {
+    fn new(include: &[String], exclude: &[String]) -> anyhow::Result<Self> {
+        fn build(globs: &[String]) -> anyhow::Result<GlobSet> {
+            let mut builder = GlobSetBuilder::new();
+            for glob in globs {
+                builder.add(Glob::new(glob).map_err(|err| anyhow!("invalid glob {glob}: {err}"))?);
+            }
+            Ok(builder.build()?)
+        }
+
+        Ok(Self {
+            include: build(include)?,
+            exclude: build(exclude)?,
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.is_match(path)) && !self.exclude.is_match(path)
+    }
+}
+
+#[derive(Default)]
+struct Summary {
+    added: usize,
+    changed: usize,
+    removed: usize,
+    unchanged: usize,
+    failed: usize,
+}
+
+/// Joins path components into the `/`-separated form used by globs and the manifest, replacing
+/// anything that would escape the sync directory or archive.
+pub(crate) fn join_path(path: &[String]) -> String {
+    path.iter()
+        .map(|name| match name.as_str() {
+            "" | "." | ".." => "_".into(),
+            name => name.replace(['/', '\\'], "-"),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write to a temporary file first so that an interrupted sync never leaves a truncated file
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".bbfs-partial");
+    let temp_path = path.with_file_name(temp_name);
+    fs::write(&temp_path, contents)?;
+    fs::rename(temp_path, path)
+}
+
+/// Removes `path` and then any of its ancestors below `root` that are left empty.
+fn remove_file(root: &Path, path: &Path) -> std::io::Result<()> {
+    fs::remove_file(path)?;
+    for ancestor in path.ancestors().skip(1) {
+        if ancestor == root || fs::remove_dir(ancestor).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub fn sync<Client: BbClient>(client: &Client, command: &SyncCommand) -> anyhow::Result<()> {
+    let filter = Filter::new(&command.include, &command.exclude)?;
+    if !command.dry_run {
+        fs::create_dir_all(&command.dir)?;
+    }
+    let mut manifest = Manifest::load(&command.dir)?;
+    let mut summary = Summary::default();
+
+    let root = client
+        .get_root()
+        .map_err(|err| anyhow!("failed to get Blackboard root: {err}"))?;
+
+    let mut seen = HashSet::new();
+    // Directories that couldn't be listed, whose files shouldn't be treated as removed
+    let mut failed_dirs = vec![];
+
+    for entry in bbfs_api::walk(client, root) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(WalkError { path, error }) => {
+                let path = join_path(&path);
+                eprintln!("! {path}: {error}");
+                summary.failed += 1;
+                failed_dirs.push(path);
+                continue;
+            }
+        };
+        // Links are synced as link files, which are made up rather than downloaded
+        let mut entry_path = entry.path.clone();
+        let link = match entry.ty {
+            // Streamed files such as lecture recordings are too large to keep a copy of
+            ItemType::File if client.is_streamed(&entry.item) => continue,
+            ItemType::File => None,
+            ItemType::Directory => continue,
+            ItemType::Link => {
+                if let Some(name) = entry_path.last_mut() {
+                    *name = link_file_name(name);
+                }
+                Some(link_file(&client.get_url(&entry.item).unwrap_or_default()))
+            }
+        };
+
+        let path = join_path(&entry_path);
+        if !filter.matches(&path) {
+            continue;
+        }
+        seen.insert(path.clone());
+
+        let revision: SyncedFile = match &link {
+            Some(link) => Revision {
+                size: link.len(),
+                ..Default::default()
+            }
+            .into(),
+            None => match client.get_revision(&entry.item) {
+                Ok(revision) => revision.into(),
+                Err(err) => {
+                    eprintln!("! {path}: {err}");
+                    summary.failed += 1;
+                    continue;
+                }
+            },
+        };
+
+        let local_path = command.dir.join(&path);
+        let local_size = fs::metadata(&local_path).ok().map(|metadata| metadata.len());
+        let previous = manifest.files.get(&path);
+        if previous == Some(&revision) && local_size == Some(revision.size as u64) {
+            summary.unchanged += 1;
+            continue;
+        }
+
+        let added = previous.is_none() || local_size.is_none();
+        if !command.dry_run {
+            let contents = match link {
+                Some(link) => Ok(link.into_bytes()),
+                None => client
+                    .get_contents(&entry.item)
+                    .map_err(|err| anyhow!("{err}")),
+            };
+            let result = contents.and_then(|contents| Ok(write_file(&local_path, &contents)?));
+            if let Err(err) = result {
+                eprintln!("! {path}: {err}");
+                summary.failed += 1;
+                continue;
+            }
+            manifest.files.insert(path.clone(), revision);
+        }
+
+        if added {
+            println!("+ {path}");
+            summary.added += 1;
+        } else {
+            println!("~ {path}");
+            summary.changed += 1;
+        }
+    }
+
+    let removed: Vec<_> = manifest
+        .files
+        .keys()
+        .filter(|path| {
+            !seen.contains(*path)
+                && filter.matches(path)
+                && !failed_dirs
+                    .iter()
+                    .any(|dir| dir.is_empty() || path.starts_with(&format!("{dir}/")))
+        })
+        .cloned()
+        .collect();
+    for path in removed {
+        if !command.dry_run {
+            let local_path = command.dir.join(&path);
+            if let Err(err) = remove_file(&command.dir, &local_path) {
+                if local_path.exists() {
+                    eprintln!("! {path}: {err}");
+                    summary.failed += 1;
+                    continue;
+                }
+            }
+            manifest.files.remove(&path);
+        }
+        println!("- {path}");
+        summary.removed += 1;
+    }
+
+    if !command.dry_run {
+        manifest.save(&command.dir)?;
+    }
+
+    println!(
+        "{}{} added, {} changed, {} removed, {} unchanged, {} failed",
+        if command.dry_run { "(dry run) " } else { "" },
+        summary.added,
+        summary.changed,
+        summary.removed,
+        summary.unchanged,
+        summary.failed,
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_path_keeps_names_within_the_root() {
+        let path = |names: &[&str]| {
+            join_path(
+                &names
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(
+            path(&["COMP3506", "Week 1", "notes.pdf"]),
+            "COMP3506/Week 1/notes.pdf"
+        );
+        assert_eq!(path(&["..", "etc", "passwd"]), "_/etc/passwd");
+        assert_eq!(path(&[".", ""]), "_/_");
+        assert_eq!(path(&["Week 1/2", "a\\b"]), "Week 1-2/a-b");
+    }
+}
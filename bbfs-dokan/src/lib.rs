@@ -1,18 +1,19 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{self, AtomicU64};
-use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::sync::{Mutex, OnceLock};
 use std::time::SystemTime;
 
 use camino::Utf8PathBuf;
 use dokan::{CreateFileInfo, FileSystemHandler, FileSystemMounter, MountOptions};
+use tracing::{debug_span, warn};
 use widestring::UCString;
-use winapi::shared::ntdef::NTSTATUS;
 use winapi::shared::ntstatus::{
     STATUS_DATA_ERROR, STATUS_FILE_IS_A_DIRECTORY, STATUS_NO_SUCH_FILE,
 };
 use winapi::um::winnt::{FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_READONLY};
 
+use bbfs_api::link::{link_file, link_file_name};
 use bbfs_scrape::client::{BbClient, BbError, ItemType};
 
 #[derive(Clone)]
@@ -54,20 +55,6 @@ impl<Client: BbClient> Bbfs<Client> {
 }
 
 impl<Client: BbClient> Bbfs<Client> {
-    fn item_ancestors<'a>(
-        &self,
-        lock: &'a MutexGuard<'_, HashMap<Utf8PathBuf, ItemNode<Client::Item>>>,
-        node: &ItemNode<Client::Item>,
-    ) -> Vec<&'a Client::Item> {
-        let mut ancestors = node
-            .path
-            .ancestors()
-            .map(|path| &lock.get(path).unwrap().item)
-            .collect::<Vec<_>>();
-        ancestors.reverse();
-        ancestors
-    }
-
     fn normalize_path(&self, path: &widestring::U16CStr) -> Utf8PathBuf {
         let path = PathBuf::from(path.to_os_string());
         Utf8PathBuf::from_path_buf(
@@ -97,6 +84,44 @@ impl<Client: BbClient> Bbfs<Client> {
         )
         .expect("transformed string should remain valid utf-8")
     }
+
+    /// The contents of the link file that a link item is shown as.
+    fn link_file(&self, item: &Client::Item) -> String {
+        link_file(&self.client.get_link_target(item).unwrap_or_default())
+    }
+
+    fn contents(&self, item: &Client::Item) -> dokan::OperationResult<Vec<u8>> {
+        match self.client.get_type(item) {
+            ItemType::Link => Ok(self.link_file(item).into_bytes()),
+            ItemType::File | ItemType::Directory => Ok(self.client.get_contents(item)?),
+        }
+    }
+
+    /// The size and last modification time of the file that `item` is shown as. A link is listed
+    /// as empty until it's `opened`, since finding where it leads can launch a tool.
+    fn file_details(
+        &self,
+        item: &Client::Item,
+        opened: bool,
+    ) -> dokan::OperationResult<(u64, SystemTime)> {
+        match self.client.get_type(item) {
+            ItemType::Link if opened => {
+                Ok((self.link_file(item).len() as u64, SystemTime::UNIX_EPOCH))
+            }
+            ItemType::Link => Ok((0, SystemTime::UNIX_EPOCH)),
+            ItemType::File | ItemType::Directory => {
+                let metadata = self.client.get_metadata(item)?;
+                let size = match metadata.size {
+                    Some(size) => size,
+                    None => self.client.get_size(item)?,
+                };
+                Ok((
+                    size as u64,
+                    metadata.modified.unwrap_or(SystemTime::UNIX_EPOCH),
+                ))
+            }
+        }
+    }
 }
 
 impl<'c, 'h: 'c, Client: BbClient + 'h> FileSystemHandler<'c, 'h> for Bbfs<Client> {
@@ -142,7 +167,7 @@ impl<'c, 'h: 'c, Client: BbClient + 'h> FileSystemHandler<'c, 'h> for Bbfs<Clien
     ) -> dokan::OperationResult<dokan::CreateFileInfo<Self::Context>> {
         let lock = self.paths.lock().unwrap();
         let path = Utf8PathBuf::from(self.normalize_path(file_name));
-        println!("create_file {path}");
+        let _span = debug_span!("create_file", %path).entered();
         match lock.get(&path) {
             Some(item) => Ok(CreateFileInfo {
                 is_dir: item.is_dir,
@@ -161,36 +186,34 @@ impl<'c, 'h: 'c, Client: BbClient + 'h> FileSystemHandler<'c, 'h> for Bbfs<Clien
         _info: &dokan::OperationInfo<'c, 'h, Self>,
         node: &'c Self::Context,
     ) -> dokan::OperationResult<u32> {
-        println!(
-            "read_file {} offset: {offset} size: {}",
-            node.path,
-            buffer.len()
-        );
+        let _span =
+            debug_span!("read_file", path = %node.path, offset, size = buffer.len()).entered();
 
-        if self.client.get_type(&node.item) != ItemType::File {
-            eprintln!("attempted to read a directory");
+        if self.client.get_type(&node.item) == ItemType::Directory {
+            warn!("attempted to read a directory");
             return Err(STATUS_FILE_IS_A_DIRECTORY);
         }
 
+        // Streamed files are too large to download whole, so only the part being read is fetched
+        if self.client.is_streamed(&node.item) {
+            let contents = self
+                .client
+                .get_range(&node.item, offset as u64, buffer.len())?;
+            buffer[..contents.len()].copy_from_slice(&contents);
+            return Ok(contents.len() as _);
+        }
+
         let offset = offset as usize;
         let end_offset = offset + buffer.len();
-        let contents = self
-            .client
-            .get_contents(&node.item)
-            .map_err(Into::<NTSTATUS>::into)
-            .and_then(|contents| {
-                contents
-                    .get(offset..end_offset.min(contents.len()))
-                    .ok_or(STATUS_DATA_ERROR)
-                    .map(|contents| contents.to_vec())
-                    .map_err(|err| {
-                        eprintln!(
-                            "invalid offset ({offset}) for read of file of size {}",
-                            contents.len()
-                        );
-                        err
-                    })
-            })?;
+        let contents = self.contents(&node.item).and_then(|contents| {
+            contents
+                .get(offset..end_offset.min(contents.len()))
+                .ok_or(STATUS_DATA_ERROR)
+                .map(|contents| contents.to_vec())
+                .inspect_err(|_| {
+                    warn!("invalid offset for read of file of size {}", contents.len())
+                })
+        })?;
         buffer[..contents.len()].copy_from_slice(&contents);
         Ok(contents.len() as _)
     }
@@ -201,6 +224,11 @@ impl<'c, 'h: 'c, Client: BbClient + 'h> FileSystemHandler<'c, 'h> for Bbfs<Clien
         _info: &dokan::OperationInfo<'c, 'h, Self>,
         node: &'c Self::Context,
     ) -> dokan::OperationResult<dokan::FileInfo> {
+        let (file_size, last_write_time) = if node.is_dir {
+            (0, SystemTime::UNIX_EPOCH)
+        } else {
+            self.file_details(&node.item, true)?
+        };
         Ok(dokan::FileInfo {
             // ? Should FILE_ATTRIBUTE_OFFLINE be set here as well?
             attributes: FILE_ATTRIBUTE_READONLY
@@ -210,13 +238,9 @@ impl<'c, 'h: 'c, Client: BbClient + 'h> FileSystemHandler<'c, 'h> for Bbfs<Clien
                     0
                 },
             creation_time: SystemTime::UNIX_EPOCH,
-            last_access_time: SystemTime::UNIX_EPOCH,
-            last_write_time: SystemTime::UNIX_EPOCH,
-            file_size: if node.is_dir {
-                0
-            } else {
-                self.client.get_size(&node.item)? as _
-            },
+            last_access_time: last_write_time,
+            last_write_time,
+            file_size,
             number_of_links: 1,
             file_index: node.index,
         })
@@ -230,16 +254,20 @@ impl<'c, 'h: 'c, Client: BbClient + 'h> FileSystemHandler<'c, 'h> for Bbfs<Clien
         node: &'c Self::Context,
     ) -> dokan::OperationResult<()> {
         let mut lock = self.paths.lock().unwrap();
-        println!("find_files {}", node.path);
+        let _span = debug_span!("find_files", path = %node.path).entered();
 
         let children = if let Some(children) = node.children.get() {
             children
         } else {
-            let items = self.client.get_children(self.item_ancestors(&lock, node))?;
+            let items = self.client.get_children(&node.item)?;
 
             let mut paths = vec![];
             for item in items {
-                let item_name = self.sanitize_name(self.client.get_name(&item)?);
+                let mut item_name = self.client.get_name(&item)?;
+                if self.client.get_type(&item) == ItemType::Link {
+                    item_name = link_file_name(&item_name);
+                }
+                let item_name = self.sanitize_name(item_name);
                 let child_node = ItemNode {
                     path: node.path.join(item_name),
                     index: self.next_index(),
@@ -258,6 +286,11 @@ impl<'c, 'h: 'c, Client: BbClient + 'h> FileSystemHandler<'c, 'h> for Bbfs<Clien
 
         for child in children {
             let child = &lock[child];
+            let (file_size, last_write_time) = if child.is_dir {
+                (0, SystemTime::UNIX_EPOCH)
+            } else {
+                self.file_details(&child.item, false)?
+            };
             fill_find_data(&dokan::FindData {
                 // ? Should FILE_ATTRIBUTE_OFFLINE be set here as well?
                 attributes: FILE_ATTRIBUTE_READONLY
@@ -267,13 +300,9 @@ impl<'c, 'h: 'c, Client: BbClient + 'h> FileSystemHandler<'c, 'h> for Bbfs<Clien
                         0
                     },
                 creation_time: SystemTime::UNIX_EPOCH,
-                last_access_time: SystemTime::UNIX_EPOCH,
-                last_write_time: SystemTime::UNIX_EPOCH,
-                file_size: if child.is_dir {
-                    0
-                } else {
-                    self.client.get_size(&child.item)? as _
-                },
+                last_access_time: last_write_time,
+                last_write_time,
+                file_size,
                 file_name: UCString::<u16>::from_str(
                     child.path.file_name().expect("paths should all have names"),
                 )
@@ -1,31 +1,50 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::ffi::OsStr;
-use std::path::PathBuf;
-use std::time::{Duration, UNIX_EPOCH};
+use std::fmt::Display;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use fuser::{
-    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry,
-    Request,
+    consts, FileAttr, FileType, Filesystem, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory,
+    ReplyEmpty, ReplyEntry, ReplyOpen, ReplyStatfs, ReplyWrite, ReplyXattr, Request, TimeOrNow,
 };
-use libc::{EIO, ENOENT};
+use libc::{EACCES, EINVAL, ENOENT, ERANGE, EROFS};
 use nix::errno::Errno;
+use tracing::{debug_span, warn};
 
+use bbfs_api::link::{link_file, link_file_name, LinkStyle, LINK_FILE_MIME_TYPE};
 use bbfs_api::{BbClient, ItemType};
 
+mod prefetch;
+#[cfg(test)]
+mod tests;
+
+pub use prefetch::PrefetchOptions;
+use prefetch::{Job, Prefetcher};
+
 // TODO: Figure out the best TTL (if any)
 const TTL: Duration = Duration::from_secs(1);
 
 const BLOCK_SIZE: u32 = 512;
 
-fn attr(inode: u64, nlink: u32, kind: FileType, size: u64, perm: u16) -> FileAttr {
+const MAX_NAME_LENGTH: u32 = 255;
+
+fn attr(
+    inode: u64,
+    nlink: u32,
+    kind: FileType,
+    size: u64,
+    perm: u16,
+    mtime: SystemTime,
+) -> FileAttr {
     FileAttr {
         ino: inode,
         size,
-        blocks: (size + (BLOCK_SIZE as u64) - 1) / (BLOCK_SIZE as u64),
-        atime: UNIX_EPOCH,
-        mtime: UNIX_EPOCH,
-        ctime: UNIX_EPOCH,
+        blocks: size.div_ceil(BLOCK_SIZE as u64),
+        atime: mtime,
+        mtime,
+        ctime: mtime,
         crtime: UNIX_EPOCH,
         kind,
         perm,
@@ -38,14 +57,68 @@ fn attr(inode: u64, nlink: u32, kind: FileType, size: u64, perm: u16) -> FileAtt
     }
 }
 
-fn dirattr(inode: u64) -> FileAttr {
-    // TODO: Correctly calculate nlink for directory
-    attr(inode, 2, FileType::Directory, 0, 0o500)
+fn dirattr(inode: u64, nlink: u32, writable: bool) -> FileAttr {
+    let perm = if writable { 0o700 } else { 0o500 };
+    attr(inode, nlink, FileType::Directory, 0, perm, UNIX_EPOCH)
+}
+
+fn fileattr(inode: u64, size: u64, mtime: SystemTime, writable: bool) -> FileAttr {
+    let perm = if writable { 0o600 } else { 0o400 };
+    // Nothing is hard linked so nlink can just be hardcoded to 1 for files
+    attr(inode, 1, FileType::RegularFile, size, perm, mtime)
+}
+
+fn uploadattr(inode: u64, size: u64) -> FileAttr {
+    attr(inode, 1, FileType::RegularFile, size, 0o600, UNIX_EPOCH)
+}
+
+fn symlinkattr(inode: u64, target: &str) -> FileAttr {
+    // Symlink permissions are never checked, so they're conventionally all set
+    attr(
+        inode,
+        1,
+        FileType::Symlink,
+        target.len() as u64,
+        0o777,
+        UNIX_EPOCH,
+    )
 }
 
-fn fileattr(inode: u64, size: u64) -> FileAttr {
-    // We don't handle symlinks so nlink can just be hardcoded to 1 for files
-    attr(inode, 1, FileType::RegularFile, size, 0o400)
+/// The error for an extended attribute that isn't set.
+#[cfg(target_os = "linux")]
+const NO_XATTR: i32 = libc::ENODATA;
+#[cfg(not(target_os = "linux"))]
+const NO_XATTR: i32 = libc::ENOATTR;
+
+/// Replies with an extended attribute value or list, or just its length if `size` is 0, as
+/// callers use that to find out how large a buffer they need.
+fn reply_xattr(reply: ReplyXattr, size: u32, data: &[u8]) {
+    if size == 0 {
+        reply.size(data.len() as u32)
+    } else if data.len() > size as usize {
+        reply.error(ERANGE)
+    } else {
+        reply.data(data)
+    }
+}
+
+/// Logs an error from the client before it's returned to the kernel, which only sees the errno.
+fn errno<E: Display + Into<Errno>>(err: E) -> Errno {
+    warn!("{err}");
+    err.into()
+}
+
+/// Where a link item leads once it's opened.
+fn link_target<Client: BbClient>(client: &Client, item: &Client::Item) -> String {
+    client.get_link_target(item).unwrap_or_default()
+}
+
+/// The contents of a file, which for a link is a link file.
+fn file_contents<Client: BbClient>(client: &Client, item: &Client::Item) -> Result<Vec<u8>, Errno> {
+    match client.get_type(item) {
+        ItemType::Link => Ok(link_file(&link_target(client, item)).into_bytes()),
+        ItemType::File | ItemType::Directory => client.get_contents(item).map_err(errno),
+    }
 }
 
 #[derive(Clone)]
@@ -57,12 +130,89 @@ struct ItemInode<Item> {
     item: Item,
     /// If None, the children haven't been loaded from the client yet
     children: Option<Vec<u64>>,
+    /// Whether the children have been handed to the prefetcher
+    prefetched: bool,
+    /// Where the symlink points if this is a file shown as a symlink to the same file elsewhere
+    duplicate_of: Option<String>,
+}
+
+/// A file being written into a writable directory, which is handed to the client once it's closed.
+/// It isn't an item until then, so it's kept apart from the inodes of items.
+struct Upload {
+    parent: u64,
+    name: String,
+    contents: Vec<u8>,
+    /// Whether it's been written to since it was last handed to the client
+    dirty: bool,
+}
+
+/// Whether the name is one that file managers and editors give the metadata and scratch files
+/// they leave beside the files they're working on, like `.DS_Store`, `._notes.pdf`, vim's `4913`
+/// and `.notes.txt.swp`, or `notes.txt~`. These would be submitted or uploaded to Blackboard for
+/// everyone to see, so they're refused.
+fn is_scratch_name(name: &str) -> bool {
+    name.starts_with('.')
+        || name.ends_with('~')
+        || name == "4913"
+        || [".swp", ".swx", ".tmp"]
+            .iter()
+            .any(|extension| name.ends_with(extension))
+}
+
+/// Writes `data` at `offset`, growing `contents` to fit it.
+fn write_at(contents: &mut Vec<u8>, offset: usize, data: &[u8]) {
+    let end = offset.saturating_add(data.len());
+    if contents.len() < end {
+        contents.resize(end, 0);
+    }
+    contents[offset..end].copy_from_slice(data);
+}
+
+/// Reads `size` bytes from `offset`, where reading at or past the end reads nothing.
+fn range(contents: &[u8], offset: usize, size: u32) -> &[u8] {
+    let start = offset.min(contents.len());
+    let end = start.saturating_add(size as usize).min(contents.len());
+    &contents[start..end]
+}
+
+/// What an open file or directory refers to.
+enum Handle {
+    /// The contents are fetched on the first read and kept until the file is closed, so that
+    /// every read through the handle sees the same version of the file
+    File {
+        ino: u64,
+        contents: Option<Vec<u8>>,
+    },
+    /// The entries are listed when the directory is opened, so that offsets into them stay
+    /// valid while it's read
+    Directory {
+        entries: Vec<(u64, FileType, String)>,
+    },
+    Upload {
+        ino: u64,
+    },
+    /// A file opened for writing to change it, such as a description. The changes are handed to
+    /// the client when it's closed.
+    Edit {
+        ino: u64,
+        contents: Vec<u8>,
+        dirty: bool,
+    },
 }
 
 pub struct Bbfs<Client: BbClient> {
     client: Client,
     next_free_inode: RefCell<u64>,
     inodes: HashMap<u64, ItemInode<Client::Item>>,
+    uploads: HashMap<u64, Upload>,
+    prefetcher: Option<Prefetcher<Client::Item>>,
+    next_free_handle: u64,
+    handles: HashMap<u64, Handle>,
+    link_style: LinkStyle,
+    /// Whether files with the same contents as one listed earlier are shown as symlinks to it
+    symlink_duplicates: bool,
+    /// The path of the first file found with each content key, which its duplicates link to
+    canonical: HashMap<String, PathBuf>,
 }
 
 impl<Client: BbClient> Bbfs<Client> {
@@ -75,21 +225,53 @@ impl<Client: BbClient> Bbfs<Client> {
                 ino: 1,
                 ty: FileType::Directory,
                 name: "root".into(),
-                item: client
-                    .get_root()
-                    .map_err(<Client::Error as Into<Errno>>::into)?,
+                item: client.get_root().map_err(errno)?,
                 children: None,
+                prefetched: false,
+                duplicate_of: None,
             },
         );
         Ok(Bbfs {
             client,
             next_free_inode: RefCell::new(2),
             inodes,
+            uploads: HashMap::new(),
+            prefetcher: None,
+            next_free_handle: 1,
+            handles: HashMap::new(),
+            link_style: LinkStyle::default(),
+            symlink_duplicates: false,
+            canonical: HashMap::new(),
         })
     }
 
+    pub fn with_link_style(mut self, link_style: LinkStyle) -> Self {
+        self.link_style = link_style;
+        self
+    }
+
+    /// Shows a file that's the same as one found earlier, such as one attached in several places,
+    /// as a symlink to where it was first found, so that it's only read and copied once.
+    pub fn with_symlinked_duplicates(mut self) -> Self {
+        self.symlink_duplicates = true;
+        self
+    }
+
+    /// Warms the listings of subdirectories and the contents of small files in the background
+    /// whenever a directory is listed.
+    pub fn with_prefetch(mut self, options: PrefetchOptions) -> Self
+    where
+        Client: Clone + Send + 'static,
+        Client::Item: 'static,
+    {
+        self.prefetcher = Some(Prefetcher::spawn(self.client.clone(), options));
+        self
+    }
+
     pub fn mount(self, mount_point: &PathBuf) -> anyhow::Result<()> {
-        fuser::mount2(self, mount_point, &[MountOption::RO]).map_err(|err| err.into())
+        // Not mounted read-only, since assignments' Submit directories and folders the user can
+        // edit take writes. Everything else refuses them with EROFS, going by its capabilities.
+        fuser::mount2(self, mount_point, &[]).map_err(|err| err.into())
     }
 
     fn get_free_inode(&self) -> u64 {
@@ -99,26 +281,639 @@ impl<Client: BbClient> Bbfs<Client> {
         free_inode
     }
 
+    fn open_handle(&mut self, handle: Handle) -> u64 {
+        let fh = self.next_free_handle;
+        self.next_free_handle += 1;
+        self.handles.insert(fh, handle);
+        fh
+    }
+
     fn attr(&self, inode: &ItemInode<Client::Item>) -> Result<FileAttr, Errno> {
+        if let Some(target) = &inode.duplicate_of {
+            return Ok(symlinkattr(inode.ino, target));
+        }
         Ok(match self.client.get_type(&inode.item) {
-            ItemType::File => fileattr(
-                inode.ino,
-                self.client
-                    .get_size(&inode.item)
-                    .map_err(<Client::Error as Into<Errno>>::into)? as u64,
+            ItemType::File => {
+                let metadata = self.client.get_metadata(&inode.item).map_err(errno)?;
+                let size = match metadata.size {
+                    Some(size) => size,
+                    None => self.client.get_size(&inode.item).map_err(errno)?,
+                };
+                fileattr(
+                    inode.ino,
+                    size as u64,
+                    metadata.modified.unwrap_or(UNIX_EPOCH),
+                    self.is_writable(inode),
+                )
+            }
+            ItemType::Directory => dirattr(inode.ino, self.nlink(inode), self.is_writable(inode)),
+            // Where a link leads is only found once it's opened, which can launch a tool, so its
+            // size isn't known until then
+            ItemType::Link => match self.link_style {
+                LinkStyle::File => fileattr(inode.ino, 0, UNIX_EPOCH, false),
+                LinkStyle::Symlink => symlinkattr(inode.ino, ""),
+            },
+        })
+    }
+
+    /// Whether something can be created in the directory, or the file can be written to.
+    fn is_writable(&self, inode: &ItemInode<Client::Item>) -> bool {
+        let can = self.client.get_capabilities(&inode.item);
+        match inode.ty {
+            FileType::Directory => can.create_file || can.create_folder,
+            FileType::RegularFile => can.set_description,
+            _ => false,
+        }
+    }
+
+    /// The path of the inode from the root of the filesystem.
+    fn path(&self, ino: u64) -> PathBuf {
+        let mut names = vec![];
+        let mut inode = &self.inodes[&ino];
+        while let Some(parent) = inode.parent {
+            names.push(inode.name.as_str());
+            inode = &self.inodes[&parent];
+        }
+        names.iter().rev().collect()
+    }
+
+    /// Where a file in the directory should link to if it's a duplicate of one found earlier,
+    /// recording it as the one to link to otherwise.
+    fn duplicate_target(&mut self, ino: u64, name: &str, item: &Client::Item) -> Option<String> {
+        if !self.symlink_duplicates {
+            return None;
+        }
+        let key = self.client.get_content_key(item)?;
+        let dir = self.path(ino);
+        let path = dir.join(name);
+        let canonical = self.canonical.entry(key).or_insert_with(|| path.clone());
+        if *canonical == path {
+            return None;
+        }
+        // Relative, so that it still points at the file wherever the filesystem is mounted
+        let shared = dir
+            .components()
+            .zip(canonical.components())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let up = dir.components().skip(shared).map(|_| Component::ParentDir);
+        let target: PathBuf = up.chain(canonical.components().skip(shared)).collect();
+        Some(target.to_string_lossy().into_owned())
+    }
+
+    fn find_child(&self, parent: u64, name: &str) -> Option<&ItemInode<Client::Item>> {
+        self.inodes
+            .values()
+            .find(|inode| inode.parent == Some(parent) && inode.name == name)
+    }
+
+    /// Whether the directory already has a child or an upload with the name.
+    fn name_taken(&self, parent: u64, name: &str) -> bool {
+        self.find_child(parent, name).is_some()
+            || self
+                .uploads
+                .values()
+                .any(|upload| upload.parent == parent && upload.name == name)
+    }
+
+    /// A directory is linked to by its entry in its parent, its own `.` and each subdirectory's
+    /// `..`. Until its children are listed the count isn't known, so 1 is reported instead,
+    /// which tools like `find` take to mean that it can't be relied upon.
+    fn nlink(&self, inode: &ItemInode<Client::Item>) -> u32 {
+        match &inode.children {
+            Some(children) => {
+                let subdirectories = children
+                    .iter()
+                    .filter(|ino| self.inodes[ino].ty == FileType::Directory)
+                    .count();
+                2 + subdirectories as u32
+            }
+            None => 1,
+        }
+    }
+
+    /// Sets the children of the directory. When it's listed again after a change, the children
+    /// that are still there under the same names keep their inode numbers, so that files that
+    /// are open stay valid.
+    fn add_children(&mut self, ino: u64, items: Vec<Client::Item>) -> Result<(), Errno> {
+        let mut previous: HashMap<String, u64> = self.inodes[&ino]
+            .children
+            .iter()
+            .flatten()
+            .map(|child| (self.inodes[child].name.clone(), *child))
+            .collect();
+        let mut children = vec![];
+        for item in items {
+            let name = self.client.get_name(&item).map_err(errno)?;
+            let (ty, name) = match (self.client.get_type(&item), self.link_style) {
+                (ItemType::File, _) => (FileType::RegularFile, name),
+                (ItemType::Directory, _) => (FileType::Directory, name),
+                (ItemType::Link, LinkStyle::File) => (FileType::RegularFile, link_file_name(&name)),
+                (ItemType::Link, LinkStyle::Symlink) => (FileType::Symlink, name),
+            };
+            let duplicate_of = match ty {
+                FileType::RegularFile => self.duplicate_target(ino, &name, &item),
+                _ => None,
+            };
+            let ty = match duplicate_of {
+                Some(_) => FileType::Symlink,
+                None => ty,
+            };
+            let child_ino = match previous.remove(&name) {
+                Some(child_ino) => {
+                    // The item may have changed, and what's in it along with it
+                    self.forget_children(child_ino);
+                    child_ino
+                }
+                None => self.get_free_inode(),
+            };
+            let child_inode = ItemInode {
+                parent: Some(ino),
+                ino: child_ino,
+                ty,
+                name,
+                item,
+                children: None,
+                prefetched: false,
+                duplicate_of,
+            };
+            children.push(child_ino);
+            self.inodes.insert(child_ino, child_inode);
+        }
+        for child_ino in previous.into_values() {
+            // Anything that's gone can't be linked to
+            let path = self.path(child_ino);
+            self.canonical
+                .retain(|_, canonical| !canonical.starts_with(&path));
+            self.forget_children(child_ino);
+            self.inodes.remove(&child_ino);
+        }
+        self.inodes.get_mut(&ino).unwrap().children = Some(children);
+        Ok(())
+    }
+
+    /// Lists the children of the directory from the client if they haven't been already.
+    fn load_children(&mut self, ino: u64) -> Result<(), Errno> {
+        let inode = self.inodes.get(&ino).ok_or(Errno::ENOENT)?;
+        if inode.ty != FileType::Directory {
+            return Err(Errno::ENOTDIR);
+        }
+        if inode.children.is_some() {
+            return Ok(());
+        }
+        let items = self.client.get_children(&inode.item).map_err(errno)?;
+        self.add_children(ino, items)
+    }
+
+    /// Forgets the listing of a directory, so that it's listed again from the client the next
+    /// time it's looked at.
+    fn forget_children(&mut self, ino: u64) {
+        let Some(inode) = self.inodes.get_mut(&ino) else {
+            return;
+        };
+        inode.prefetched = false;
+        for child in inode.children.take().into_iter().flatten() {
+            self.forget_children(child);
+            self.inodes.remove(&child);
+        }
+    }
+
+    /// Lists a directory again after something in it was changed, so that whatever that changed
+    /// shows up. If that fails, the listing is forgotten so that it's tried again later.
+    fn relist_children(&mut self, ino: u64) {
+        let Some(inode) = self.inodes.get(&ino) else {
+            return;
+        };
+        let relisted = (self.client.get_children(&inode.item).map_err(errno))
+            .and_then(|items| self.add_children(ino, items));
+        if relisted.is_err() {
+            self.forget_children(ino);
+        }
+    }
+
+    /// Hands an upload to the client if it's been written to since it last was.
+    fn submit_upload(&mut self, ino: u64) -> Result<(), Errno> {
+        let Some(upload) = self.uploads.get_mut(&ino) else {
+            return Ok(());
+        };
+        if !upload.dirty {
+            return Ok(());
+        }
+        // Not tried again when the file is next closed, since a submission can't be taken back
+        upload.dirty = false;
+        let parent = upload.parent;
+        let dir = &self.inodes.get(&parent).ok_or(Errno::ENOENT)?.item;
+        self.client
+            .create_file(dir, &upload.name, &upload.contents)
+            .map_err(errno)?;
+        self.relist_children(parent);
+        Ok(())
+    }
+
+    /// Hands the changes made through an edit handle to the client if there are any.
+    fn save_edit(&mut self, fh: u64) -> Result<(), Errno> {
+        let Some(Handle::Edit {
+            ino,
+            contents,
+            dirty,
+        }) = self.handles.get_mut(&fh)
+        else {
+            return Ok(());
+        };
+        if !*dirty {
+            return Ok(());
+        }
+        *dirty = false;
+        let inode = self.inodes.get(ino).ok_or(Errno::ENOENT)?;
+        let parent = inode.parent;
+        self.client
+            .set_description(&inode.item, &String::from_utf8_lossy(contents))
+            .map_err(errno)?;
+        if let Some(parent) = parent {
+            self.relist_children(parent);
+        }
+        Ok(())
+    }
+
+    /// Deletes a child of the directory, as long as it's a directory or not as expected. Folders
+    /// on Blackboard are deleted along with everything in them, so a directory is only deleted
+    /// once it's empty.
+    fn remove(&mut self, parent: u64, name: &str, directory: bool) -> Result<(), Errno> {
+        self.load_children(parent)?;
+        let child = self.find_child(parent, name).ok_or(Errno::ENOENT)?;
+        match (child.ty == FileType::Directory, directory) {
+            (true, false) => return Err(Errno::EISDIR),
+            (false, true) => return Err(Errno::ENOTDIR),
+            _ => {}
+        }
+        if !self.client.get_capabilities(&child.item).delete {
+            return Err(Errno::EROFS);
+        }
+        let ino = child.ino;
+        if directory {
+            self.load_children(ino)?;
+            let children = &self.inodes[&ino].children;
+            if !children.as_ref().is_none_or(Vec::is_empty) {
+                return Err(Errno::ENOTEMPTY);
+            }
+        }
+        self.client.delete(&self.inodes[&ino].item).map_err(errno)?;
+        self.relist_children(parent);
+        Ok(())
+    }
+
+    /// Adds the listings that the prefetcher has finished since the last operation.
+    fn collect_prefetched(&mut self) {
+        let Some(prefetcher) = &self.prefetcher else {
+            return;
+        };
+        for (ino, items) in prefetcher.finished_listings() {
+            if self
+                .inodes
+                .get(&ino)
+                .is_some_and(|inode| inode.children.is_none())
+            {
+                // It'll be listed again when it's opened
+                let _ = self.add_children(ino, items);
+            }
+        }
+    }
+
+    fn prefetch_children(&mut self, ino: u64) {
+        let Some(prefetcher) = &self.prefetcher else {
+            return;
+        };
+        let inode = &self.inodes[&ino];
+        if inode.prefetched {
+            return;
+        }
+        for child_ino in inode.children.iter().flatten() {
+            let child = &self.inodes[child_ino];
+            match child.ty {
+                FileType::Directory if child.children.is_none() => prefetcher.queue(Job::List {
+                    ino: child.ino,
+                    item: child.item.clone(),
+                }),
+                // Link files are made up on the spot, so there's nothing to fetch, and streamed
+                // files are too large to fetch without being asked to
+                FileType::RegularFile
+                    if self.client.get_type(&child.item) == ItemType::File
+                        && !self.client.is_streamed(&child.item) =>
+                {
+                    prefetcher.queue(Job::Read {
+                        item: child.item.clone(),
+                    })
+                }
+                _ => {}
+            }
+        }
+        self.inodes.get_mut(&ino).unwrap().prefetched = true;
+    }
+
+    /// The extended attributes describing where the item came from.
+    fn xattrs(&self, inode: &ItemInode<Client::Item>) -> Result<Vec<(&str, String)>, Errno> {
+        let metadata = self.client.get_metadata(&inode.item).map_err(errno)?;
+        let mime_type = match self.client.get_type(&inode.item) {
+            ItemType::Link if inode.ty == FileType::RegularFile => Some(LINK_FILE_MIME_TYPE.into()),
+            _ => metadata.mime_type,
+        };
+        Ok([
+            ("user.bbfs.url", metadata.url.clone()),
+            ("user.bbfs.content_id", metadata.content_id),
+            ("user.bbfs.course", metadata.course_id),
+            ("user.bbfs.description", metadata.description),
+            ("user.bbfs.mime_type", mime_type),
+            (
+                "user.bbfs.lti_launch",
+                metadata
+                    .lti_launch
+                    .as_ref()
+                    .map(|launch| launch.url.clone()),
             ),
-            ItemType::Directory => dirattr(inode.ino),
+            (
+                "user.bbfs.lti_parameters",
+                metadata
+                    .lti_launch
+                    .filter(|launch| !launch.parameters.is_empty())
+                    .map(|launch| {
+                        launch
+                            .parameters
+                            .iter()
+                            .map(|(name, value)| format!("{name}={value}\n"))
+                            .collect()
+                    }),
+            ),
+            // Understood by file managers and by curl/wget for downloaded files
+            ("user.xdg.origin.url", metadata.url),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect())
+    }
+
+    /// The attributes of a child of the directory, listing it first if it hasn't been.
+    fn lookup_child(&mut self, parent: u64, name: &str) -> Result<FileAttr, Errno> {
+        self.collect_prefetched();
+        self.load_children(parent)?;
+        if let Some((ino, upload)) = self
+            .uploads
+            .iter()
+            .find(|(_, upload)| upload.parent == parent && upload.name == name)
+        {
+            return Ok(uploadattr(*ino, upload.contents.len() as u64));
+        }
+        let inode = self.find_child(parent, name).ok_or(Errno::ENOENT)?;
+        self.attr(inode)
+    }
+
+    fn get_attr(&self, ino: u64) -> Result<FileAttr, Errno> {
+        if let Some(upload) = self.uploads.get(&ino) {
+            return Ok(uploadattr(ino, upload.contents.len() as u64));
+        }
+        self.attr(self.inodes.get(&ino).ok_or(Errno::ENOENT)?)
+    }
+
+    /// Opens a file with the flags given to `open`, returning its handle.
+    fn open_file(&mut self, ino: u64, flags: i32) -> Result<u64, Errno> {
+        if let Some(upload) = self.uploads.get_mut(&ino) {
+            if flags & libc::O_TRUNC != 0 {
+                upload.contents.clear();
+                upload.dirty = true;
+            }
+            return Ok(self.open_handle(Handle::Upload { ino }));
+        }
+        let inode = self.inodes.get(&ino).ok_or(Errno::ENOENT)?;
+        if inode.ty == FileType::Directory {
+            return Err(Errno::EISDIR);
+        }
+        let truncate = flags & libc::O_TRUNC != 0;
+        if flags & libc::O_ACCMODE != libc::O_RDONLY || truncate {
+            if !self.is_writable(inode) {
+                return Err(Errno::EROFS);
+            }
+            let contents = match truncate {
+                true => vec![],
+                false => file_contents(&self.client, &inode.item)?,
+            };
+            return Ok(self.open_handle(Handle::Edit {
+                ino,
+                contents,
+                dirty: truncate,
+            }));
+        }
+        Ok(self.open_handle(Handle::File {
+            ino,
+            contents: None,
+        }))
+    }
+
+    /// Reads up to `size` bytes from `offset` through a handle opened by `open_file`.
+    fn read_file(&mut self, ino: u64, fh: u64, offset: i64, size: u32) -> Result<Vec<u8>, Errno> {
+        let offset = usize::try_from(offset).map_err(|_| Errno::EINVAL)?;
+        let contents = match self.handles.get_mut(&fh) {
+            Some(Handle::File {
+                ino: handle_ino,
+                contents,
+            }) if *handle_ino == ino => contents,
+            Some(Handle::Upload { ino: handle_ino }) if *handle_ino == ino => {
+                let contents = &self.uploads[&ino].contents;
+                return Ok(range(contents, offset, size).to_vec());
+            }
+            Some(Handle::Edit {
+                ino: handle_ino,
+                contents,
+                ..
+            }) if *handle_ino == ino => return Ok(range(contents, offset, size).to_vec()),
+            Some(Handle::Directory { .. }) => return Err(Errno::EISDIR),
+            _ => {
+                warn!("attempted to read through an invalid file handle");
+                return Err(Errno::EBADF);
+            }
+        };
+
+        let item = &self.inodes[&ino].item;
+        // Streamed files are too large to keep in the handle, so each read fetches only its part
+        if self.client.is_streamed(item) {
+            return (self.client)
+                .get_range(item, offset as u64, size as usize)
+                .map_err(errno);
+        }
+
+        if contents.is_none() {
+            *contents = Some(file_contents(&self.client, item)?);
+        }
+        Ok(range(contents.as_deref().unwrap_or_default(), offset, size).to_vec())
+    }
+
+    /// Starts an upload of a new file into the directory, returning its inode and handle. It's
+    /// handed to the client once it's released.
+    fn create_upload(&mut self, parent: u64, name: &str) -> Result<(u64, u64), Errno> {
+        self.load_children(parent)?;
+        let dir = &self.inodes.get(&parent).ok_or(Errno::ENOENT)?.item;
+        if !self.client.get_capabilities(dir).create_file {
+            return Err(Errno::EROFS);
+        }
+        if is_scratch_name(name) {
+            return Err(Errno::EACCES);
+        }
+        if self.name_taken(parent, name) {
+            return Err(Errno::EEXIST);
+        }
+        let ino = self.get_free_inode();
+        self.uploads.insert(
+            ino,
+            Upload {
+                parent,
+                name: name.into(),
+                contents: vec![],
+                dirty: false,
+            },
+        );
+        Ok((ino, self.open_handle(Handle::Upload { ino })))
+    }
+
+    /// Writes `data` at `offset` through a handle opened for writing, returning how much was
+    /// written.
+    fn write_file(&mut self, ino: u64, fh: u64, offset: i64, data: &[u8]) -> Result<u32, Errno> {
+        let offset = usize::try_from(offset).map_err(|_| Errno::EINVAL)?;
+        let (contents, dirty) = match self.handles.get_mut(&fh) {
+            Some(Handle::Upload { ino: handle_ino }) if *handle_ino == ino => {
+                let upload = self.uploads.get_mut(&ino).ok_or(Errno::EBADF)?;
+                (&mut upload.contents, &mut upload.dirty)
+            }
+            Some(Handle::Edit {
+                ino: handle_ino,
+                contents,
+                dirty,
+            }) if *handle_ino == ino => (contents, dirty),
+            // Files are only writable through the handles made for writing them
+            _ => return Err(Errno::EBADF),
+        };
+        write_at(contents, offset, data);
+        *dirty = true;
+        Ok(data.len() as u32)
+    }
+
+    /// Hands what's been written through a handle to the client as the file is closed, so that
+    /// close returns any error. An upload is only handed over when it's closed through its last
+    /// handle, so that it's only submitted once however many times it was opened.
+    fn flush_file(&mut self, fh: u64) -> Result<(), Errno> {
+        self.save_edit(fh)?;
+        match self.handles.get(&fh) {
+            Some(&Handle::Upload { ino }) if !self.open_elsewhere(fh, ino) => {
+                self.submit_upload(ino)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether an upload is open through any handle other than `fh`.
+    fn open_elsewhere(&self, fh: u64, ino: u64) -> bool {
+        self.handles.iter().any(|(&other, handle)| {
+            other != fh && matches!(handle, Handle::Upload { ino: open } if *open == ino)
         })
     }
 
-    fn path(&self, inode: &ItemInode<Client::Item>) -> Vec<&Client::Item> {
-        match inode.parent.and_then(|ino| self.inodes.get(&ino)) {
-            Some(parent) => {
-                let mut path = self.path(parent);
-                path.push(&self.inodes[&inode.ino].item);
-                path
+    /// Closes a file's handle. Everything written through it was handed to the client when it
+    /// was flushed, so this only catches writes made after that.
+    fn release_file(&mut self, fh: u64) -> Result<(), Errno> {
+        let flushed = self.flush_file(fh);
+        if let Some(Handle::Upload { ino }) = self.handles.remove(&fh) {
+            if !self.open_elsewhere(fh, ino) {
+                self.uploads.remove(&ino);
+            }
+        }
+        flushed
+    }
+
+    /// Creates a folder in the directory, returning its attributes once it's been listed.
+    fn create_folder(&mut self, parent: u64, name: &str) -> Result<FileAttr, Errno> {
+        self.load_children(parent)?;
+        let dir = &self.inodes.get(&parent).ok_or(Errno::ENOENT)?.item;
+        if !self.client.get_capabilities(dir).create_folder {
+            return Err(Errno::EROFS);
+        }
+        if is_scratch_name(name) {
+            return Err(Errno::EACCES);
+        }
+        if self.name_taken(parent, name) {
+            return Err(Errno::EEXIST);
+        }
+        self.client.create_folder(dir, name).map_err(errno)?;
+        self.relist_children(parent);
+        match self.find_child(parent, name) {
+            Some(inode) => self.attr(inode),
+            None => {
+                warn!("created {name}, but it wasn't listed afterwards");
+                Err(Errno::EIO)
+            }
+        }
+    }
+
+    /// Renames a child of the directory in place.
+    fn rename_child(
+        &mut self,
+        parent: u64,
+        name: &str,
+        newparent: u64,
+        newname: &str,
+    ) -> Result<(), Errno> {
+        // Moving between folders isn't supported, so tools like mv copy and delete instead
+        if newparent != parent {
+            return Err(Errno::EXDEV);
+        }
+        self.load_children(parent)?;
+        let child = self.find_child(parent, name).ok_or(Errno::ENOENT)?;
+        if !self.client.get_capabilities(&child.item).rename {
+            return Err(Errno::EROFS);
+        }
+        if is_scratch_name(newname) {
+            return Err(Errno::EACCES);
+        }
+        if self.name_taken(parent, newname) {
+            return Err(Errno::EEXIST);
+        }
+        self.client.rename(&child.item, newname).map_err(errno)?;
+        self.relist_children(parent);
+        Ok(())
+    }
+
+    /// Lists the directory for reading with `directory_entries`, returning its handle.
+    fn open_directory(&mut self, ino: u64) -> Result<u64, Errno> {
+        self.collect_prefetched();
+        self.load_children(ino)?;
+        self.prefetch_children(ino);
+
+        let inode = &self.inodes[&ino];
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".into()),
+            (inode.parent.unwrap_or(1), FileType::Directory, "..".into()),
+        ];
+        entries.extend(
+            self.cached_children(inode)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|child| (child.ino, child.ty, child.name)),
+        );
+        entries.extend(
+            self.uploads
+                .iter()
+                .filter(|(_, upload)| upload.parent == ino)
+                .map(|(ino, upload)| (*ino, FileType::RegularFile, upload.name.clone())),
+        );
+        Ok(self.open_handle(Handle::Directory { entries }))
+    }
+
+    fn directory_entries(&self, fh: u64) -> Result<&[(u64, FileType, String)], Errno> {
+        match self.handles.get(&fh) {
+            Some(Handle::Directory { entries }) => Ok(entries),
+            Some(Handle::File { .. } | Handle::Upload { .. } | Handle::Edit { .. }) => {
+                Err(Errno::ENOTDIR)
+            }
+            None => {
+                warn!("attempted to read a directory through an invalid handle");
+                Err(Errno::EBADF)
             }
-            None => vec![&self.inodes[&inode.ino].item],
         }
     }
 
@@ -137,36 +932,115 @@ impl<Client: BbClient> Bbfs<Client> {
 
 impl<Client: BbClient> Filesystem for Bbfs<Client> {
     fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
-        let name = name.to_str().unwrap();
-        println!("lookup(name={name})");
+        // Every name that's listed is valid UTF-8, so anything else can't be found
+        let Some(name) = name.to_str() else {
+            return reply.error(ENOENT);
+        };
+        let _span = debug_span!("lookup", parent, name).entered();
+        match self.lookup_child(parent, name) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(err) => reply.error(err as _),
+        }
+    }
 
-        match self
-            .inodes
-            .values()
-            .find(|inode| inode.parent == Some(parent) && inode.name == name)
-        {
-            Some(inode) => {
-                let attr = match self.attr(inode) {
-                    Ok(attr) => attr,
-                    Err(err) => return reply.error(Errno::from(err) as _),
-                };
-                reply.entry(&TTL, &attr, 0)
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        let _span = debug_span!("getattr", ino).entered();
+        match self.get_attr(ino) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(err) => reply.error(err as _),
+        }
+    }
+
+    fn getxattr(&mut self, _req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        let _span = debug_span!("getxattr", ino, ?name).entered();
+
+        let Some(inode) = self.inodes.get(&ino) else {
+            return reply.error(ENOENT);
+        };
+        match self.xattrs(inode) {
+            Ok(xattrs) => match xattrs.iter().find(|(xattr, _)| name == *xattr) {
+                Some((_, value)) => reply_xattr(reply, size, value.as_bytes()),
+                None => reply.error(NO_XATTR),
+            },
+            Err(err) => reply.error(err as _),
+        }
+    }
+
+    fn listxattr(&mut self, _req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+        let _span = debug_span!("listxattr", ino).entered();
+
+        let Some(inode) = self.inodes.get(&ino) else {
+            return reply.error(ENOENT);
+        };
+        match self.xattrs(inode) {
+            Ok(xattrs) => {
+                // The names are each terminated by a null byte
+                let names: Vec<u8> = xattrs
+                    .iter()
+                    .flat_map(|(name, _)| name.bytes().chain([0]))
+                    .collect();
+                reply_xattr(reply, size, &names)
             }
-            None => reply.error(ENOENT),
+            Err(err) => reply.error(err as _),
         }
     }
 
-    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
-        println!("getattr(ino={ino})");
+    fn access(&mut self, _req: &Request, ino: u64, mask: i32, reply: ReplyEmpty) {
+        let _span = debug_span!("access", ino, mask).entered();
 
-        let inode = match self.inodes.get(&ino) {
-            Some(inode) => inode,
-            None => return reply.error(ENOENT),
+        if self.uploads.contains_key(&ino) {
+            return match mask & libc::X_OK {
+                0 => reply.ok(),
+                _ => reply.error(EACCES),
+            };
+        }
+        let Some(inode) = self.inodes.get(&ino) else {
+            return reply.error(ENOENT);
         };
+        if mask & libc::W_OK != 0 && !self.is_writable(inode) {
+            reply.error(EROFS)
+        } else if mask & libc::X_OK != 0 && inode.ty != FileType::Directory {
+            reply.error(EACCES)
+        } else {
+            reply.ok()
+        }
+    }
 
-        match self.attr(inode) {
-            Ok(attr) => reply.attr(&TTL, &attr),
-            Err(err) => reply.error(Errno::from(err) as _),
+    fn statfs(&mut self, _req: &Request, _ino: u64, reply: ReplyStatfs) {
+        let _span = debug_span!("statfs").entered();
+
+        // The filesystem is as large as what's been downloaded. What's written is handed straight
+        // to Blackboard rather than kept, but copying refuses to start without space free, so
+        // there's no end to the space left as long as anything can be written to.
+        let blocks = self.client.get_cache_size().div_ceil(BLOCK_SIZE as u64);
+        let writable = self.inodes.values().any(|inode| self.is_writable(inode));
+        let free = if writable {
+            u64::MAX / BLOCK_SIZE as u64
+        } else {
+            0
+        };
+        reply.statfs(
+            blocks + free,
+            free,
+            free,
+            self.inodes.len() as u64,
+            free,
+            BLOCK_SIZE,
+            MAX_NAME_LENGTH,
+            BLOCK_SIZE,
+        )
+    }
+
+    fn open(&mut self, _req: &Request, ino: u64, flags: i32, reply: ReplyOpen) {
+        let _span = debug_span!("open", ino, flags).entered();
+        // A link file is listed as empty, since where it leads isn't known until now, so it's
+        // read past the size it was listed with
+        let is_link = (self.inodes.get(&ino))
+            .is_some_and(|inode| self.client.get_type(&inode.item) == ItemType::Link);
+        let open_flags = if is_link { consts::FOPEN_DIRECT_IO } else { 0 };
+        match self.open_file(ino, flags) {
+            Ok(fh) => reply.opened(fh, open_flags),
+            Err(err) => reply.error(err as _),
         }
     }
 
@@ -174,48 +1048,58 @@ impl<Client: BbClient> Filesystem for Bbfs<Client> {
         &mut self,
         _req: &Request,
         ino: u64,
-        _fh: u64,
+        fh: u64,
         offset: i64,
         size: u32,
         _flags: i32,
         _lock: Option<u64>,
         reply: ReplyData,
     ) {
-        println!("read(ino={ino}, offset={offset}, size={size})");
+        let _span = debug_span!("read", ino, fh, offset, size).entered();
+        match self.read_file(ino, fh, offset, size) {
+            Ok(data) => reply.data(&data),
+            Err(err) => reply.error(err as _),
+        }
+    }
 
-        let inode = match self.inodes.get(&ino) {
-            Some(inode) => inode,
-            None => {
-                eprintln!("attempted to read from non-existent inode (ino={ino})");
-                return reply.error(ENOENT);
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        let _span = debug_span!("readlink", ino).entered();
+
+        match self.inodes.get(&ino) {
+            Some(ItemInode {
+                duplicate_of: Some(target),
+                ..
+            }) => reply.data(target.as_bytes()),
+            Some(inode) if inode.ty == FileType::Symlink => {
+                reply.data(link_target(&self.client, &inode.item).as_bytes())
             }
-        };
+            Some(_) => reply.error(EINVAL),
+            None => reply.error(ENOENT),
+        }
+    }
 
-        if self.client.get_type(&inode.item) != ItemType::File {
-            eprintln!("attempted to read a directory");
-            return reply.error(EIO);
-        }
-
-        let offset = offset as usize;
-        let end_offset = offset + (size as usize);
-        match self
-            .client
-            .get_contents(&inode.item)
-            .map_err(Into::<Errno>::into)
-            .and_then(|contents| {
-                contents
-                    .get(offset..end_offset.min(contents.len()))
-                    .ok_or(Errno::EIO)
-                    .map(|contents| contents.to_vec())
-                    .map_err(|err| {
-                        eprintln!(
-                            "invalid offset ({offset}) for read of file of size {}",
-                            contents.len()
-                        );
-                        err
-                    })
-            }) {
-            Ok(contents) => reply.data(&contents),
+    fn release(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        let _span = debug_span!("release", ino, fh).entered();
+        match self.release_file(fh) {
+            Ok(()) => reply.ok(),
+            // Only seen in the log, since close has already returned
+            Err(err) => reply.error(err as _),
+        }
+    }
+
+    fn opendir(&mut self, _req: &Request, ino: u64, _flags: i32, reply: ReplyOpen) {
+        let _span = debug_span!("opendir", ino).entered();
+        match self.open_directory(ino) {
+            Ok(fh) => reply.opened(fh, 0),
             Err(err) => reply.error(err as _),
         }
     }
@@ -224,74 +1108,264 @@ impl<Client: BbClient> Filesystem for Bbfs<Client> {
         &mut self,
         _req: &Request,
         ino: u64,
-        _fh: u64,
+        fh: u64,
         offset: i64,
         mut reply: ReplyDirectory,
     ) {
-        println!("readdir(ino={ino}, offset={offset})");
-
-        let mut entries = vec![];
-        entries.push((ino, FileType::Directory, "."));
+        let _span = debug_span!("readdir", ino, fh, offset).entered();
 
-        let children = if let Some(inode) = self.inodes.get(&ino) {
-            if self.client.get_type(&inode.item) != ItemType::Directory {
-                eprintln!("attempted to read directory contents of a file (ino={ino})");
-                return reply.error(EIO);
+        let entries = match self.directory_entries(fh) {
+            Ok(entries) => entries,
+            Err(err) => return reply.error(err as _),
+        };
+        for (i, (ino, ty, name)) in entries.iter().enumerate().skip(offset as usize) {
+            if reply.add(*ino, (i + 1) as i64, *ty, name) {
+                break;
             }
+        }
+        reply.ok();
+    }
 
-            entries.push((inode.parent.unwrap_or(1), FileType::Directory, ".."));
-            self.cached_children(inode)
-        } else {
-            eprintln!("attempted to read directory contents of non-existent inode (ino={ino})");
-            return reply.error(ENOENT);
+    fn releasedir(&mut self, _req: &Request, ino: u64, fh: u64, _flags: i32, reply: ReplyEmpty) {
+        let _span = debug_span!("releasedir", ino, fh).entered();
+        self.handles.remove(&fh);
+        reply.ok()
+    }
+
+    fn create(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let Some(name) = name.to_str() else {
+            return reply.error(EINVAL);
         };
+        let _span = debug_span!("create", parent, name).entered();
+        match self.create_upload(parent, name) {
+            Ok((ino, fh)) => reply.created(&TTL, &uploadattr(ino, 0), 0, fh, 0),
+            Err(err) => reply.error(err as _),
+        }
+    }
 
-        let children = if let Some(children) = children {
-            children
-        } else {
-            let items = match self
-                .client
-                .get_children(self.path(&self.inodes[&ino]))
-                .map_err(Into::<Errno>::into)
-            {
-                Ok(items) => items,
-                Err(err) => return reply.error(err as _),
-            };
+    fn write(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        let _span = debug_span!("write", ino, fh, offset, size = data.len()).entered();
 
-            let mut inodes = vec![];
-            for item in items {
-                let child_ino = self.get_free_inode();
-                let child_inode = ItemInode {
-                    parent: Some(ino),
-                    ino: child_ino,
-                    ty: match self.client.get_type(&item) {
-                        ItemType::File => FileType::RegularFile,
-                        ItemType::Directory => FileType::Directory,
-                    },
-                    name: match self.client.get_name(&item) {
-                        Ok(name) => name,
-                        Err(err) => return reply.error(err.into() as _),
-                    },
-                    item,
-                    children: None,
-                };
-                inodes.push(child_inode.clone());
-                self.inodes.insert(child_ino, child_inode);
+        match self.write_file(ino, fh, offset, data) {
+            Ok(written) => reply.written(written),
+            Err(err) => reply.error(err as _),
+        }
+    }
+
+    fn flush(&mut self, _req: &Request, ino: u64, fh: u64, _lock_owner: u64, reply: ReplyEmpty) {
+        let _span = debug_span!("flush", ino, fh).entered();
+
+        // Closing a file is what hands what's written to the client, so that close returns the
+        // errors, such as a submission being refused because the assignment has closed
+        match self.flush_file(fh) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err as _),
+        }
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<TimeOrNow>,
+        _mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        let _span = debug_span!("setattr", ino, size).entered();
+
+        // Only the size of a writable file can be changed. Anything else is ignored rather than
+        // refused, so that copying a file doesn't fail when it tries to keep its mode and times.
+        let Ok(size) = size.map(usize::try_from).transpose() else {
+            return reply.error(EINVAL);
+        };
+        if let Some(upload) = self.uploads.get_mut(&ino) {
+            if let Some(size) = size {
+                upload.contents.resize(size, 0);
+                upload.dirty = true;
             }
-            self.inodes.get_mut(&ino).unwrap().children =
-                Some(inodes.iter().map(|inode| inode.ino).collect());
-            self.cached_children(&self.inodes[&ino]).unwrap()
+            return reply.attr(&TTL, &uploadattr(ino, upload.contents.len() as u64));
+        }
+        let Some(inode) = self.inodes.get(&ino) else {
+            return reply.error(ENOENT);
         };
+        if !self.is_writable(inode) {
+            return reply.error(EROFS);
+        }
+        if let Some(size) = size {
+            match fh.and_then(|fh| self.handles.get_mut(&fh)) {
+                Some(Handle::Edit {
+                    contents, dirty, ..
+                }) => {
+                    contents.resize(size, 0);
+                    *dirty = true;
+                }
+                // Truncated before it's opened, so the change is made straight away
+                _ => {
+                    let truncated =
+                        file_contents(&self.client, &inode.item).and_then(|mut contents| {
+                            contents.resize(size, 0);
+                            (self.client)
+                                .set_description(&inode.item, &String::from_utf8_lossy(&contents))
+                                .map_err(errno)
+                        });
+                    if let Err(err) = truncated {
+                        return reply.error(err as _);
+                    }
+                    if let Some(parent) = inode.parent {
+                        self.relist_children(parent);
+                    }
+                }
+            }
+        }
+        match self.inodes.get(&ino).map(|inode| self.attr(inode)) {
+            Some(Ok(attr)) => reply.attr(&TTL, &attr),
+            Some(Err(err)) => reply.error(err as _),
+            None => reply.error(ENOENT),
+        }
+    }
 
-        children
-            .iter()
-            .for_each(|child| entries.push((child.ino, child.ty, &child.name)));
+    fn mkdir(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let Some(name) = name.to_str() else {
+            return reply.error(EINVAL);
+        };
+        let _span = debug_span!("mkdir", parent, name).entered();
+        match self.create_folder(parent, name) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(err) => reply.error(err as _),
+        }
+    }
 
-        for (i, entry) in entries.into_iter().enumerate().skip(offset as usize) {
-            if reply.add(entry.0, (i + 1) as i64, entry.1, entry.2) {
-                break;
-            }
+    fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let Some(name) = name.to_str() else {
+            return reply.error(EINVAL);
+        };
+        let _span = debug_span!("unlink", parent, name).entered();
+        match self.remove(parent, name, false) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err as _),
         }
-        reply.ok();
+    }
+
+    fn rmdir(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        let Some(name) = name.to_str() else {
+            return reply.error(EINVAL);
+        };
+        let _span = debug_span!("rmdir", parent, name).entered();
+        match self.remove(parent, name, true) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err as _),
+        }
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        _flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        let (Some(name), Some(newname)) = (name.to_str(), newname.to_str()) else {
+            return reply.error(EINVAL);
+        };
+        let _span = debug_span!("rename", parent, name, newparent, newname).entered();
+        match self.rename_child(parent, name, newparent, newname) {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err as _),
+        }
+    }
+
+    // Everything below has no counterpart on Blackboard, so it can't be done
+
+    fn mknod(
+        &mut self,
+        _req: &Request,
+        _parent: u64,
+        _name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _rdev: u32,
+        reply: ReplyEntry,
+    ) {
+        reply.error(EROFS)
+    }
+
+    fn symlink(
+        &mut self,
+        _req: &Request,
+        _parent: u64,
+        _link_name: &OsStr,
+        _target: &Path,
+        reply: ReplyEntry,
+    ) {
+        reply.error(EROFS)
+    }
+
+    fn link(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        _newparent: u64,
+        _newname: &OsStr,
+        reply: ReplyEntry,
+    ) {
+        reply.error(EROFS)
+    }
+
+    fn setxattr(
+        &mut self,
+        _req: &Request,
+        _ino: u64,
+        _name: &OsStr,
+        _value: &[u8],
+        _flags: i32,
+        _position: u32,
+        reply: ReplyEmpty,
+    ) {
+        reply.error(EROFS)
+    }
+
+    fn removexattr(&mut self, _req: &Request, _ino: u64, _name: &OsStr, reply: ReplyEmpty) {
+        reply.error(EROFS)
     }
 }
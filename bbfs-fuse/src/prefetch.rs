@@ -0,0 +1,104 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tracing::{debug, debug_span};
+
+use bbfs_api::BbClient;
+
+/// How the prefetcher warms the contents of directories as they're listed.
+#[derive(Clone, Debug)]
+pub struct PrefetchOptions {
+    /// How many prefetches may run at once
+    pub workers: usize,
+    /// Files larger than this many bytes are left to be downloaded when they're read
+    pub max_file_size: usize,
+}
+
+impl Default for PrefetchOptions {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            max_file_size: 1024 * 1024,
+        }
+    }
+}
+
+pub(crate) enum Job<Item> {
+    /// List the directory, which is sent back for the inode
+    List { ino: u64, item: Item },
+    /// Download the file if it's small enough, so that the client has it cached
+    Read { item: Item },
+}
+
+/// Runs prefetch jobs on a pool of background threads, sending finished listings back to the
+/// filesystem so that it can pick them up before its next operation.
+pub(crate) struct Prefetcher<Item> {
+    jobs: Sender<Job<Item>>,
+    listings: Receiver<(u64, Vec<Item>)>,
+}
+
+impl<Item: Send + 'static> Prefetcher<Item> {
+    pub(crate) fn spawn<Client>(client: Client, options: PrefetchOptions) -> Self
+    where
+        Client: BbClient<Item = Item> + Clone + Send + 'static,
+    {
+        let (jobs, job_receiver) = mpsc::channel::<Job<Item>>();
+        let (listing_sender, listings) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        for _ in 0..options.workers.max(1) {
+            let client = client.clone();
+            let job_receiver = job_receiver.clone();
+            let listing_sender = listing_sender.clone();
+            let max_file_size = options.max_file_size;
+            thread::spawn(move || loop {
+                let job = match job_receiver.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    // The filesystem has been unmounted
+                    Err(_) => break,
+                };
+                match job {
+                    Job::List { ino, item } => {
+                        let _span = debug_span!("prefetch_list", ino).entered();
+                        match client.get_children(&item) {
+                            Ok(items) => {
+                                if listing_sender.send((ino, items)).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(err) => debug!("failed to prefetch listing: {err}"),
+                        }
+                    }
+                    Job::Read { item } => {
+                        let _span = debug_span!("prefetch_read").entered();
+                        let result = client.get_size(&item).and_then(|size| {
+                            if size <= max_file_size {
+                                client.get_contents(&item).map(|_| ())
+                            } else {
+                                Ok(())
+                            }
+                        });
+                        if let Err(err) = result {
+                            debug!("failed to prefetch file: {err}");
+                        }
+                    }
+                }
+            });
+        }
+
+        Self { jobs, listings }
+    }
+}
+
+impl<Item> Prefetcher<Item> {
+    pub(crate) fn queue(&self, job: Job<Item>) {
+        // The workers only stop once this is dropped, so this can't fail
+        let _ = self.jobs.send(job);
+    }
+
+    /// Listings that have finished since this was last called.
+    pub(crate) fn finished_listings(&self) -> Vec<(u64, Vec<Item>)> {
+        self.listings.try_iter().collect()
+    }
+}
@@ -0,0 +1,611 @@
+//! Runs the filesystem against a fake client, going through the same methods that the FUSE
+//! operations reply with.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+
+use bbfs_api::{Capabilities, Unsupported};
+
+use super::*;
+
+#[derive(Debug)]
+struct FakeError(Errno);
+
+impl Display for FakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fake client failed: {}", self.0)
+    }
+}
+
+impl Error for FakeError {}
+
+impl From<FakeError> for Errno {
+    fn from(err: FakeError) -> Self {
+        err.0
+    }
+}
+
+impl From<Unsupported> for FakeError {
+    fn from(_: Unsupported) -> Self {
+        FakeError(Errno::ENOTSUP)
+    }
+}
+
+#[derive(Clone)]
+enum Node {
+    Directory,
+    File(Vec<u8>),
+    Link(String),
+    /// A link to a page that only leads to the tool's URL once it's been launched
+    Launch {
+        page: String,
+        tool: String,
+    },
+    /// A file that can be listed but fails to be downloaded
+    Broken,
+}
+
+/// A client whose items are the paths of the nodes it's given, like `/Week 1/slides.pdf`.
+#[derive(Default)]
+struct FakeClient {
+    nodes: Mutex<BTreeMap<String, Node>>,
+    capabilities: BTreeMap<String, Capabilities>,
+    /// The paths of the files created through the client, in the order they were created
+    created: Mutex<Vec<String>>,
+}
+
+impl FakeClient {
+    fn with(mut self, path: &str, node: Node) -> Self {
+        self.nodes.get_mut().unwrap().insert(path.into(), node);
+        self
+    }
+
+    fn with_capabilities(mut self, path: &str, capabilities: Capabilities) -> Self {
+        self.capabilities.insert(path.into(), capabilities);
+        self
+    }
+
+    fn node(&self, path: &str) -> Result<Node, FakeError> {
+        match self.nodes.lock().unwrap().get(path) {
+            Some(node) => Ok(node.clone()),
+            None => Err(FakeError(Errno::ENOENT)),
+        }
+    }
+}
+
+fn child(dir: &str, name: &str) -> String {
+    format!("{}/{name}", dir.trim_end_matches('/'))
+}
+
+fn parent(path: &str) -> &str {
+    match path.rsplit_once('/') {
+        Some(("", _)) => "/",
+        Some((parent, _)) => parent,
+        None => "",
+    }
+}
+
+impl BbClient for FakeClient {
+    type Item = String;
+    type Error = FakeError;
+
+    fn get_root(&self) -> Result<String, FakeError> {
+        Ok("/".into())
+    }
+
+    fn get_children(&self, item: &String) -> Result<Vec<String>, FakeError> {
+        match self.node(item)? {
+            Node::Directory => Ok(self
+                .nodes
+                .lock()
+                .unwrap()
+                .keys()
+                .filter(|path| *path != "/" && parent(path) == item)
+                .cloned()
+                .collect()),
+            _ => Err(FakeError(Errno::ENOTDIR)),
+        }
+    }
+
+    fn get_size(&self, item: &String) -> Result<usize, FakeError> {
+        match self.node(item)? {
+            // Like a file whose size is known without downloading it
+            Node::Broken => Ok(10),
+            _ => self.get_contents(item).map(|contents| contents.len()),
+        }
+    }
+
+    fn get_contents(&self, item: &String) -> Result<Vec<u8>, FakeError> {
+        match self.node(item)? {
+            Node::File(contents) => Ok(contents),
+            Node::Broken => Err(FakeError(Errno::EIO)),
+            Node::Directory | Node::Link(_) | Node::Launch { .. } => Err(FakeError(Errno::EISDIR)),
+        }
+    }
+
+    fn get_type(&self, item: &String) -> ItemType {
+        match self.node(item) {
+            Ok(Node::Directory) => ItemType::Directory,
+            Ok(Node::Link(_) | Node::Launch { .. }) => ItemType::Link,
+            _ => ItemType::File,
+        }
+    }
+
+    fn get_name(&self, item: &String) -> Result<String, FakeError> {
+        Ok(item.rsplit('/').next().unwrap_or_default().into())
+    }
+
+    fn get_id(&self, item: &String) -> String {
+        item.clone()
+    }
+
+    fn get_url(&self, item: &String) -> Option<String> {
+        match self.node(item) {
+            Ok(Node::Link(url) | Node::Launch { page: url, .. }) => Some(url),
+            _ => None,
+        }
+    }
+
+    fn get_link_target(&self, item: &String) -> Option<String> {
+        match self.node(item) {
+            Ok(Node::Launch { tool, .. }) => Some(tool),
+            _ => self.get_url(item),
+        }
+    }
+
+    fn get_capabilities(&self, item: &String) -> Capabilities {
+        self.capabilities.get(item).copied().unwrap_or_default()
+    }
+
+    fn create_file(&self, dir: &String, name: &str, contents: &[u8]) -> Result<(), FakeError> {
+        let path = child(dir, name);
+        let mut nodes = self.nodes.lock().unwrap();
+        if nodes.contains_key(&path) {
+            return Err(FakeError(Errno::EEXIST));
+        }
+        nodes.insert(path.clone(), Node::File(contents.to_vec()));
+        self.created.lock().unwrap().push(path);
+        Ok(())
+    }
+
+    fn create_folder(&self, dir: &String, name: &str) -> Result<(), FakeError> {
+        let mut nodes = self.nodes.lock().unwrap();
+        nodes.insert(child(dir, name), Node::Directory);
+        Ok(())
+    }
+
+    /// Moves the item along with everything in it, the same as renaming a folder on Blackboard.
+    fn rename(&self, item: &String, name: &str) -> Result<(), FakeError> {
+        let renamed = child(parent(item), name);
+        let mut nodes = self.nodes.lock().unwrap();
+        let moved: Vec<String> = (nodes.keys())
+            .filter(|path| *path == item || path.starts_with(&format!("{item}/")))
+            .cloned()
+            .collect();
+        for path in moved {
+            let node = nodes.remove(&path).unwrap();
+            nodes.insert(path.replacen(item, &renamed, 1), node);
+        }
+        Ok(())
+    }
+
+    fn delete(&self, item: &String) -> Result<(), FakeError> {
+        let mut nodes = self.nodes.lock().unwrap();
+        nodes.retain(|path, _| path != item && !path.starts_with(&format!("{item}/")));
+        Ok(())
+    }
+
+    /// Files stand in for descriptions, so a file's description is its contents.
+    fn set_description(&self, item: &String, description: &str) -> Result<(), FakeError> {
+        let mut nodes = self.nodes.lock().unwrap();
+        nodes.insert(item.clone(), Node::File(description.into()));
+        Ok(())
+    }
+}
+
+fn course() -> FakeClient {
+    FakeClient::default()
+        .with("/", Node::Directory)
+        .with("/Week 1", Node::Directory)
+        .with("/Week 1/slides.pdf", Node::File(b"0123456789".to_vec()))
+        .with("/Week 1/broken.pdf", Node::Broken)
+        .with("/Ed", Node::Link("https://edstem.org".into()))
+        .with("/outline.txt", Node::File(b"outline".to_vec()))
+}
+
+fn mount(client: FakeClient) -> Bbfs<FakeClient> {
+    Bbfs::new(client).unwrap()
+}
+
+fn lookup_ino(fs: &mut Bbfs<FakeClient>, path: &str) -> u64 {
+    path.split('/')
+        .filter(|name| !name.is_empty())
+        .fold(1, |parent, name| fs.lookup_child(parent, name).unwrap().ino)
+}
+
+fn list(fs: &mut Bbfs<FakeClient>, ino: u64) -> Vec<(String, FileType)> {
+    let fh = fs.open_directory(ino).unwrap();
+    fs.directory_entries(fh)
+        .unwrap()
+        .iter()
+        .map(|(_, ty, name)| (name.clone(), *ty))
+        .collect()
+}
+
+fn read(fs: &mut Bbfs<FakeClient>, path: &str, offset: i64, size: u32) -> Result<Vec<u8>, Errno> {
+    let ino = lookup_ino(fs, path);
+    let fh = fs.open_file(ino, libc::O_RDONLY)?;
+    fs.read_file(ino, fh, offset, size)
+}
+
+#[test]
+fn lookup_finds_only_listed_children() {
+    let mut fs = mount(course());
+    let week = fs.lookup_child(1, "Week 1").unwrap();
+    assert_eq!(week.kind, FileType::Directory);
+    let slides = fs.lookup_child(week.ino, "slides.pdf").unwrap();
+    assert_eq!(slides.kind, FileType::RegularFile);
+
+    assert_eq!(fs.lookup_child(1, "Week 2").unwrap_err(), Errno::ENOENT);
+    assert_eq!(fs.lookup_child(1, "slides.pdf").unwrap_err(), Errno::ENOENT);
+    assert_eq!(
+        fs.lookup_child(slides.ino, "anything").unwrap_err(),
+        Errno::ENOTDIR
+    );
+    assert_eq!(
+        fs.lookup_child(1000, "anything").unwrap_err(),
+        Errno::ENOENT
+    );
+}
+
+#[test]
+fn lookup_keeps_inode_numbers() {
+    let mut fs = mount(course());
+    let first = lookup_ino(&mut fs, "/Week 1/slides.pdf");
+    assert_eq!(lookup_ino(&mut fs, "/Week 1/slides.pdf"), first);
+    // Listing a directory again keeps the numbers of what's still in it
+    let week = lookup_ino(&mut fs, "/Week 1");
+    fs.relist_children(week);
+    assert_eq!(lookup_ino(&mut fs, "/Week 1/slides.pdf"), first);
+}
+
+#[test]
+fn readdir_lists_dot_entries_and_children() {
+    let mut fs = mount(course());
+    let ed = link_file_name("Ed");
+    assert_eq!(
+        list(&mut fs, 1),
+        [
+            (".".into(), FileType::Directory),
+            ("..".into(), FileType::Directory),
+            (ed, FileType::RegularFile),
+            ("Week 1".into(), FileType::Directory),
+            ("outline.txt".into(), FileType::RegularFile),
+        ]
+    );
+
+    let week = lookup_ino(&mut fs, "/Week 1");
+    let names: Vec<_> = list(&mut fs, week)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(names, [".", "..", "broken.pdf", "slides.pdf"]);
+}
+
+#[test]
+fn readdir_refuses_files_and_unknown_handles() {
+    let mut fs = mount(course());
+    let outline = lookup_ino(&mut fs, "/outline.txt");
+    assert_eq!(fs.open_directory(outline).unwrap_err(), Errno::ENOTDIR);
+    let fh = fs.open_file(outline, libc::O_RDONLY).unwrap();
+    assert_eq!(fs.directory_entries(fh).unwrap_err(), Errno::ENOTDIR);
+    assert_eq!(fs.directory_entries(1000).unwrap_err(), Errno::EBADF);
+}
+
+#[test]
+fn getattr_describes_files_directories_and_links() {
+    let mut fs = mount(course());
+    let root = fs.get_attr(1).unwrap();
+    assert_eq!((root.kind, root.perm), (FileType::Directory, 0o500));
+
+    let slides = lookup_ino(&mut fs, "/Week 1/slides.pdf");
+    let slides = fs.get_attr(slides).unwrap();
+    assert_eq!(
+        (slides.kind, slides.size, slides.perm),
+        (FileType::RegularFile, 10, 0o400)
+    );
+
+    let ed = lookup_ino(&mut fs, &link_file_name("Ed"));
+    let ed = fs.get_attr(ed).unwrap();
+    // Where a link leads isn't known until it's opened
+    assert_eq!((ed.kind, ed.size), (FileType::RegularFile, 0));
+
+    // The root has been listed by now, so its subdirectory's .. is counted
+    assert_eq!(fs.get_attr(1).unwrap().nlink, 3);
+
+    assert_eq!(fs.get_attr(1000).unwrap_err(), Errno::ENOENT);
+}
+
+#[test]
+fn links_can_be_symlinks() {
+    let mut fs = mount(course()).with_link_style(LinkStyle::Symlink);
+    let ed = fs.lookup_child(1, "Ed").unwrap();
+    assert_eq!((ed.kind, ed.size), (FileType::Symlink, 0));
+}
+
+#[test]
+fn links_lead_where_they_launch_only_once_opened() {
+    let page = "https://learn.uq.edu.au/webapps/blackboard/execute/blti/launchLink";
+    let tool = "https://www.gradescope.com.au/courses/12345?from_lti=true";
+    let client = course().with(
+        "/Gradescope",
+        Node::Launch {
+            page: page.into(),
+            tool: tool.into(),
+        },
+    );
+    let mut fs = mount(client);
+    let name = link_file_name("Gradescope");
+    let ino = lookup_ino(&mut fs, &name);
+    assert_eq!(fs.get_attr(ino).unwrap().size, 0);
+    assert_eq!(
+        read(&mut fs, &name, 0, 1000).unwrap(),
+        link_file(tool).as_bytes()
+    );
+}
+
+#[test]
+fn reads_return_the_requested_range() {
+    let mut fs = mount(course());
+    let path = "/Week 1/slides.pdf";
+    assert_eq!(read(&mut fs, path, 0, 4).unwrap(), b"0123");
+    assert_eq!(read(&mut fs, path, 4, 3).unwrap(), b"456");
+    // Reads past the end are cut short, or read nothing at all
+    assert_eq!(read(&mut fs, path, 8, 100).unwrap(), b"89");
+    assert_eq!(read(&mut fs, path, 10, 1).unwrap(), b"");
+    assert_eq!(read(&mut fs, path, 1000, 1).unwrap(), b"");
+    assert_eq!(read(&mut fs, path, -1, 1).unwrap_err(), Errno::EINVAL);
+
+    let ed = link_file_name("Ed");
+    assert_eq!(
+        read(&mut fs, &ed, 0, 1000).unwrap(),
+        link_file("https://edstem.org").as_bytes()
+    );
+}
+
+#[test]
+fn reads_report_errors() {
+    let mut fs = mount(course());
+    assert_eq!(
+        read(&mut fs, "/Week 1/broken.pdf", 0, 10).unwrap_err(),
+        Errno::EIO
+    );
+
+    let week = lookup_ino(&mut fs, "/Week 1");
+    assert_eq!(
+        fs.open_file(week, libc::O_RDONLY).unwrap_err(),
+        Errno::EISDIR
+    );
+    let slides = lookup_ino(&mut fs, "/Week 1/slides.pdf");
+    assert_eq!(
+        fs.open_file(slides, libc::O_WRONLY).unwrap_err(),
+        Errno::EROFS
+    );
+    assert_eq!(
+        fs.open_file(1000, libc::O_RDONLY).unwrap_err(),
+        Errno::ENOENT
+    );
+
+    assert_eq!(fs.read_file(slides, 1000, 0, 10).unwrap_err(), Errno::EBADF);
+    let fh = fs.open_directory(week).unwrap();
+    assert_eq!(fs.read_file(week, fh, 0, 10).unwrap_err(), Errno::EISDIR);
+    // A handle only reads the file it was opened for
+    let outline = lookup_ino(&mut fs, "/outline.txt");
+    let fh = fs.open_file(outline, libc::O_RDONLY).unwrap();
+    assert_eq!(fs.read_file(slides, fh, 0, 10).unwrap_err(), Errno::EBADF);
+}
+
+#[test]
+fn handles_keep_the_contents_they_first_read() {
+    let client = course();
+    let mut fs = mount(client);
+    let ino = lookup_ino(&mut fs, "/outline.txt");
+    let fh = fs.open_file(ino, libc::O_RDONLY).unwrap();
+    assert_eq!(fs.read_file(ino, fh, 0, 100).unwrap(), b"outline");
+
+    fs.client
+        .nodes
+        .lock()
+        .unwrap()
+        .insert("/outline.txt".into(), Node::File(b"new outline".to_vec()));
+    assert_eq!(fs.read_file(ino, fh, 0, 100).unwrap(), b"outline");
+    assert_eq!(
+        read(&mut fs, "/outline.txt", 0, 100).unwrap(),
+        b"new outline"
+    );
+}
+
+fn submissions() -> FakeClient {
+    let submit = Capabilities {
+        create_file: true,
+        ..Default::default()
+    };
+    course()
+        .with("/Submit", Node::Directory)
+        .with_capabilities("/Submit", submit)
+}
+
+#[test]
+fn uploads_are_submitted_once_the_last_handle_is_closed() {
+    let mut fs = mount(submissions());
+    let submit = lookup_ino(&mut fs, "/Submit");
+    let (ino, fh) = fs.create_upload(submit, "essay.pdf").unwrap();
+    assert_eq!(fs.write_file(ino, fh, 0, b"my essay").unwrap(), 8);
+    let other = fs.open_file(ino, libc::O_WRONLY).unwrap();
+    fs.write_file(ino, other, 3, b"ESSAY").unwrap();
+
+    fs.flush_file(fh).unwrap();
+    fs.release_file(fh).unwrap();
+    assert!(fs.client.created.lock().unwrap().is_empty());
+    fs.flush_file(other).unwrap();
+    assert_eq!(*fs.client.created.lock().unwrap(), ["/Submit/essay.pdf"]);
+    fs.release_file(other).unwrap();
+    assert_eq!(fs.client.created.lock().unwrap().len(), 1);
+    assert_eq!(
+        read(&mut fs, "/Submit/essay.pdf", 0, 100).unwrap(),
+        b"my ESSAY"
+    );
+}
+
+#[test]
+fn refused_submissions_fail_the_close() {
+    let mut fs = mount(submissions());
+    let submit = lookup_ino(&mut fs, "/Submit");
+    let (ino, fh) = fs.create_upload(submit, "essay.pdf").unwrap();
+    fs.write_file(ino, fh, 0, b"my essay").unwrap();
+    // Blackboard refuses it, as it would once the assignment has closed
+    (fs.client.nodes.lock().unwrap()).insert("/Submit/essay.pdf".into(), Node::Directory);
+    assert_eq!(fs.flush_file(fh).unwrap_err(), Errno::EEXIST);
+    // It isn't submitted again as it's released
+    assert_eq!(fs.release_file(fh), Ok(()));
+}
+
+#[test]
+fn scratch_files_and_read_only_directories_are_refused() {
+    let mut fs = mount(submissions());
+    let submit = lookup_ino(&mut fs, "/Submit");
+    for name in [
+        ".DS_Store",
+        "._essay.pdf",
+        "4913",
+        ".essay.pdf.swp",
+        "essay.pdf~",
+    ] {
+        assert_eq!(fs.create_upload(submit, name).unwrap_err(), Errno::EACCES);
+    }
+    assert_eq!(fs.create_upload(submit, "outline.txt").map(drop), Ok(()));
+    assert_eq!(
+        fs.create_upload(submit, "outline.txt").unwrap_err(),
+        Errno::EEXIST
+    );
+
+    let week = lookup_ino(&mut fs, "/Week 1");
+    assert_eq!(
+        fs.create_upload(week, "essay.pdf").unwrap_err(),
+        Errno::EROFS
+    );
+    assert_eq!(fs.create_upload(1, "essay.pdf").unwrap_err(), Errno::EROFS);
+}
+
+fn editable_course() -> FakeClient {
+    let folder = Capabilities {
+        create_file: true,
+        create_folder: true,
+        ..Default::default()
+    };
+    let item = Capabilities {
+        rename: true,
+        delete: true,
+        ..Default::default()
+    };
+    let description = Capabilities {
+        set_description: true,
+        ..Default::default()
+    };
+    course()
+        .with("/Week 1/Old", Node::Directory)
+        .with("/Week 1/Old/notes.txt", Node::File(b"notes".to_vec()))
+        .with("/Week 1/Empty", Node::Directory)
+        .with_capabilities("/Week 1", folder)
+        .with_capabilities("/Week 1/Old", item)
+        .with_capabilities("/Week 1/Empty", item)
+        .with_capabilities("/Week 1/slides.pdf", item)
+        .with_capabilities("/outline.txt", description)
+}
+
+#[test]
+fn folders_are_made_and_renamed_with_ordinary_names() {
+    let mut fs = mount(editable_course());
+    let week = lookup_ino(&mut fs, "/Week 1");
+
+    let attr = fs.create_folder(week, "Week 2").unwrap();
+    assert_eq!(attr.kind, FileType::Directory);
+    assert_eq!(lookup_ino(&mut fs, "/Week 1/Week 2"), attr.ino);
+    assert_eq!(fs.create_folder(week, "Week 2").unwrap_err(), Errno::EEXIST);
+    for name in [".hidden", "untitled~", "4913"] {
+        assert_eq!(fs.create_folder(week, name).unwrap_err(), Errno::EACCES);
+    }
+    assert_eq!(fs.create_folder(1, "Week 2").unwrap_err(), Errno::EROFS);
+
+    fs.rename_child(week, "Old", week, "Older").unwrap();
+    assert_eq!(
+        read(&mut fs, "/Week 1/Older/notes.txt", 0, 100).unwrap(),
+        b"notes"
+    );
+    assert!(fs.lookup_child(week, "Old").is_err());
+    assert_eq!(
+        fs.rename_child(week, "Empty", 1, "Empty").unwrap_err(),
+        Errno::EXDEV
+    );
+    assert_eq!(
+        fs.rename_child(week, "Empty", week, ".Empty").unwrap_err(),
+        Errno::EACCES
+    );
+    assert_eq!(
+        fs.rename_child(week, "broken.pdf", week, "a.pdf")
+            .unwrap_err(),
+        Errno::EROFS
+    );
+}
+
+#[test]
+fn only_empty_folders_are_removed() {
+    let mut fs = mount(editable_course());
+    let week = lookup_ino(&mut fs, "/Week 1");
+
+    assert_eq!(fs.remove(week, "Old", true).unwrap_err(), Errno::ENOTEMPTY);
+    assert_eq!(
+        read(&mut fs, "/Week 1/Old/notes.txt", 0, 100).unwrap(),
+        b"notes"
+    );
+    assert_eq!(fs.remove(week, "Old", false).unwrap_err(), Errno::EISDIR);
+    fs.remove(week, "Empty", true).unwrap();
+    assert!(fs.lookup_child(week, "Empty").is_err());
+
+    assert_eq!(
+        fs.remove(week, "slides.pdf", true).unwrap_err(),
+        Errno::ENOTDIR
+    );
+    fs.remove(week, "slides.pdf", false).unwrap();
+    assert!(fs.lookup_child(week, "slides.pdf").is_err());
+    assert_eq!(
+        fs.remove(week, "broken.pdf", false).unwrap_err(),
+        Errno::EROFS
+    );
+}
+
+#[test]
+fn descriptions_are_saved_when_flushed() {
+    let mut fs = mount(editable_course());
+    let ino = lookup_ino(&mut fs, "/outline.txt");
+    let fh = fs.open_file(ino, libc::O_WRONLY | libc::O_TRUNC).unwrap();
+    fs.write_file(ino, fh, 0, b"new outline").unwrap();
+    fs.save_edit(fh).unwrap();
+    assert!(matches!(
+        fs.client.node("/outline.txt"),
+        Ok(Node::File(contents)) if contents == b"new outline"
+    ));
+    fs.release_file(fh).unwrap();
+    assert_eq!(
+        read(&mut fs, "/outline.txt", 0, 100).unwrap(),
+        b"new outline"
+    );
+
+    let slides = lookup_ino(&mut fs, "/Week 1/slides.pdf");
+    assert_eq!(
+        fs.open_file(slides, libc::O_WRONLY).unwrap_err(),
+        Errno::EROFS
+    );
+}
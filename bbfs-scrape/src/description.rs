@@ -0,0 +1,540 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+use regex::Regex;
+use soup::prelude::*;
+
+use crate::{CourseItemContent, BB_BASE_URL};
+
+static SCRIPT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new("(?s)<script.*?>.*?</script>").unwrap());
+static BR: LazyLock<Regex> = LazyLock::new(|| Regex::new("<br>(</br>)?").unwrap());
+static BLANK_LINES: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\n[ \t]*\n(\s*\n)*").unwrap());
+static WHITESPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
+
+/// The format that the descriptions of content items are shown in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DescriptionFormat {
+    /// Markdown, keeping links, lists and emphasis, with embedded files downloaded alongside
+    #[default]
+    Markdown,
+    /// The HTML shown on Blackboard, as is
+    Html,
+    /// Only the text, without any formatting
+    Text,
+}
+
+impl DescriptionFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+            Self::Text => "txt",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Markdown => "text/markdown",
+            Self::Html => "text/html",
+            Self::Text => "text/plain",
+        }
+    }
+
+    /// Renders a description scraped from Blackboard, linking to embedded images and files by
+    /// the paths that `local_path` gives for their URLs, or by their URLs where it gives none.
+    pub(crate) fn render(&self, html: &str, local_path: impl Fn(&str) -> Option<String>) -> String {
+        match self {
+            Self::Markdown => to_markdown(html, &local_path),
+            Self::Html => html.into(),
+            Self::Text => to_text(html),
+        }
+    }
+
+    /// The HTML to store on Blackboard for a description written in this format. Links to the
+    /// files shown beside it are pointed back at Blackboard by `blackboard_url`, which gives the
+    /// URL of a file by the path it's linked to by.
+    pub(crate) fn html_of(
+        &self,
+        written: &str,
+        blackboard_url: impl Fn(&str) -> Option<String>,
+    ) -> String {
+        match self {
+            Self::Markdown => from_markdown(written, &blackboard_url),
+            Self::Html => written.into(),
+            // Each block separated by a blank line becomes a paragraph
+            Self::Text => written
+                .replace("\r\n", "\n")
+                .split("\n\n")
+                .map(str::trim)
+                .filter(|paragraph| !paragraph.is_empty())
+                .map(|paragraph| format!("<p>{}</p>", escape_html(paragraph).replace('\n', "<br>")))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownFormatError(String);
+
+impl Display for UnknownFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unknown description format {:?} (expected md, html or txt)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownFormatError {}
+
+impl FromStr for DescriptionFormat {
+    type Err = UnknownFormatError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "md" | "markdown" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "txt" | "text" => Ok(Self::Text),
+            _ => Err(UnknownFormatError(format.into())),
+        }
+    }
+}
+
+impl Display for DescriptionFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// Removes the scripts that Blackboard embeds in descriptions, returning None if nothing would
+/// be left to show.
+pub(crate) fn clean(html: &str) -> Option<String> {
+    let html = SCRIPT.replace_all(html, "").trim().to_string();
+    let soup = Soup::new(&html);
+    let empty = soup.text().trim().is_empty() && soup.tag("img").find().is_none();
+    (!empty).then_some(html)
+}
+
+/// The Blackboard paths of the images and files embedded in a description, which Blackboard's
+/// editor links to by their `bbcswebdav` URLs rather than listing as attachments.
+pub(crate) fn embedded_files(html: &str) -> Vec<String> {
+    let soup = Soup::new(html);
+    let images = soup.tag("img").find_all().filter_map(|img| img.get("src"));
+    let files = soup
+        .tag("a")
+        .find_all()
+        .filter_map(|a| a.get("href"))
+        .filter(|href| {
+            blackboard_path(href).is_some_and(|path| {
+                matches!(
+                    CourseItemContent::from_url(path.into()),
+                    CourseItemContent::FileUrl(_)
+                )
+            })
+        });
+    let mut paths: Vec<String> = vec![];
+    for url in images.chain(files) {
+        match blackboard_path(&url) {
+            Some(path) if !paths.iter().any(|seen| seen == path) => paths.push(path.into()),
+            _ => {}
+        }
+    }
+    paths
+}
+
+/// The path of a URL on Blackboard, or None if it's hosted elsewhere.
+pub(crate) fn blackboard_path(url: &str) -> Option<&str> {
+    let path = url.strip_prefix(BB_BASE_URL).unwrap_or(url);
+    (path.starts_with('/') && !path.starts_with("//")).then_some(path)
+}
+
+fn absolute_url(url: &str) -> String {
+    if url.starts_with('/') && !url.starts_with("//") {
+        format!("{BB_BASE_URL}{url}")
+    } else {
+        url.into()
+    }
+}
+
+/// Renders Markdown as HTML, understanding the same tables and strikethrough that descriptions
+/// are rendered with.
+fn from_markdown(markdown: &str, blackboard_url: &dyn Fn(&str) -> Option<String>) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let events = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Start(Tag::Link(ty, url, title)) => {
+            let url = blackboard_url(&url).map_or(url, CowStr::from);
+            Event::Start(Tag::Link(ty, url, title))
+        }
+        Event::Start(Tag::Image(ty, url, title)) => {
+            let url = blackboard_url(&url).map_or(url, CowStr::from);
+            Event::Start(Tag::Image(ty, url, title))
+        }
+        event => event,
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+    html
+}
+
+fn to_text(html: &str) -> String {
+    let html = BR.replace_all(html, "\n");
+    Soup::new(&html).text().trim().into()
+}
+
+fn to_markdown(html: &str, local_path: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut markdown = String::new();
+    if let Some(body) = Soup::new(html).tag("body").find() {
+        for child in body.children() {
+            write_markdown(&child, &mut markdown, local_path);
+        }
+    }
+    let markdown = BLANK_LINES.replace_all(markdown.trim(), "\n\n");
+    format!("{markdown}\n")
+}
+
+/// Starts a new block, separated from whatever came before by a blank line.
+fn start_block(markdown: &mut String) {
+    let trimmed = markdown.trim_end_matches([' ', '\t']).len();
+    markdown.truncate(trimmed);
+    if !markdown.is_empty() && !markdown.ends_with("\n\n") {
+        markdown.push_str(if markdown.ends_with('\n') {
+            "\n"
+        } else {
+            "\n\n"
+        });
+    }
+}
+
+/// Escapes the characters in text that HTML would otherwise read as markup.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Escapes the characters in text that Markdown would otherwise read as formatting.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Writes the node's children to a separate string, for when they need to be wrapped or
+/// indented as a whole.
+fn children_markdown(
+    node: &(impl NodeExt + QueryBuilderExt),
+    local_path: &dyn Fn(&str) -> Option<String>,
+) -> String {
+    let mut markdown = String::new();
+    for child in node.children() {
+        write_markdown(&child, &mut markdown, local_path);
+    }
+    markdown.trim().into()
+}
+
+fn write_markdown(
+    node: &(impl NodeExt + QueryBuilderExt),
+    markdown: &mut String,
+    local_path: &dyn Fn(&str) -> Option<String>,
+) {
+    if node.is_text() {
+        let text = WHITESPACE.replace_all(&node.text(), " ").into_owned();
+        // Whitespace at the start of a line would be read as indentation
+        let text = if markdown.is_empty() || markdown.ends_with(['\n', ' ']) {
+            text.trim_start()
+        } else {
+            &text
+        };
+        markdown.push_str(&escape(text));
+        return;
+    }
+    if !node.is_element() {
+        return;
+    }
+
+    match node.name() {
+        "script" | "style" => {}
+        "br" => markdown.push_str("  \n"),
+        "hr" => {
+            start_block(markdown);
+            markdown.push_str("---\n\n");
+        }
+        "p" | "div" | "section" | "article" | "header" | "footer" => {
+            start_block(markdown);
+            for child in node.children() {
+                write_markdown(&child, markdown, local_path);
+            }
+            start_block(markdown);
+        }
+        heading @ ("h1" | "h2" | "h3" | "h4" | "h5" | "h6") => {
+            let level: usize = heading[1..].parse().unwrap();
+            start_block(markdown);
+            let text = children_markdown(node, local_path).replace('\n', " ");
+            markdown.push_str(&format!("{} {text}\n\n", "#".repeat(level)));
+        }
+        "strong" | "b" => wrap_inline(node, markdown, "**", local_path),
+        "em" | "i" => wrap_inline(node, markdown, "*", local_path),
+        "del" | "s" | "strike" => wrap_inline(node, markdown, "~~", local_path),
+        "code" => {
+            let code = node.text();
+            let fence = if code.contains('`') { "``" } else { "`" };
+            markdown.push_str(&format!("{fence}{code}{fence}"));
+        }
+        "pre" => {
+            start_block(markdown);
+            markdown.push_str(&format!("```\n{}\n```\n\n", node.text().trim_end()));
+        }
+        "a" => {
+            let text = children_markdown(node, local_path);
+            match node.get("href").filter(|href| !href.starts_with('#')) {
+                Some(href) => {
+                    let url = local_path(&href).unwrap_or_else(|| absolute_url(&href));
+                    let text = if text.is_empty() { escape(&url) } else { text };
+                    markdown.push_str(&format!("[{text}](<{url}>)"));
+                }
+                None => markdown.push_str(&text),
+            }
+        }
+        "img" => {
+            if let Some(src) = node.get("src") {
+                let alt = escape(&node.get("alt").unwrap_or_default());
+                let path = local_path(&src).unwrap_or_else(|| absolute_url(&src));
+                markdown.push_str(&format!("![{alt}](<{path}>)"));
+            }
+        }
+        list @ ("ul" | "ol") => {
+            start_block(markdown);
+            let items = node
+                .children()
+                .filter(|child| child.is_element() && child.name() == "li");
+            for (i, item) in items.enumerate() {
+                let marker = if list == "ol" {
+                    format!("{}. ", i + 1)
+                } else {
+                    "- ".into()
+                };
+                let indent = " ".repeat(marker.len());
+                let contents = children_markdown(&item, local_path);
+                // Blank lines are dropped to keep the list tight, which is how it's shown on
+                // Blackboard even where an item holds paragraphs or a nested list
+                let lines = contents.lines().filter(|line| !line.is_empty());
+                for (j, line) in lines.enumerate() {
+                    markdown.push_str(if j == 0 { &marker } else { &indent });
+                    markdown.push_str(line);
+                    markdown.push('\n');
+                }
+                if contents.is_empty() {
+                    markdown.push_str(marker.trim_end());
+                    markdown.push('\n');
+                }
+            }
+            markdown.push('\n');
+        }
+        "blockquote" => {
+            start_block(markdown);
+            for line in children_markdown(node, local_path).lines() {
+                markdown.push_str(if line.is_empty() { ">" } else { "> " });
+                markdown.push_str(line);
+                markdown.push('\n');
+            }
+            markdown.push('\n');
+        }
+        "table" => {
+            start_block(markdown);
+            write_table(node, markdown, local_path);
+            markdown.push('\n');
+        }
+        _ => {
+            for child in node.children() {
+                write_markdown(&child, markdown, local_path);
+            }
+        }
+    }
+}
+
+fn wrap_inline(
+    node: &(impl NodeExt + QueryBuilderExt),
+    markdown: &mut String,
+    delimiter: &str,
+    local_path: &dyn Fn(&str) -> Option<String>,
+) {
+    let text = children_markdown(node, local_path);
+    if text.is_empty() {
+        return;
+    }
+    // Emphasis can't start right after a word without a space in between
+    if markdown.ends_with(|c: char| c.is_alphanumeric()) {
+        markdown.push(' ');
+    }
+    markdown.push_str(&format!("{delimiter}{text}{delimiter}"));
+}
+
+/// Writes a table as a Markdown table, treating its first row as the header since Blackboard's
+/// editor rarely marks one.
+fn write_table(
+    table: &(impl NodeExt + QueryBuilderExt),
+    markdown: &mut String,
+    local_path: &dyn Fn(&str) -> Option<String>,
+) {
+    let rows: Vec<Vec<String>> = table
+        .tag("tr")
+        .find_all()
+        .map(|row| {
+            row.children()
+                .filter(|cell| cell.is_element() && matches!(cell.name(), "td" | "th"))
+                .map(|cell| {
+                    children_markdown(&cell, local_path)
+                        .replace('\n', " ")
+                        .replace('|', "\\|")
+                })
+                .collect()
+        })
+        .filter(|row: &Vec<String>| !row.is_empty())
+        .collect();
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    for (i, row) in rows.iter().enumerate() {
+        let cells: Vec<&str> = (0..columns)
+            .map(|column| row.get(column).map_or("", String::as_str))
+            .collect();
+        markdown.push_str(&format!("| {} |\n", cells.join(" | ")));
+        if i == 0 {
+            markdown.push_str(&format!("|{}\n", " --- |".repeat(columns)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markdown(html: &str) -> String {
+        DescriptionFormat::Markdown.render(html, |_| None)
+    }
+
+    #[test]
+    fn paragraphs_and_emphasis_become_markdown() {
+        assert_eq!(
+            markdown(
+                "<p>Read <strong>all</strong> of it,<br>or <em>most</em>.</p><p>Then rest</p>"
+            ),
+            "Read **all** of it,  \nor *most*.\n\nThen rest\n"
+        );
+    }
+
+    #[test]
+    fn headings_lists_and_quotes_keep_their_structure() {
+        let html = "<h2>Week 1</h2>\
+                    <ul><li>Slides</li><li>Notes<ol><li>Part A</li><li>Part B</li></ol></li></ul>\
+                    <blockquote><p>Be on time</p></blockquote>";
+        assert_eq!(
+            markdown(html),
+            "## Week 1\n\n- Slides\n- Notes\n  1. Part A\n  2. Part B\n\n> Be on time\n"
+        );
+    }
+
+    #[test]
+    fn links_point_to_local_files_or_blackboard() {
+        let html = r#"<p><a href="/bbcswebdav/pid-1/notes.pdf">Notes</a>
+            <a href="https://example.com/">Elsewhere</a>
+            <img src="/bbcswebdav/pid-1/diagram.png" alt="Diagram"></p>"#;
+        let rendered = DescriptionFormat::Markdown.render(html, |url| {
+            url.ends_with(".png").then(|| "diagram.png".into())
+        });
+        assert_eq!(
+            rendered,
+            "[Notes](<https://learn.uq.edu.au/bbcswebdav/pid-1/notes.pdf>) \
+             [Elsewhere](<https://example.com/>) ![Diagram](<diagram.png>)\n"
+        );
+    }
+
+    #[test]
+    fn tables_use_their_first_row_as_the_header() {
+        let html = "<table><tr><td>Week</td><td>Topic</td></tr>\
+                    <tr><td>1</td><td>Sorting | searching</td></tr></table>";
+        assert_eq!(
+            markdown(html),
+            "| Week | Topic |\n| --- | --- |\n| 1 | Sorting \\| searching |\n"
+        );
+    }
+
+    #[test]
+    fn markdown_in_the_text_is_escaped() {
+        assert_eq!(
+            markdown("<p>*Not* a [link] or # heading</p>"),
+            "\\*Not\\* a \\[link\\] or \\# heading\n"
+        );
+    }
+
+    #[test]
+    fn scripts_are_removed_and_empty_descriptions_dropped() {
+        assert_eq!(
+            clean("<p>Hi</p><script>alert(1)</script>").as_deref(),
+            Some("<p>Hi</p>")
+        );
+        assert_eq!(clean("<p> </p><script>alert(1)</script>"), None);
+        assert!(clean(r#"<img src="/bbcswebdav/a.png">"#).is_some());
+    }
+
+    #[test]
+    fn embedded_images_and_files_are_found_once_each() {
+        let html = r#"<p><img src="https://learn.uq.edu.au/bbcswebdav/pid-1/a.png">
+            <a href="/bbcswebdav/pid-1/notes.pdf">Notes</a>
+            <a href="/bbcswebdav/pid-1/notes.pdf">Notes again</a>
+            <a href="/webapps/blackboard/content/listContent.jsp?content_id=_1_1">Folder</a>
+            <a href="https://example.com/b.pdf">Elsewhere</a>
+            <img src="https://example.com/c.png"></p>"#;
+        assert_eq!(
+            embedded_files(html),
+            ["/bbcswebdav/pid-1/a.png", "/bbcswebdav/pid-1/notes.pdf"]
+        );
+    }
+
+    #[test]
+    fn text_keeps_line_breaks_only() {
+        assert_eq!(
+            DescriptionFormat::Text.render("<p><b>Due</b> Friday<br>at 4pm</p>", |_| None),
+            "Due Friday\nat 4pm"
+        );
+    }
+
+    #[test]
+    fn written_markdown_is_stored_as_html_linking_to_blackboard() {
+        let written = "Read **all** of [the notes](<notes.pdf>).\n\n\
+                       | Week | Topic |\n| --- | --- |\n| 1 | ~~Sorting~~ |\n";
+        let html = DescriptionFormat::Markdown.html_of(written, |path| {
+            (path == "notes.pdf").then(|| "https://learn.uq.edu.au/bbcswebdav/notes.pdf".into())
+        });
+        assert_eq!(
+            html,
+            "<p>Read <strong>all</strong> of \
+             <a href=\"https://learn.uq.edu.au/bbcswebdav/notes.pdf\">the notes</a>.</p>\n\
+             <table><thead><tr><th>Week</th><th>Topic</th></tr></thead><tbody>\n\
+             <tr><td>1</td><td><del>Sorting</del></td></tr>\n</tbody></table>\n"
+        );
+
+        let rendered = markdown("<p>Read <strong>all</strong> of it</p><ul><li>Slides</li></ul>");
+        let html = DescriptionFormat::Markdown.html_of(&rendered, |_| None);
+        assert_eq!(
+            html,
+            "<p>Read <strong>all</strong> of it</p>\n<ul>\n<li>Slides</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn written_text_is_escaped_into_paragraphs() {
+        assert_eq!(
+            DescriptionFormat::Text.html_of("Due <Friday>\nat 4pm\r\n\r\nLate", |_| None),
+            "<p>Due &lt;Friday&gt;<br>at 4pm</p><p>Late</p>"
+        );
+    }
+}
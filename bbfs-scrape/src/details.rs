@@ -0,0 +1,168 @@
+//! What Blackboard's REST API says about the items in a folder that the folder's page doesn't, or
+//! only says in the user's language: when they were made, when they're due and whether students
+//! can see them.
+
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+#[derive(Deserialize)]
+struct Children {
+    results: Vec<Content>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Content {
+    id: String,
+    created: Option<String>,
+    #[serde(default)]
+    availability: Availability,
+    #[serde(default)]
+    content_handler: ContentHandler,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Availability {
+    /// "Yes", "No", or "Term" for only while the term is on
+    available: Option<String>,
+    #[serde(default)]
+    adaptive_release: AdaptiveRelease,
+}
+
+/// The dates an item is shown to students between, if it's restricted to them.
+#[derive(Default, Deserialize)]
+struct AdaptiveRelease {
+    start: Option<String>,
+    end: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContentHandler {
+    /// The grade column of an assignment or test, which is where its due date is kept
+    grade_column_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GradeColumn {
+    grading: Grading,
+}
+
+#[derive(Deserialize)]
+struct Grading {
+    due: Option<String>,
+}
+
+fn parse_date(date: &Option<String>) -> Option<SystemTime> {
+    let date = OffsetDateTime::parse(date.as_deref()?, &Rfc3339).ok()?;
+    Some(date.into())
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Details {
+    pub(crate) created: Option<SystemTime>,
+    /// Whether the item's release dates keep it from students right now
+    pub(crate) hidden: bool,
+    /// Whether the item has been made unavailable to students altogether
+    pub(crate) unavailable: bool,
+    /// The grade column to look the item's due date up in, if it has one
+    pub(crate) grade_column_id: Option<String>,
+}
+
+/// The path of the API's listing of the items in a folder.
+pub(crate) fn children_path(course_id: &str, folder_id: &str) -> String {
+    format!("/learn/api/public/v1/courses/{course_id}/contents/{folder_id}/children?limit=200")
+}
+
+/// The path of the API's description of a grade column.
+pub(crate) fn grade_column_path(course_id: &str, column_id: &str) -> String {
+    format!("/learn/api/public/v2/courses/{course_id}/gradebook/columns/{column_id}")
+}
+
+/// Reads the details of each item in a folder, by the item's content ID.
+pub(crate) fn parse_children(
+    json: &str,
+    now: SystemTime,
+) -> Result<HashMap<String, Details>, serde_json::Error> {
+    let children: Children = serde_json::from_str(json)?;
+    Ok(children
+        .results
+        .into_iter()
+        .map(|content| {
+            let release = &content.availability.adaptive_release;
+            let released = parse_date(&release.start).is_none_or(|start| start <= now)
+                && parse_date(&release.end).is_none_or(|end| now < end);
+            let details = Details {
+                created: parse_date(&content.created),
+                hidden: !released,
+                unavailable: content.availability.available.as_deref() == Some("No"),
+                grade_column_id: content.content_handler.grade_column_id,
+            };
+            (content.id, details)
+        })
+        .collect())
+}
+
+/// Reads when a grade column's assessment is due, if it has a due date.
+pub(crate) fn parse_due(json: &str) -> Result<Option<SystemTime>, serde_json::Error> {
+    let column: GradeColumn = serde_json::from_str(json)?;
+    Ok(parse_date(&column.grading.due))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn at(date: &str) -> SystemTime {
+        parse_date(&Some(date.into())).unwrap()
+    }
+
+    #[test]
+    fn items_are_described_by_their_availability() {
+        let json = r#"{"results": [
+            {"id": "_1_1", "created": "2023-02-20T01:02:03.000Z",
+             "availability": {"available": "Yes"}},
+            {"id": "_2_1", "availability": {"available": "No"}},
+            {"id": "_3_1", "availability": {"available": "Yes",
+                "adaptiveRelease": {"start": "2023-03-01T00:00:00.000Z"}}},
+            {"id": "_4_1", "availability": {"available": "Yes",
+                "adaptiveRelease": {"end": "2023-03-01T00:00:00.000Z"}},
+             "contentHandler": {"id": "resource/x-bb-assignment", "gradeColumnId": "_9_1"}}
+        ]}"#;
+        let details = parse_children(json, at("2023-02-25T00:00:00Z")).unwrap();
+
+        assert_eq!(
+            details["_1_1"],
+            Details {
+                created: Some(at("2023-02-20T01:02:03Z")),
+                ..Default::default()
+            }
+        );
+        assert!(details["_2_1"].unavailable && !details["_2_1"].hidden);
+        assert!(details["_3_1"].hidden && !details["_3_1"].unavailable);
+        assert!(!details["_4_1"].hidden);
+        assert_eq!(details["_4_1"].grade_column_id.as_deref(), Some("_9_1"));
+
+        let later = parse_children(json, at("2023-03-02T00:00:00Z")).unwrap();
+        assert!(!later["_3_1"].hidden);
+        assert!(later["_4_1"].hidden);
+    }
+
+    #[test]
+    fn due_dates_are_read_from_grade_columns() {
+        let json = r#"{"id": "_9_1",
+            "grading": {"type": "Attempts", "due": "2023-03-10T06:00:00.000Z"}}"#;
+        assert_eq!(
+            parse_due(json).unwrap(),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1678428000))
+        );
+        assert_eq!(parse_due(r#"{"grading": {}}"#).unwrap(), None);
+    }
+}
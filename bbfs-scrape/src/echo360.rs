@@ -0,0 +1,167 @@
+//! Echo360, where lecture recordings are kept. Courses link to it from their menu, and launching
+//! it starts a session on Echo360 for the course's section.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A lecture recording listed in the Echo360 section that a course launches into.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Recording {
+    title: String,
+    /// The day the lecture was given, like 2023-07-24
+    date: Option<String>,
+    /// The page the recording is watched on
+    pub(crate) page_url: String,
+    /// Where the video can be downloaded from with the Echo360 session, if it can be
+    pub(crate) media_url: Option<String>,
+    /// The Blackboard URL of the LTI launch that the session is started with
+    pub(crate) launch_url: String,
+}
+
+impl Recording {
+    /// The name of the recording, which starts with its date so that recordings sort by it.
+    pub(crate) fn name(&self) -> String {
+        let name = match &self.date {
+            Some(date) => format!("{date} {}", self.title),
+            None => self.title.clone(),
+        };
+        name.replace(['/', '\\'], "-")
+    }
+
+    /// The name of the recording's video file.
+    pub(crate) fn video_name(&self) -> String {
+        format!("{}.{}", self.name(), self.extension())
+    }
+
+    fn extension(&self) -> &str {
+        self.media_url
+            .as_deref()
+            .and_then(|url| url.split(['?', '#']).next()?.rsplit_once('.'))
+            .map(|(_, extension)| extension)
+            .filter(|extension| !extension.is_empty() && !extension.contains('/'))
+            .unwrap_or("mp4")
+    }
+
+    pub(crate) fn mime_type(&self) -> Option<&'static str> {
+        match self.extension() {
+            "mp4" | "m4v" => Some("video/mp4"),
+            "m4a" => Some("audio/mp4"),
+            "mp3" => Some("audio/mpeg"),
+            "webm" => Some("video/webm"),
+            _ => None,
+        }
+    }
+}
+
+/// A session on Echo360, started by launching it from a course.
+#[derive(Clone, Debug)]
+pub(crate) struct Session {
+    /// Where Echo360 is hosted, like https://echo360.net.au
+    pub(crate) origin: String,
+    pub(crate) section_id: String,
+    pub(crate) cookies: String,
+}
+
+/// Whether a link in a course's menu leads to its lecture recordings.
+pub(crate) fn is_recordings_link(name: &str, url: &str) -> bool {
+    let name = name.to_lowercase().replace(['_', '-'], " ");
+    name.contains("lecture recording") || name.contains("echo360") || url.contains("echo360")
+}
+
+/// The section that Echo360 ended up at after the launch, which holds the course's recordings.
+pub(crate) fn section_id(url: &str) -> Option<String> {
+    Regex::new("/section/([0-9A-Za-z-]+)")
+        .unwrap()
+        .captures(url)
+        .map(|captures| captures[1].into())
+}
+
+/// Reads the recordings out of a section's syllabus, which lists every lesson scheduled in it,
+/// whether or not it was recorded.
+pub(crate) fn parse_syllabus(
+    json: &str,
+    origin: &str,
+    launch_url: &str,
+) -> Result<Vec<Recording>, String> {
+    let syllabus: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    let lessons = syllabus["data"]
+        .as_array()
+        .ok_or("the syllabus didn't list any lessons")?;
+    let mut recordings: Vec<Recording> = lessons
+        .iter()
+        .filter_map(|entry| {
+            let lesson = &entry["lesson"];
+            // Lessons are listed before they're recorded
+            if !lesson["hasVideo"].as_bool().unwrap_or(true) {
+                return None;
+            }
+            let id = lesson["lesson"]["id"].as_str()?;
+            let title = lesson["lesson"]["name"].as_str().unwrap_or("Recording");
+            let date = lesson["lesson"]["timing"]["start"]
+                .as_str()
+                .or(lesson["startTimeUTC"].as_str())
+                .and_then(|start| start.get(..10));
+            // The highest quality file is listed last
+            let media_url = lesson["video"]["media"]["media"]["current"]["primaryFiles"]
+                .as_array()
+                .and_then(|files| files.iter().rev().find_map(|file| file["s3Url"].as_str()));
+            Some(Recording {
+                title: title.trim().into(),
+                date: date.map(Into::into),
+                page_url: format!("{origin}/lesson/{id}/classroom"),
+                media_url: media_url.map(Into::into),
+                launch_url: launch_url.into(),
+            })
+        })
+        .collect();
+
+    // Lectures given twice on the same day, or split into parts, are often titled the same, so
+    // the later ones are numbered to tell them apart
+    let mut seen = HashMap::new();
+    for recording in &mut recordings {
+        let count = seen.entry(recording.name()).or_insert(0);
+        *count += 1;
+        if *count > 1 {
+            recording.title = format!("{} ({count})", recording.title);
+        }
+    }
+    Ok(recordings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recordings_with_the_same_date_and_title_are_numbered() {
+        let lesson = |id: &str, start: &str| {
+            serde_json::json!({"lesson": {
+                "lesson": {"id": id, "name": "Lecture", "timing": {"start": start}},
+                "video": {"media": {"media": {"current": {"primaryFiles": [
+                    {"s3Url": format!("https://media.example.com/{id}.mp4")}
+                ]}}}},
+            }})
+        };
+        let syllabus = serde_json::json!({"data": [
+            lesson("a", "2023-07-24T10:00:00"),
+            lesson("b", "2023-07-24T14:00:00"),
+            lesson("c", "2023-07-25T10:00:00"),
+            lesson("d", "2023-07-24T16:00:00"),
+        ]});
+        let recordings =
+            parse_syllabus(&syllabus.to_string(), "https://echo360.net.au", "/launch").unwrap();
+        let names: Vec<_> = recordings.iter().map(Recording::video_name).collect();
+        assert_eq!(
+            names,
+            [
+                "2023-07-24 Lecture.mp4",
+                "2023-07-24 Lecture (2).mp4",
+                "2023-07-25 Lecture.mp4",
+                "2023-07-24 Lecture (3).mp4",
+            ]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Changing course content, which staff can do through Blackboard's REST API with the same
+//! session that content is read with.
+
+use std::io;
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::request::Requester;
+use crate::{submission, BbError, BbScrapeClient, BB_BASE_URL};
+
+/// Whether a course role lets its members edit the course's content.
+pub(crate) fn can_edit(course_role: &str) -> bool {
+    matches!(
+        course_role,
+        "Instructor" | "TeachingAssistant" | "CourseBuilder"
+    )
+}
+
+/// Blackboard only accepts changes made with a session if they carry the XSRF token kept in its
+/// BbRouter cookie.
+fn xsrf_token(cookies: &str) -> Option<&str> {
+    Regex::new("xsrf:([0-9A-Za-z-]+)")
+        .unwrap()
+        .captures(cookies)
+        .and_then(|captures| captures.get(1))
+        .map(|token| token.as_str())
+}
+
+/// Where the API keeps a content item.
+fn content_url(course_id: &str, content_id: &str) -> String {
+    format!("{BB_BASE_URL}/learn/api/public/v1/courses/{course_id}/contents/{content_id}")
+}
+
+/// Sends a change to the API, returning the JSON it responded with, if any.
+fn send(
+    requester: &Requester,
+    cookies: &str,
+    method: &str,
+    url: &str,
+    content_type: &str,
+    body: &[u8],
+) -> Result<Value, BbError> {
+    let mut headers = vec![("Content-Type", content_type)];
+    headers.extend(xsrf_token(cookies).map(|token| ("X-Blackboard-XSRF", token)));
+    let (final_url, json) = requester
+        .send(method, url, cookies, &headers, body, |response| {
+            Ok((response.get_url().to_owned(), response.into_string()?))
+        })
+        .map_err(|err| {
+            BbError::from_request(
+                err,
+                url,
+                |err| BbError::FailedToEdit(url.into(), err),
+                |err| BbError::FailedToReadEdit(url.into(), err),
+            )
+        })?;
+    BbScrapeClient::check_session(&final_url)?;
+    // Deleting responds with nothing at all
+    if json.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_str(&json).map_err(|err| {
+        BbError::FailedToReadEdit(url.into(), io::Error::new(io::ErrorKind::InvalidData, err))
+    })
+}
+
+fn send_json(
+    requester: &Requester,
+    cookies: &str,
+    method: &str,
+    url: &str,
+    json: Value,
+) -> Result<Value, BbError> {
+    let body = json.to_string();
+    send(
+        requester,
+        cookies,
+        method,
+        url,
+        "application/json",
+        body.as_bytes(),
+    )
+}
+
+pub(crate) fn create_folder(
+    requester: &Requester,
+    cookies: &str,
+    course_id: &str,
+    parent_id: &str,
+    name: &str,
+) -> Result<(), BbError> {
+    let url = format!("{}/children", content_url(course_id, parent_id));
+    let folder = json!({
+        "title": name,
+        "contentHandler": { "id": "resource/x-bb-folder" },
+    });
+    send_json(requester, cookies, "POST", &url, folder).map(drop)
+}
+
+/// Uploads a file as a new item in a folder, titled with the file's name.
+pub(crate) fn create_file(
+    requester: &Requester,
+    cookies: &str,
+    course_id: &str,
+    parent_id: &str,
+    name: &str,
+    contents: &[u8],
+) -> Result<(), BbError> {
+    // The file is uploaded on its own first, and the item is then made to refer to the upload
+    let upload_url = format!("{BB_BASE_URL}/learn/api/public/v1/uploads");
+    let (body, content_type) = submission::multipart(&[], "file", name, contents);
+    let upload = send(
+        requester,
+        cookies,
+        "POST",
+        &upload_url,
+        &content_type,
+        &body,
+    )?;
+    let upload_id = upload["id"].as_str().ok_or_else(|| {
+        BbError::FailedToReadEdit(
+            upload_url.clone(),
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Blackboard didn't say where it put the upload",
+            ),
+        )
+    })?;
+
+    let url = format!("{}/children", content_url(course_id, parent_id));
+    let file = json!({
+        "title": name,
+        "contentHandler": {
+            "id": "resource/x-bb-file",
+            "file": {
+                "uploadId": upload_id,
+                "fileName": name,
+                "duplicateFileHandling": "Rename",
+            },
+        },
+    });
+    send_json(requester, cookies, "POST", &url, file).map(drop)
+}
+
+/// Changes the given fields of a content item, such as its title or body.
+pub(crate) fn update(
+    requester: &Requester,
+    cookies: &str,
+    course_id: &str,
+    content_id: &str,
+    fields: Value,
+) -> Result<(), BbError> {
+    let url = content_url(course_id, content_id);
+    send_json(requester, cookies, "PATCH", &url, fields).map(drop)
+}
+
+pub(crate) fn delete(
+    requester: &Requester,
+    cookies: &str,
+    course_id: &str,
+    content_id: &str,
+) -> Result<(), BbError> {
+    let url = content_url(course_id, content_id);
+    send(requester, cookies, "DELETE", &url, "application/json", &[]).map(drop)
+}
@@ -1,135 +1,229 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::io::Read;
 use std::num::ParseIntError;
-use std::sync::Mutex;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::anyhow;
 use bbfs_api::BbClient;
+use bbfs_api::Capabilities;
 use bbfs_api::ItemType;
+use bbfs_api::LtiLaunch;
+use bbfs_api::Metadata;
+use bbfs_api::Revision;
+use bbfs_api::Unsupported;
 use pct_str::PctStr;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use soup::prelude::*;
+use time::format_description::well_known::Rfc2822;
 use time::OffsetDateTime;
-use ureq::{Agent, AgentBuilder};
+use tracing::{debug, warn};
+
+pub mod description;
+mod details;
+pub mod echo360;
+mod editing;
+pub mod lti;
+pub mod naming;
+#[cfg(feature = "async")]
+mod nonblocking;
+pub mod request;
+pub mod snapshot;
+mod submission;
+
+pub use description::DescriptionFormat;
+pub use echo360::Recording;
+pub use lti::ResolvedLaunch;
+pub use naming::CourseNameTemplate;
+pub use request::RequestPolicy;
+pub use snapshot::Snapshot;
+
+use request::{RequestError, Requester};
 
 // TODO: Update all hardcoded URLs to use this.
 pub const BB_BASE_URL: &str = "https://learn.uq.edu.au";
 
-#[cfg(target_os = "linux")]
-pub fn create_link_file(hyperlink: &str) -> String {
-    format!(
-        "\
-[Desktop Entry]
-Encoding=UTF-8
-Type=Link
-URL={BB_BASE_URL}{hyperlink}
-Icon=text-html
-",
-    )
+/// Finds the value of a query parameter in a Blackboard URL.
+fn query_param(url: &str, name: &str) -> Option<String> {
+    Regex::new(&format!("[?&]{name}=([^&#]+)"))
+        .unwrap()
+        .captures(url)
+        .map(|captures| captures[1].into())
 }
 
-#[cfg(target_os = "linux")]
-pub const LINK_FILE_EXT: &str = "desktop";
-
-#[cfg(target_os = "macos")]
-pub fn create_link_file(hyperlink: &str) -> String {
-    format!("{{ URL = \"{BB_BASE_URL}{hyperlink}\"; }}")
-}
-
-#[cfg(target_os = "macos")]
-pub const LINK_FILE_EXT: &str = "webloc";
-
-#[cfg(target_os = "windows")]
-pub fn create_link_file(hyperlink: &str) -> String {
-    format!(
-        "\
-[InternetShortcut]
-URL=https://learn.uq.edu.au{hyperlink}
-"
-    )
-}
-
-#[cfg(target_os = "windows")]
-pub const LINK_FILE_EXT: &str = "url";
-
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Course {
-    short_name: String,
+    name: String,
     id: String,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct CourseItem {
     name: String,
     content: Option<CourseItemContent>,
+    #[serde(default)]
     description: Option<String>,
+    #[serde(default)]
     attachments: Vec<String>,
+    /// Blackboard's ID for the content item, the one that looks like _1234567_1
+    #[serde(default)]
+    id: Option<String>,
+    /// Blackboard's ID for the course the item is in, the one that looks like _123456_1
+    #[serde(default)]
+    course_id: Option<String>,
+    /// The folder page the item is listed on, or None if it's in the course menu
+    #[serde(default)]
+    listed_in: Option<String>,
+    /// Whether the item is hidden from students, which only staff can see
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    hidden: bool,
+    /// Whether the item has been made unavailable, so that it's listed but can't be opened
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    unavailable: bool,
+    /// When the item was made
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created: Option<SystemTime>,
+    /// When the item is due, if it's an assessment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<SystemTime>,
+    /// Whether this is a file that belongs to the content item with the ID rather than the item
+    /// itself, so that the item isn't changed through it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    attached: bool,
 }
 
 impl CourseItem {
+    /// Fills in what the API says about the item, and when it's due if it's an assessment.
+    fn add_details(&mut self, details: &details::Details, due: Option<SystemTime>) {
+        self.created = details.created;
+        self.hidden = details.hidden;
+        self.unavailable = details.unavailable;
+        self.due = due;
+    }
+
+    /// A file attached to the item, which is named by where its download redirects to.
+    fn attachment(&self, url: &str) -> Item {
+        Item::CourseItem(CourseItem {
+            name: "".into(),
+            content: Some(CourseItemContent::FileUrl(url.into())),
+            description: None,
+            attachments: vec![],
+            id: self.id.clone(),
+            course_id: self.course_id.clone(),
+            listed_in: self.listed_in.clone(),
+            hidden: self.hidden,
+            unavailable: self.unavailable,
+            created: self.created,
+            due: self.due,
+            attached: true,
+        })
+    }
+
+    /// Where the item is on Blackboard: its course and content IDs, along with the URL of the file
+    /// if it's one attached to the item. Items in the course menu have no content ID, so their
+    /// name and URL are used instead.
+    fn key(&self) -> String {
+        let url = match &self.content {
+            Some(
+                CourseItemContent::FileUrl(url)
+                | CourseItemContent::FolderUrl(url)
+                | CourseItemContent::Link(url),
+            ) => url.as_str(),
+            None => "",
+        };
+        match (&self.course_id, &self.id) {
+            (Some(course_id), Some(id)) if self.attached => format!("{course_id}/{id}/{url}"),
+            (Some(course_id), Some(id)) => format!("{course_id}/{id}"),
+            (course_id, _) => format!(
+                "{}/{}/{url}",
+                course_id.as_deref().unwrap_or_default(),
+                self.name
+            ),
+        }
+    }
+
+    /// The item's attachments, leaving out the file it's made of, since that's listed already.
     fn attachments_as_items(&self) -> Vec<Item> {
         self.attachments
             .iter()
-            .map(|attachment| {
-                Item::CourseItem(CourseItem {
-                    name: "".into(),
-                    content: Some(CourseItemContent::FileUrl(attachment.clone())),
-                    description: None,
-                    attachments: vec![],
-                })
-            })
+            .filter(|attachment| !self.is_made_of(attachment))
+            .map(|attachment| self.attachment(attachment))
             .collect()
     }
 
+    /// Whether the item is the file at the path, which older snapshots also list as an
+    /// attachment.
+    fn is_made_of(&self, path: &str) -> bool {
+        matches!(&self.content, Some(CourseItemContent::FileUrl(url)) if url == path)
+    }
+
+    /// Of the images and files embedded in the description, the ones that aren't also attached,
+    /// which are shown beside it.
+    fn unattached<'a>(&'a self, embedded: &'a [String]) -> impl Iterator<Item = &'a String> {
+        embedded
+            .iter()
+            .filter(|path| !self.attachments.contains(path) && !self.is_made_of(path))
+    }
+
     fn maybe_new_description_file(&self) -> Option<Item> {
-        self.description.as_ref().map(|description| {
-            Item::SynthesizedFile(SynthesizedFile {
-                name: self.name.clone(),
-                contents: description.clone(),
-            })
-        })
+        self.description
+            .is_some()
+            .then(|| Item::Description(self.clone()))
     }
 
-    fn maybe_new_link_file(&self) -> Option<Item> {
+    fn maybe_new_link(&self) -> Option<Item> {
         match &self.content {
             Some(CourseItemContent::Link(link)) if !self.attachments.is_empty() => {
-                Some(Item::SynthesizedFile(SynthesizedFile {
-                    name: format!("{}.{}", self.name, LINK_FILE_EXT),
-                    contents: create_link_file(link),
-                }))
+                Some(Item::new_link(&self.name, link))
+            }
+            _ => None,
+        }
+    }
+
+    /// The Blackboard path of the LTI launch of the course's lecture recordings on Echo360, if
+    /// the item links to them.
+    fn recordings_launch(&self) -> Option<&str> {
+        match &self.content {
+            Some(CourseItemContent::Link(url)) if echo360::is_recordings_link(&self.name, url) => {
+                description::blackboard_path(url)
             }
             _ => None,
         }
     }
 
-    fn get_blackboard_link(&self, parent: &Item) -> String {
+    /// The Blackboard path of the page that files are submitted to the assignment on, if the item
+    /// is an assignment.
+    fn assignment_url(&self) -> Option<&str> {
         match &self.content {
-            Some(CourseItemContent::FolderUrl(url)) => url.clone(),
-            Some(CourseItemContent::Link(_) | CourseItemContent::FileUrl(_)) | None => match parent
+            Some(CourseItemContent::Link(url))
+                if url.contains("/webapps/assignment/uploadAssignment") =>
             {
-                Item::Course(ref course) => format!(
-                    "https://learn.uq.edu.au/ultra/courses/{}/cl/outline",
-                    course.id
-                ),
-                Item::CourseItem(ref item) => match &item.content {
-                    Some(CourseItemContent::FolderUrl(url)) => url.clone(),
-                    Some(CourseItemContent::FileUrl(_))
-                    | Some(CourseItemContent::Link(_))
-                    | None => unreachable!(),
-                },
-                Item::SynthesizedDirectory(_) | Item::SynthesizedFile(_) => {
-                    unreachable!()
-                }
-            },
+                description::blackboard_path(url)
+            }
+            _ => None,
+        }
+    }
+
+    /// The page the item can be found on in Blackboard.
+    fn get_blackboard_link(&self) -> String {
+        match (&self.content, &self.listed_in, &self.course_id) {
+            (Some(CourseItemContent::FolderUrl(url)), _, _) | (_, Some(url), _) => url.clone(),
+            (_, None, Some(course_id)) => {
+                format!("https://learn.uq.edu.au/ultra/courses/{course_id}/cl/outline")
+            }
+            // Only items restored from a snapshot made before the folder was recorded
+            (_, None, None) => format!("{BB_BASE_URL}/ultra/course"),
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CourseItemContent {
     FileUrl(String),
     FolderUrl(String),
@@ -151,35 +245,106 @@ impl CourseItemContent {
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct SynthesizedFile {
+/// A link to a web page that isn't itself an item on Blackboard, such as the page an item is
+/// listed on.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SynthesizedLink {
     name: String,
-    contents: String,
+    url: String,
 }
 
-#[derive(Clone, Debug)]
+/// A file whose contents are read from the local machine whenever it's accessed, so that it stays
+/// up to date while mounted.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LocalFile {
+    name: String,
+    path: PathBuf,
+    /// Served in place of the file's contents until it's been created
+    placeholder: String,
+}
+
+impl LocalFile {
+    fn contents(&self) -> Vec<u8> {
+        std::fs::read(&self.path).unwrap_or_else(|_| self.placeholder.clone().into_bytes())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SynthesizedDirectory {
     name: String,
     contents: Vec<Item>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Item {
     Course(Course),
     CourseItem(CourseItem),
-    SynthesizedFile(SynthesizedFile),
+    /// The description of a content item that has contents of its own, shown beside them
+    Description(CourseItem),
     SynthesizedDirectory(SynthesizedDirectory),
+    LocalFile(LocalFile),
+    Link(SynthesizedLink),
+    /// The video of a lecture recording on Echo360
+    Recording(Recording),
+    /// The directory that files are copied into to submit them to an assignment
+    Submissions(CourseItem),
+    /// Blackboard's confirmation of the files submitted to an assignment
+    Receipt(CourseItem),
 }
 
 impl Item {
-    fn make_link_file(name: &str, link: &str) -> Item {
-        Item::SynthesizedFile(SynthesizedFile {
-            name: format!("{name}.{LINK_FILE_EXT}"),
-            contents: create_link_file(link),
+    /// A digest of where the item is on Blackboard, which stays the same however much else about
+    /// it changes. It's also what the item is kept under in the snapshot.
+    fn id(&self) -> String {
+        let key = match self {
+            Item::Course(course) => format!("course:{}", course.id),
+            Item::CourseItem(item) => format!("item:{}", item.key()),
+            Item::Description(item) => format!("description:{}", item.key()),
+            Item::Submissions(item) => format!("submissions:{}", item.key()),
+            Item::Receipt(item) => format!("receipt:{}", item.key()),
+            Item::Recording(recording) => format!("recording:{}", recording.page_url),
+            // Everything about these is made up by BlackboardFS, so none of it changes
+            Item::SynthesizedDirectory(_) | Item::LocalFile(_) | Item::Link(_) => {
+                serde_json::to_string(self).expect("items should always be serializable")
+            }
+        };
+        Sha256::digest(key)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    /// The Blackboard path of the LTI launch the item opens, if it opens an external tool.
+    fn launch_url(&self) -> Option<&str> {
+        match self {
+            Item::CourseItem(CourseItem {
+                content: Some(CourseItemContent::Link(url)),
+                ..
+            })
+            | Item::Link(SynthesizedLink { url, .. })
+                if lti::is_launch_url(url) =>
+            {
+                description::blackboard_path(url)
+            }
+            _ => None,
+        }
+    }
+
+    fn new_link(name: &str, url: &str) -> Item {
+        Item::Link(SynthesizedLink {
+            name: name.into(),
+            url: url.into(),
         })
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct Announcement {
+    /// The one that looks like _1234567_1
+    pub id: String,
+    pub title: String,
+}
+
 #[derive(Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct User {
@@ -197,6 +362,9 @@ pub struct CourseMembership {
     /// The one that looks like _1234587_1
     pub course_id: String,
     pub course: CourseMembershipDetails,
+    /// Like Student or Instructor
+    #[serde(default)]
+    pub course_role_id: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -218,13 +386,49 @@ pub struct CourseTerm {
     pub end_date: Option<OffsetDateTime>,
 }
 
-impl From<CourseMembership> for Course {
-    fn from(value: CourseMembership) -> Self {
-        Course {
-            short_name: value.course.short_name[..8].into(),
-            id: value.course_id,
+/// A course along with the names of the directories it should be placed in.
+type PlacedCourse = (Vec<String>, Course);
+
+/// A course's path within the filesystem, along with its item.
+pub type CourseLocation = (Vec<String>, Item);
+
+impl Course {
+    fn new(membership: CourseMembership, template: &CourseNameTemplate) -> PlacedCourse {
+        let mut path = template.render(&membership.course);
+        let name = path.pop().expect("templates should have at least one component");
+        let course = Course {
+            name,
+            id: membership.course_id,
+        };
+        (path, course)
+    }
+}
+
+/// Groups courses into nested directories according to the paths their names were rendered to.
+fn course_tree(courses: Vec<PlacedCourse>) -> Vec<Item> {
+    let mut items = vec![];
+    let mut directories: Vec<(String, Vec<PlacedCourse>)> = vec![];
+    for (mut path, course) in courses {
+        if path.is_empty() {
+            items.push(Item::Course(course));
+            continue;
+        }
+        let directory_name = path.remove(0);
+        match directories
+            .iter_mut()
+            .find(|(name, _)| *name == directory_name)
+        {
+            Some((_, contents)) => contents.push((path, course)),
+            None => directories.push((directory_name, vec![(path, course)])),
         }
     }
+    items.extend(directories.into_iter().map(|(name, contents)| {
+        Item::SynthesizedDirectory(SynthesizedDirectory {
+            name,
+            contents: course_tree(contents),
+        })
+    }));
+    items
 }
 
 #[derive(Clone, Debug)]
@@ -233,6 +437,8 @@ pub enum BbPage {
     CourseList { user_id: String },
     Course { id: String },
     Folder { url: String },
+    Api { path: String },
+    Launch { url: String },
 }
 
 impl BbPage {
@@ -243,169 +449,1338 @@ impl BbPage {
             Self::Course { id } => {
                 format!("/webapps/blackboard/execute/announcement?method=search&course_id={id}")
             }
-            Self::Folder { url } => {
+            Self::Folder { url } | Self::Launch { url } => {
                 url.clone()
             }
+            Self::Api { path } => path.clone(),
         };
         format!("{BB_BASE_URL}{path}")
     }
 }
 
+/// How long to serve the snapshot after failing to reach Blackboard before trying it again.
+const OFFLINE_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The most redirects and forms to follow when launching an external tool.
+const MAX_LAUNCH_STEPS: usize = 10;
+
+/// What a HEAD request for a file reveals about it.
+#[derive(Clone, Debug)]
+struct FileHead {
+    name: String,
+    /// Where the download ends up, without its query. Blackboard redirects every place a file is
+    /// attached to the same URL, so this identifies the file itself.
+    resolved_url: String,
+    revision: Revision,
+    mime_type: Option<String>,
+}
+
+/// Something that's only fetched once at a time, by whoever holds its lock, and is empty until
+/// it has been.
+type Fetched<T> = Arc<Mutex<Option<T>>>;
+
 pub struct BbScrapeClient {
     cookies: String,
-    agent: Agent,
+    requester: Requester,
+    /// Used instead of `requester` when the client is used through `AsyncBbClient`
+    #[cfg(feature = "async")]
+    async_requester: request::AsyncRequester,
     all_courses: bool,
-    // TODO: Consider a dashmap or similar
-    cache: Mutex<HashMap<CourseItem, Vec<u8>>>,
+    course_name_template: CourseNameTemplate,
+    description_format: DescriptionFormat,
+    /// The contents of each downloaded file, keyed by where its download resolves to, so that a
+    /// file attached in several places is only downloaded once. Each has its own lock, so that a
+    /// download doesn't hold up reading any other file.
+    cache: Mutex<HashMap<String, Fetched<Vec<u8>>>>,
+    /// What a HEAD request revealed about each file URL, cleared whenever its directory is
+    /// listed again. Each has its own lock, so that a file is only asked about once at a time
+    /// without holding up the others.
+    heads: Mutex<HashMap<String, Fetched<FileHead>>>,
+    /// Where each file URL's download resolves to, which is kept when its directory is listed
+    /// again, since a file that's replaced on Blackboard is attached under a new URL
+    content_keys: Mutex<HashMap<String, String>>,
+    /// Where each LTI launch ended up, or None if it couldn't be launched
+    launches: Mutex<HashMap<String, Option<ResolvedLaunch>>>,
+    /// The Echo360 session started by each LTI launch, which is reused until it expires
+    echo360_sessions: Mutex<HashMap<String, echo360::Session>>,
+    /// The size of each recording's video by its page URL, since videos aren't changed once
+    /// they're recorded and finding out takes a request
+    recording_sizes: Mutex<HashMap<String, usize>>,
+    /// What Blackboard confirmed about each submission to an assignment since mounting
+    receipts: Mutex<HashMap<CourseItem, String>>,
+    /// The images and files embedded in each item's description, so that it's only parsed once
+    /// each time the item is listed
+    embedded_files: Mutex<HashMap<CourseItem, Arc<[String]>>>,
+    /// Each item's rendered description, which names the files embedded in it by asking about
+    /// them, so it's only rendered again once the item is listed again
+    descriptions: Mutex<HashMap<CourseItem, String>>,
+    /// The courses whose content the user's role lets them edit
+    editable_courses: Mutex<HashSet<String>>,
+    /// Fetch the metadata of every file in a directory in parallel as soon as it's listed
+    prefetch_metadata: bool,
+    /// Shared so that the async client can write to it from the blocking thread pool
+    snapshot: Option<Arc<Snapshot>>,
+    /// Where the change report shown as WHATS_NEW.md at the root is read from
+    whats_new: Option<PathBuf>,
+    /// Never contact Blackboard, and only serve the snapshot
+    offline: bool,
+    /// Set after failing to reach Blackboard, so that we don't wait on a dead network for every
+    /// request
+    offline_until: Mutex<Option<Instant>>,
 }
 
 impl BbScrapeClient {
     pub fn new(cookies: String, all_courses: bool) -> Self {
-        let agent: Agent = AgentBuilder::new()
-            .timeout_read(Duration::from_secs(5))
-            .timeout_write(Duration::from_secs(5))
-            .build();
         Self {
             cookies,
-            agent,
+            requester: Requester::new(RequestPolicy::default()),
+            #[cfg(feature = "async")]
+            async_requester: request::AsyncRequester::new(RequestPolicy::default()),
             all_courses,
+            course_name_template: CourseNameTemplate::default(),
+            description_format: DescriptionFormat::default(),
             cache: Mutex::new(HashMap::new()),
+            heads: Mutex::new(HashMap::new()),
+            content_keys: Mutex::new(HashMap::new()),
+            launches: Mutex::new(HashMap::new()),
+            echo360_sessions: Mutex::new(HashMap::new()),
+            recording_sizes: Mutex::new(HashMap::new()),
+            receipts: Mutex::new(HashMap::new()),
+            embedded_files: Mutex::new(HashMap::new()),
+            descriptions: Mutex::new(HashMap::new()),
+            editable_courses: Mutex::new(HashSet::new()),
+            prefetch_metadata: false,
+            snapshot: None,
+            whats_new: None,
+            offline: false,
+            offline_until: Mutex::new(None),
+        }
+    }
+
+    pub fn with_request_policy(mut self, policy: RequestPolicy) -> Self {
+        #[cfg(feature = "async")]
+        {
+            self.async_requester = request::AsyncRequester::new(policy.clone());
+        }
+        self.requester = Requester::new(policy);
+        self
+    }
+
+    /// Whether to fetch the name and size of every file in a directory in parallel as soon as
+    /// it's listed, rather than one at a time as each is looked at. Listing the directory waits
+    /// for them, so this is off by default.
+    pub fn with_metadata_prefetch(mut self, prefetch: bool) -> Self {
+        self.prefetch_metadata = prefetch;
+        self
+    }
+
+    pub fn with_course_name_template(mut self, template: CourseNameTemplate) -> Self {
+        self.course_name_template = template;
+        self
+    }
+
+    pub fn with_description_format(mut self, format: DescriptionFormat) -> Self {
+        self.description_format = format;
+        self
+    }
+
+    /// Records everything fetched from Blackboard into `snapshot`, and serves from it when
+    /// Blackboard can't be reached.
+    pub fn with_snapshot(mut self, snapshot: Snapshot) -> Self {
+        self.snapshot = Some(Arc::new(snapshot));
+        self
+    }
+
+    /// Writes out whatever's been recorded in the snapshot since it was last written, which
+    /// should be done before exiting while the client may still be shared with other threads.
+    pub fn flush_snapshot(&self) {
+        if let Some(snapshot) = &self.snapshot {
+            snapshot.flush();
+        }
+    }
+
+    /// Shows the change report at `path` as WHATS_NEW.md at the root.
+    pub fn with_whats_new(mut self, path: PathBuf) -> Self {
+        self.whats_new = Some(path);
+        self
+    }
+
+    /// Serves exclusively from the snapshot without ever contacting Blackboard.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+            || self
+                .offline_until
+                .lock()
+                .unwrap()
+                .is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Fetches a value from Blackboard and records it in the snapshot, or restores it from the
+    /// snapshot if we're offline.
+    fn fetch_or_restore<T>(
+        &self,
+        fetch: impl FnOnce() -> Result<T, BbError>,
+        record: impl FnOnce(&Snapshot, &T),
+        restore: impl FnOnce(&Snapshot) -> Option<T>,
+    ) -> Result<T, BbError> {
+        let fetched = self.can_fetch().then(fetch);
+        self.record_or_restore(fetched, record, restore)
+    }
+
+    fn can_fetch(&self) -> bool {
+        match self.snapshot {
+            Some(_) => !self.is_offline(),
+            // Without a snapshot there's nothing to fall back on, so always try Blackboard
+            None => !self.offline,
+        }
+    }
+
+    /// Records a value fetched from Blackboard in the snapshot, or restores it from the snapshot
+    /// if it wasn't fetched or Blackboard couldn't be reached.
+    fn record_or_restore<T>(
+        &self,
+        fetched: Option<Result<T, BbError>>,
+        record: impl FnOnce(&Snapshot, &T),
+        restore: impl FnOnce(&Snapshot) -> Option<T>,
+    ) -> Result<T, BbError> {
+        let Some(snapshot) = &self.snapshot else {
+            return fetched.unwrap_or(Err(BbError::NotAvailableOffline));
+        };
+
+        match fetched {
+            Some(Ok(value)) => {
+                record(snapshot, &value);
+                return Ok(value);
+            }
+            Some(Err(err)) if err.is_network_error() => {
+                warn!("failed to reach Blackboard, serving from snapshot: {err}");
+                *self.offline_until.lock().unwrap() = Some(Instant::now() + OFFLINE_RETRY_INTERVAL);
+            }
+            Some(Err(err)) => return Err(err),
+            None => {}
+        }
+
+        restore(snapshot).ok_or(BbError::NotAvailableOffline)
+    }
+
+    fn get_page(&self, page: BbPage) -> Result<String, BbError> {
+        let url = page.url();
+        let (final_url, contents) = self
+            .requester
+            .call("GET", &url, &self.cookies, &[], |response| {
+                let final_url = response.get_url().to_owned();
+                Ok((final_url, response.into_string()?))
+            })
+            .map_err(|err| {
+                BbError::from_request(
+                    err,
+                    &url,
+                    |err| BbError::FailedToGetPage(page.clone(), err),
+                    |err| BbError::FailedToReadPageContents(page.clone(), err),
+                )
+            })?;
+        Self::check_session(&final_url)?;
+        Ok(contents)
+    }
+
+    /// Every request to Blackboard should check where it ended up, so that the login page isn't
+    /// mistaken for what was asked for.
+    pub(crate) fn check_session(final_url: &str) -> Result<(), BbError> {
+        // An expired session gets redirected to the UQ login page rather than being refused
+        if final_url.starts_with(BB_BASE_URL) {
+            Ok(())
+        } else {
+            Err(BbError::SessionExpired)
+        }
+    }
+
+    fn get_me(&self) -> Result<User, BbError> {
+        let json = self.get_page(BbPage::Me)?;
+        serde_json::from_str(&json).map_err(BbError::FailedToParseMe)
+    }
+
+    /// url should be from a CourseItemContent::FileUrl
+    fn head_file(&self, url: &str) -> Result<ureq::Response, BbError> {
+        let url = &format!("{}{}", BB_BASE_URL, url);
+        let response = self
+            .requester
+            .call("HEAD", url, &self.cookies, &[], Ok)
+            .map_err(|err| {
+                BbError::from_request(err, url, BbError::FailedToGetHeaders, |err| {
+                    BbError::FailedToGetHeaders(Box::new(err.into()))
+                })
+            })?;
+        Self::check_session(response.get_url())?;
+        Ok(response)
+    }
+
+    fn content_length<'a>(header: impl Fn(&str) -> Option<&'a str>) -> Result<usize, BbError> {
+        header("Content-Length")
+            .ok_or(BbError::MissingContentLengthHeader)?
+            .parse()
+            .map_err(BbError::InvalidContentLengthHeader)
+    }
+
+    /// Finds out a file's name and revision with a single HEAD request, which is only made once
+    /// until the file's directory is listed again.
+    ///
+    /// url should be from a CourseItemContent::FileUrl
+    fn file_head(&self, url: &str) -> Result<FileHead, BbError> {
+        let known = self.known_head(url);
+        let mut known = known.lock().unwrap();
+        if let Some(head) = &*known {
+            return Ok(head.clone());
+        }
+
+        let response = self.head_file(url)?;
+        let head = Self::parse_file_head(response.get_url(), |name| response.header(name))?;
+        *known = Some(head.clone());
+        Ok(head)
+    }
+
+    /// Where what's known about a file URL is kept, which is empty until it's been fetched.
+    fn known_head(&self, url: &str) -> Fetched<FileHead> {
+        self.heads
+            .lock()
+            .unwrap()
+            .entry(url.into())
+            .or_default()
+            .clone()
+    }
+
+    /// Where the contents stored under `key` are kept, which is empty until they've been
+    /// downloaded.
+    fn cached_contents(&self, key: &str) -> Fetched<Vec<u8>> {
+        self.cache
+            .lock()
+            .unwrap()
+            .entry(key.into())
+            .or_default()
+            .clone()
+    }
+
+    /// Reads a file's metadata from the URL a HEAD request for it ended up at and the headers
+    /// of the response.
+    fn parse_file_head<'a>(
+        final_url: &str,
+        header: impl Fn(&str) -> Option<&'a str>,
+    ) -> Result<FileHead, BbError> {
+        // The name comes from where the download redirects to
+        let resolved_url = final_url.split('?').next().unwrap();
+        #[allow(clippy::double_ended_iterator_last)]
+        let file_name = resolved_url.split('/').last().unwrap();
+        Ok(FileHead {
+            resolved_url: resolved_url.into(),
+            name: PctStr::new(file_name)
+                .map(PctStr::decode)
+                .unwrap_or(file_name.to_owned()),
+            revision: Revision {
+                size: Self::content_length(&header)?,
+                etag: header("ETag").map(Into::into),
+                last_modified: header("Last-Modified").map(Into::into),
+                validated_at: None,
+            },
+            mime_type: header("Content-Type")
+                .and_then(|content_type| content_type.split(';').next())
+                .map(|mime_type| mime_type.trim().into()),
+        })
+    }
+
+    /// Forgets what's known about the files in a freshly listed directory, so that changes to
+    /// them are picked up, and fetches their metadata again in parallel if metadata prefetching
+    /// is enabled.
+    fn refresh_file_heads(&self, items: &[Item]) {
+        let urls = self.forget_files(items);
+        if !self.prefetch_metadata || urls.is_empty() {
+            return;
+        }
+
+        // The requester limits how many of these actually run at once
+        let next = Mutex::new(urls.into_iter());
+        let workers = self.requester.max_concurrent_requests();
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let Some(url) = next.lock().unwrap().next() else {
+                        break;
+                    };
+                    // Errors will be hit again and reported when the file is looked at
+                    if let Err(err) = self.file_head(url) {
+                        debug!("failed to prefetch metadata of {url}: {err}");
+                    }
+                });
+            }
+        });
+    }
+
+    /// What a file's contents are cached under, which is where its download resolves to if that's
+    /// known, so that the same file attached in several places is only downloaded once. It's only
+    /// asked about the first time, since it's remembered along with the item from then on.
+    ///
+    /// url should be from a CourseItemContent::FileUrl
+    fn content_key(&self, item: &CourseItem, url: &str) -> String {
+        if let Some(key) = self.known_content_key(item, url) {
+            return key;
+        }
+        match self.file_head(url) {
+            Ok(head) => {
+                self.record_content_key(item, url, &head.resolved_url);
+                head.resolved_url
+            }
+            // The download will hit the same error again if there is one, so it's reported there
+            Err(_) => url.into(),
+        }
+    }
+
+    /// The content key of the file if it's been found before, since mounting or in the snapshot.
+    fn known_content_key(&self, item: &CourseItem, url: &str) -> Option<String> {
+        let known = self.content_keys.lock().unwrap().get(url).cloned();
+        known.or_else(|| {
+            let snapshot = self.snapshot.as_ref()?;
+            let key = snapshot.content_key(&Item::CourseItem(item.clone()))?;
+            (self.content_keys.lock().unwrap()).insert(url.into(), key.clone());
+            Some(key)
+        })
+    }
+
+    /// Remembers the content key of the file, along with the item in the snapshot so that its
+    /// contents are stored under it.
+    fn record_content_key(&self, item: &CourseItem, url: &str, key: &str) {
+        if let Some(snapshot) = &self.snapshot {
+            snapshot.record_content_key(&Item::CourseItem(item.clone()), key);
+        }
+        (self.content_keys.lock().unwrap()).insert(url.into(), key.into());
+    }
+
+    /// Fills in when the cached copy of the file was last validated, if it's of this revision.
+    fn validated_revision(&self, item: &Item, mut revision: Revision) -> Revision {
+        // Only a cached copy of this exact revision is fresh
+        revision.validated_at = self
+            .snapshot
+            .as_ref()
+            .and_then(|snapshot| snapshot.revision(item))
+            .filter(|cached| {
+                cached.size == revision.size
+                    && cached.etag == revision.etag
+                    && cached.last_modified == revision.last_modified
+            })
+            .and_then(|cached| cached.validated_at);
+        revision
+    }
+
+    /// Describes an item, given the revision of its contents if it's a file and what a HEAD
+    /// request revealed about it if one was made.
+    fn metadata(
+        &self,
+        item: &Item,
+        revision: Option<Revision>,
+        head: Option<FileHead>,
+    ) -> Metadata {
+        let url = self.get_url(item);
+        let size = revision.as_ref().map(|revision| revision.size);
+        let modified = revision
+            .and_then(|revision| revision.last_modified)
+            .and_then(|date| OffsetDateTime::parse(&date, &Rfc2822).ok())
+            .map(SystemTime::from);
+        let metadata = match item {
+            Item::Course(course) => Metadata {
+                url,
+                course_id: Some(course.id.clone()),
+                ..Default::default()
+            },
+            Item::CourseItem(course_item) => Metadata {
+                url,
+                content_id: course_item.id.clone(),
+                course_id: course_item.course_id.clone(),
+                description: course_item
+                    .description
+                    .as_ref()
+                    .map(|html| DescriptionFormat::Text.render(html, |_| None)),
+                mime_type: match &course_item.content {
+                    Some(CourseItemContent::FileUrl(_)) => head.and_then(|head| head.mime_type),
+                    None if course_item.description.is_some() => {
+                        Some(self.description_format.mime_type().into())
+                    }
+                    _ => None,
+                },
+                created: course_item.created,
+                hidden: course_item.hidden,
+                available: !course_item.unavailable,
+                due: course_item.due,
+                ..Default::default()
+            },
+            Item::Description(_) => Metadata {
+                url,
+                mime_type: Some(self.description_format.mime_type().into()),
+                ..Default::default()
+            },
+            Item::LocalFile(file) => Metadata {
+                url,
+                mime_type: file.name.ends_with(".md").then(|| "text/markdown".into()),
+                ..Default::default()
+            },
+            Item::Recording(recording) => Metadata {
+                url,
+                mime_type: recording.mime_type().map(Into::into),
+                ..Default::default()
+            },
+            Item::Receipt(_) => Metadata {
+                url,
+                mime_type: Some("text/plain".into()),
+                ..Default::default()
+            },
+            Item::SynthesizedDirectory(_) | Item::Link(_) | Item::Submissions(_) => Metadata {
+                url,
+                ..Default::default()
+            },
+        };
+        let lti_launch = item.launch_url().map(|url| LtiLaunch {
+            url: format!("{BB_BASE_URL}{url}"),
+            parameters: self
+                .known_launch(url)
+                .map(|launch| launch.parameters)
+                .unwrap_or_default(),
+        });
+        Metadata {
+            size,
+            modified,
+            lti_launch,
+            ..metadata
+        }
+    }
+
+    /// Renders the item's description, linking to the images and files embedded in it by the
+    /// names of the files they're shown as beside it.
+    fn render_description(&self, course_item: &CourseItem) -> String {
+        let Some(html) = &course_item.description else {
+            return String::new();
+        };
+        if let Some(rendered) = self.descriptions.lock().unwrap().get(course_item) {
+            return rendered.clone();
+        }
+        // Attachments are included, since they're shown beside the description as well
+        let embedded = self.embedded_files(course_item);
+        let rendered = self.description_format.render(html, |url| {
+            let path = description::blackboard_path(url)?;
+            if !embedded.iter().any(|embedded| embedded == path) {
+                return None;
+            }
+            self.get_name(&course_item.attachment(path)).ok()
+        });
+        (self.descriptions.lock().unwrap()).insert(course_item.clone(), rendered.clone());
+        rendered
+    }
+
+    /// The Blackboard paths of the images and files embedded in the item's description.
+    fn embedded_files(&self, course_item: &CourseItem) -> Arc<[String]> {
+        let Some(html) = &course_item.description else {
+            return Arc::new([]);
+        };
+        let mut embedded_files = self.embedded_files.lock().unwrap();
+        let embedded = embedded_files
+            .entry(course_item.clone())
+            .or_insert_with(|| description::embedded_files(html).into());
+        embedded.clone()
+    }
+
+    /// The directory at the root of the filesystem.
+    fn root(courses: Vec<PlacedCourse>) -> Item {
+        Item::SynthesizedDirectory(SynthesizedDirectory {
+            name: "root".into(),
+            contents: course_tree(courses),
+        })
+    }
+
+    /// Adds the files read from the local machine to the root, which aren't snapshotted.
+    fn add_local_files(&self, mut root: Item) -> Item {
+        if let (Some(path), Item::SynthesizedDirectory(root)) = (&self.whats_new, &mut root) {
+            root.contents.push(Item::LocalFile(LocalFile {
+                name: "WHATS_NEW.md".into(),
+                path: path.clone(),
+                placeholder: "Nothing has been checked for changes yet. Run `bbfs changes` to \
+                              check for new content on Blackboard.\n"
+                    .into(),
+            }));
+        }
+        root
+    }
+
+    /// Forgets the metadata and contents of the files among `items`, returning their URLs.
+    fn forget_files<'a>(&self, items: &'a [Item]) -> Vec<&'a str> {
+        // Descriptions name the files embedded in them, which are listed beside them. What was
+        // found in an earlier version of a listed item is forgotten along with it.
+        let described: Vec<_> = items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Description(course_item) | Item::CourseItem(course_item) => Some(course_item),
+                _ => None,
+            })
+            .collect();
+        let relisted = |cached: &CourseItem| {
+            (described.iter())
+                .any(|item| *item == cached || (item.id.is_some() && item.id == cached.id))
+        };
+        (self.descriptions.lock().unwrap()).retain(|course_item, _| !relisted(course_item));
+        (self.embedded_files.lock().unwrap()).retain(|course_item, _| !relisted(course_item));
+        let urls: Vec<_> = items
+            .iter()
+            .filter_map(|item| match item {
+                Item::CourseItem(CourseItem {
+                    content: Some(CourseItemContent::FileUrl(url)),
+                    ..
+                }) => Some(url.as_str()),
+                _ => None,
+            })
+            .collect();
+        // Their contents are cached under what they resolve to, if that's been found
+        let mut keys: HashSet<String> = {
+            let content_keys = self.content_keys.lock().unwrap();
+            let resolved = urls
+                .iter()
+                .filter_map(|url| content_keys.get(*url).cloned());
+            urls.iter()
+                .map(|url| url.to_string())
+                .chain(resolved)
+                .collect()
+        };
+        let forgotten: Vec<_> = {
+            let mut heads = self.heads.lock().unwrap();
+            urls.iter().filter_map(|url| heads.remove(*url)).collect()
+        };
+        // Waits for any HEAD request still being made, without holding up the others
+        for head in forgotten {
+            keys.extend(head.lock().unwrap().take().map(|head| head.resolved_url));
+        }
+        // Their contents will be revalidated against the snapshot when they're next read
+        self.cache
+            .lock()
+            .unwrap()
+            .retain(|key, _| !keys.contains(key));
+        urls
+    }
+
+    fn get_courses(&self) -> Result<Vec<PlacedCourse>, BbError> {
+        let user_id = self.get_me()?.id;
+        let json = self.get_page(BbPage::CourseList { user_id })?;
+        self.parse_courses(&json)
+    }
+
+    /// Reads the courses to show from the list of the user's course memberships.
+    fn parse_courses(&self, json: &str) -> Result<Vec<PlacedCourse>, BbError> {
+        let memberships_data: CourseMemberships =
+            serde_json::from_str(json).map_err(BbError::FailedToParseMemberships)?;
+        *self.editable_courses.lock().unwrap() = memberships_data
+            .results
+            .iter()
+            .filter(|membership| {
+                (membership.course_role_id.as_deref()).is_some_and(editing::can_edit)
+            })
+            .map(|membership| membership.course_id.clone())
+            .collect();
+        Ok(memberships_data
+            .results
+            .into_iter()
+            .filter(|course_entry| {
+                self.all_courses || {
+                    let now = OffsetDateTime::now_utc();
+                    if let Some(CourseTerm {
+                        start_date: Some(start),
+                        end_date: Some(end),
+                    }) = course_entry.course.term
+                    {
+                        start <= now && now <= end
+                    } else {
+                        false
+                    }
+                }
+            })
+            .map(|course_entry| Course::new(course_entry, &self.course_name_template))
+            .collect())
+    }
+
+    fn get_course_contents(&self, course: &Course) -> Result<Vec<CourseItem>, BbError> {
+        let html = self.get_page(BbPage::Course {
+            id: course.id.clone(),
+        })?;
+        Ok(Self::course_contents(course, &html))
+    }
+
+    fn course_contents(course: &Course, html: &str) -> Vec<CourseItem> {
+        Self::parse_course_sidebar(html)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|item| CourseItem {
+                course_id: Some(course.id.clone()),
+                ..item
+            })
+            .collect()
+    }
+
+    /// Lists the announcements of the course at `item`, which should be an `Item::Course`.
+    pub fn get_announcements(&self, item: &Item) -> Result<Vec<Announcement>, BbError> {
+        let Item::Course(course) = item else {
+            return Ok(vec![]);
+        };
+        let html = self.get_page(BbPage::Course {
+            id: course.id.clone(),
+        })?;
+        Ok(Self::parse_announcements(&html))
+    }
+
+    /// url should be from a CourseItemContent::Folder
+    fn get_directory_contents(&self, url: String) -> Result<Vec<CourseItem>, BbError> {
+        let html = self.get_page(BbPage::Folder { url: url.clone() })?;
+        let mut items = Self::directory_contents(&url, &html)?;
+        self.add_folder_details(&url, &mut items);
+        Ok(items)
+    }
+
+    /// Fills in what the API says about the items in a folder, which is only a nicety, so failing
+    /// to get it leaves them as they were.
+    fn add_folder_details(&self, url: &str, items: &mut [CourseItem]) {
+        let Some(page) = Self::folder_details_page(url) else {
+            return;
+        };
+        let details = self.get_page(page);
+        let details = match details.and_then(|json| Self::folder_details(&json)) {
+            Ok(details) => details,
+            Err(err) => {
+                debug!("failed to get the details of the items in {url}: {err}");
+                return;
+            }
+        };
+        for (item, details) in Self::with_details(items, &details) {
+            let due = details.grade_column_id.as_ref().and_then(|column_id| {
+                let page = Self::grade_column_page(item, column_id)?;
+                details::parse_due(&self.get_page(page).ok()?).ok()?
+            });
+            item.add_details(details, due);
+        }
+    }
+
+    /// The API's listing of the items in a folder, which says what the folder's page doesn't.
+    fn folder_details_page(url: &str) -> Option<BbPage> {
+        let path = details::children_path(
+            &query_param(url, "course_id")?,
+            &query_param(url, "content_id")?,
+        );
+        Some(BbPage::Api { path })
+    }
+
+    fn folder_details(json: &str) -> Result<HashMap<String, details::Details>, BbError> {
+        details::parse_children(json, SystemTime::now())
+            .map_err(|err| BbError::FailedToWebScrapeFolder(err.into()))
+    }
+
+    fn grade_column_page(item: &CourseItem, column_id: &str) -> Option<BbPage> {
+        let path = details::grade_column_path(item.course_id.as_ref()?, column_id);
+        Some(BbPage::Api { path })
+    }
+
+    /// Pairs each item with its details, leaving out any the API didn't describe.
+    fn with_details<'a, 'b>(
+        items: &'a mut [CourseItem],
+        details: &'b HashMap<String, details::Details>,
+    ) -> impl Iterator<Item = (&'a mut CourseItem, &'b details::Details)> {
+        items.iter_mut().filter_map(|item| {
+            let details = details.get(item.id.as_ref()?)?;
+            Some((item, details))
+        })
+    }
+
+    fn directory_contents(url: &str, html: &str) -> Result<Vec<CourseItem>, BbError> {
+        let course_id = query_param(url, "course_id");
+        Ok(Self::parse_folder_contents(html)?
+            .into_iter()
+            .map(|item| CourseItem {
+                course_id: course_id.clone(),
+                listed_in: Some(url.into()),
+                ..item
+            })
+            .collect())
+    }
+
+    fn get_course_item_size(&self, item: &CourseItem) -> Result<usize, BbError> {
+        Ok(match &item.content {
+            Some(content) => match content {
+                CourseItemContent::FileUrl(url) => self.file_head(url)?.revision.size,
+                CourseItemContent::FolderUrl(_) | CourseItemContent::Link(_) => {
+                    return Err(BbError::NotAFile(Box::new(Item::CourseItem(item.clone()))))
+                }
+            },
+            None => self.render_description(item).len(),
+        })
+    }
+
+    /// Downloads a file, or if a copy is already in the snapshot, only checks that it's still up
+    /// to date.
+    ///
+    /// url should be from a CourseItemContent::FileUrl
+    fn download_file(&self, item: &CourseItem, url: &str) -> Result<Vec<u8>, BbError> {
+        let url = &format!("{}{}", BB_BASE_URL, url);
+        let cached = self.cached_download(item);
+        let headers = Self::conditional_headers(&cached);
+
+        let (final_url, not_modified, bytes, etag, last_modified) = self
+            .requester
+            .call("GET", url, &self.cookies, &headers, |response| {
+                let final_url = response.get_url().to_owned();
+                let not_modified = response.status() == 304;
+                let etag = response.header("ETag").map(String::from);
+                let last_modified = response.header("Last-Modified").map(String::from);
+                let mut bytes = Vec::new();
+                response.into_reader().read_to_end(&mut bytes)?;
+                Ok((final_url, not_modified, bytes, etag, last_modified))
+            })
+            .map_err(|err| {
+                BbError::from_request(
+                    err,
+                    url,
+                    |err| BbError::FailedToGetContents(Box::new(item.clone()), err),
+                    |err| BbError::FailedToReadContents(Box::new(item.clone()), err),
+                )
+            })?;
+        Self::check_session(&final_url)?;
+
+        Ok(Self::finish_download(
+            self.snapshot.as_deref(),
+            item,
+            url,
+            cached,
+            not_modified,
+            bytes,
+            etag,
+            last_modified,
+        ))
+    }
+
+    /// The copy of a file in the snapshot and the revision it was downloaded at, if there is one.
+    fn cached_download(&self, item: &CourseItem) -> Option<(Vec<u8>, Revision)> {
+        let snapshot = self.snapshot.as_ref()?;
+        let item = Item::CourseItem(item.clone());
+        Some((snapshot.contents(&item)?, snapshot.revision(&item)?))
+    }
+
+    /// Headers that ask Blackboard to only send the file if it's changed since it was cached.
+    fn conditional_headers(cached: &Option<(Vec<u8>, Revision)>) -> Vec<(&'static str, &str)> {
+        let mut headers = vec![];
+        if let Some((_, revision)) = cached {
+            if let Some(etag) = &revision.etag {
+                headers.push(("If-None-Match", etag.as_str()));
+            }
+            if let Some(last_modified) = &revision.last_modified {
+                headers.push(("If-Modified-Since", last_modified.as_str()));
+            }
+        }
+        headers
+    }
+
+    /// Records a downloaded file in the snapshot, returning its contents, which are the cached
+    /// ones if Blackboard said they haven't changed.
+    #[allow(clippy::too_many_arguments)]
+    fn finish_download(
+        snapshot: Option<&Snapshot>,
+        item: &CourseItem,
+        url: &str,
+        cached: Option<(Vec<u8>, Revision)>,
+        not_modified: bool,
+        bytes: Vec<u8>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    ) -> Vec<u8> {
+        let Some(snapshot) = snapshot else {
+            return bytes;
+        };
+        let snapshot_item = Item::CourseItem(item.clone());
+        match cached {
+            Some((contents, _)) if not_modified => {
+                debug!("{url} hasn't changed since it was downloaded");
+                snapshot.record_validated(&snapshot_item);
+                contents
+            }
+            _ => {
+                snapshot.record_contents(
+                    &snapshot_item,
+                    &bytes,
+                    etag.as_deref(),
+                    last_modified.as_deref(),
+                );
+                bytes
+            }
+        }
+    }
+
+    fn get_course_item_contents(&self, item: &CourseItem) -> Result<Vec<u8>, BbError> {
+        Ok(match &item.content {
+            Some(content) => match content {
+                CourseItemContent::FileUrl(url) => {
+                    let key = self.content_key(item, url);
+                    let cached = self.cached_contents(&key);
+                    let mut cached = cached.lock().unwrap();
+                    if let Some(bytes) = &*cached {
+                        return Ok(bytes.clone());
+                    }
+                    // Stored under its key in the snapshot as well, which may have been found
+                    // through the same file attached elsewhere. It's only the URL if that
+                    // couldn't be found.
+                    if key != *url {
+                        self.record_content_key(item, url, &key);
+                    }
+                    let bytes = self.download_file(item, url)?;
+                    *cached = Some(bytes.clone());
+                    bytes
+                }
+                //CourseItemContent::FolderUrl(_) => unreachable!(),
+                CourseItemContent::FolderUrl(_) => vec![],
+                CourseItemContent::Link(_) => {
+                    return Err(BbError::NotAFile(Box::new(Item::CourseItem(item.clone()))))
+                }
+            },
+            None => self.render_description(item).into_bytes(),
+        })
+    }
+
+    /// Launches the external tool at `launch_url` the way a browser would, submitting the forms
+    /// and following the redirects along the way by hand so that the cookies the tool sets can be
+    /// collected. Returns where the launch ended up, along with those cookies.
+    fn launch(&self, launch_url: &str) -> Result<(ResolvedLaunch, String), BbError> {
+        let html = self.get_page(BbPage::Launch {
+            url: launch_url.into(),
+        })?;
+        let form = lti::parse_launch_form(&html, true).ok_or_else(|| {
+            BbError::FailedToLaunch(
+                launch_url.into(),
+                "Blackboard's launch page had no form to submit".into(),
+            )
+        })?;
+        let parameters = form.parameters();
+
+        // Each host the launch passes through only gets back the cookies it set
+        let mut jars: HashMap<String, Vec<(String, String)>> = HashMap::new();
+        let mut method = "POST";
+        let mut url = form.action;
+        let mut fields = form.fields;
+        for _ in 0..MAX_LAUNCH_STEPS {
+            let origin = lti::origin(&url).unwrap_or_default().to_string();
+            // A launch can pass back through Blackboard, such as to log in to an LTI 1.3 tool
+            let on_blackboard = origin == BB_BASE_URL;
+            let cookies = if on_blackboard {
+                self.cookies.clone()
+            } else {
+                lti::cookie_header(jars.get(&origin).map_or(&[], Vec::as_slice))
+            };
+            let form: Vec<(&str, &str)> = fields
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            let response = self
+                .requester
+                .send_without_redirects(method, &url, &cookies, &form)
+                .map_err(|err| Self::tool_error(err, &url))?;
+            if !on_blackboard {
+                let jar = jars.entry(origin.clone()).or_default();
+                lti::add_cookies(jar, response.all("Set-Cookie"));
+            }
+
+            if let Some(location) = response
+                .header("Location")
+                .filter(|_| (300..400).contains(&response.status()))
+            {
+                url = match lti::origin(location) {
+                    Some(_) => location.into(),
+                    None => format!("{origin}{location}"),
+                };
+                method = "GET";
+                fields = vec![];
+                continue;
+            }
+            if response.content_type() != "text/html" {
+                break;
+            }
+            let html = response
+                .into_string()
+                .map_err(|err| BbError::FailedToReadTool(url.clone(), err))?;
+            // Some pages along the way do nothing but submit another form
+            match lti::parse_launch_form(&html, false) {
+                Some(next) => {
+                    method = "POST";
+                    url = next.action;
+                    fields = next.fields;
+                }
+                None => break,
+            }
+        }
+
+        let jar = jars.remove(lti::origin(&url).unwrap_or_default());
+        let launch = ResolvedLaunch { url, parameters };
+        self.launches
+            .lock()
+            .unwrap()
+            .insert(launch_url.into(), Some(launch.clone()));
+        if let Some(snapshot) = &self.snapshot {
+            snapshot.record_launch(launch_url, &launch);
+        }
+        Ok((launch, lti::cookie_header(&jar.unwrap_or_default())))
+    }
+
+    /// Where the LTI launch at `launch_url` ends up, launching it if that isn't known yet. Links
+    /// open this directly, since the launch page only works when it's submitted as a form.
+    fn resolved_launch(&self, launch_url: &str) -> Option<ResolvedLaunch> {
+        if let Some(launch) = self.launches.lock().unwrap().get(launch_url) {
+            return launch.clone();
+        }
+        let launched = self.fetch_or_restore(
+            || Ok(self.launch(launch_url)?.0),
+            // It's recorded as it's launched
+            |_, _| {},
+            |snapshot| snapshot.launch(launch_url),
+        );
+        match launched {
+            Ok(launch) => Some(launch),
+            Err(err) => {
+                warn!("failed to launch {launch_url}: {err}");
+                // Launching again won't help unless Blackboard couldn't be reached
+                if !err.is_network_error() && !matches!(err, BbError::NotAvailableOffline) {
+                    self.launches
+                        .lock()
+                        .unwrap()
+                        .insert(launch_url.into(), None);
+                }
+                None
+            }
+        }
+    }
+
+    /// Where the LTI launch at `launch_url` has already been found to end up, without launching
+    /// it. Reading an item's metadata shouldn't submit anything to a tool.
+    fn known_launch(&self, launch_url: &str) -> Option<ResolvedLaunch> {
+        if let Some(launch) = self.launches.lock().unwrap().get(launch_url) {
+            return launch.clone();
+        }
+        self.snapshot.as_ref()?.launch(launch_url)
+    }
+
+    fn tool_error(err: RequestError, url: &str) -> BbError {
+        BbError::from_request(
+            err,
+            url,
+            |err| BbError::FailedToGetTool(url.into(), err),
+            |err| BbError::FailedToReadTool(url.into(), err),
+        )
+    }
+
+    /// Starts a session on Echo360 by launching it from `launch_url`, or reuses the one launched
+    /// before.
+    fn echo360_session(&self, launch_url: &str) -> Result<echo360::Session, BbError> {
+        if let Some(session) = self.echo360_sessions.lock().unwrap().get(launch_url) {
+            return Ok(session.clone());
+        }
+
+        let (launch, cookies) = self.launch(launch_url)?;
+        let section_id = echo360::section_id(&launch.url).ok_or_else(|| {
+            BbError::FailedToLaunch(
+                launch_url.into(),
+                format!("Echo360 opened {} rather than a course section", launch.url),
+            )
+        })?;
+        let session = echo360::Session {
+            origin: lti::origin(&launch.url).unwrap_or_default().into(),
+            section_id,
+            cookies,
+        };
+        self.echo360_sessions
+            .lock()
+            .unwrap()
+            .insert(launch_url.into(), session.clone());
+        Ok(session)
+    }
+
+    /// Makes a request to Echo360 with the session started by the launch at `launch_url`,
+    /// launching it again once if the session has expired.
+    fn echo360_call<T>(
+        &self,
+        launch_url: &str,
+        method: &str,
+        url: impl Fn(&echo360::Session) -> String,
+        headers: &[(&str, &str)],
+        read: impl Fn(ureq::Response) -> std::io::Result<T>,
+    ) -> Result<T, BbError> {
+        let mut relaunched = false;
+        loop {
+            let session = self.echo360_session(launch_url)?;
+            let url = url(&session);
+            match self
+                .requester
+                .call(method, &url, &session.cookies, headers, &read)
+            {
+                Err(RequestError::Request(err))
+                    if !relaunched && matches!(*err, ureq::Error::Status(401 | 403, _)) =>
+                {
+                    debug!("Echo360 session for {launch_url} expired, launching it again");
+                    self.echo360_sessions.lock().unwrap().remove(launch_url);
+                    relaunched = true;
+                }
+                result => return result.map_err(|err| Self::tool_error(err, &url)),
+            }
+        }
+    }
+
+    /// Lists the recordings in the Echo360 section launched from `launch_url`, each as a link to
+    /// the page it's watched on, and as a video where it can be downloaded.
+    fn get_recordings(&self, launch_url: &str) -> Result<Vec<Item>, BbError> {
+        let json = self.echo360_call(
+            launch_url,
+            "GET",
+            |session| format!("{}/section/{}/syllabus", session.origin, session.section_id),
+            &[],
+            |response| response.into_string(),
+        )?;
+        let origin = self.echo360_session(launch_url)?.origin;
+        let recordings = echo360::parse_syllabus(&json, &origin, launch_url)
+            .map_err(BbError::FailedToParseSyllabus)?;
+
+        let mut items = vec![];
+        for recording in recordings {
+            items.push(Item::new_link(&recording.name(), &recording.page_url));
+            if recording.media_url.is_some() {
+                items.push(Item::Recording(recording));
+            }
+        }
+        items.push(Item::new_link("Echo360", launch_url));
+        Ok(items)
+    }
+
+    fn media_url<'a>(&self, recording: &'a Recording) -> Result<&'a str, BbError> {
+        recording
+            .media_url
+            .as_deref()
+            .ok_or_else(|| BbError::NotAFile(Box::new(Item::Recording(recording.clone()))))
+    }
+
+    /// Finds out how large a recording is by asking for only its first byte, since the servers
+    /// that videos are kept on don't all answer HEAD requests.
+    fn get_recording_size(&self, recording: &Recording) -> Result<usize, BbError> {
+        let media_url = self.media_url(recording)?;
+        self.echo360_call(
+            &recording.launch_url,
+            "GET",
+            |_| media_url.into(),
+            &[("Range", "bytes=0-0")],
+            |response| {
+                // Content-Range looks like "bytes 0-0/12345"
+                let size = match response.header("Content-Range") {
+                    Some(range) => range.rsplit('/').next().map(str::parse),
+                    None if response.status() == 200 => {
+                        response.header("Content-Length").map(str::parse)
+                    }
+                    None => None,
+                };
+                Ok(size)
+            },
+        )?
+        .ok_or(BbError::MissingContentLengthHeader)?
+        .map_err(BbError::InvalidContentLengthHeader)
+    }
+
+    /// Downloads up to `size` bytes of a recording from `offset` on.
+    fn get_recording_range(
+        &self,
+        item: &Item,
+        recording: &Recording,
+        offset: u64,
+        size: usize,
+    ) -> Result<Vec<u8>, BbError> {
+        if !self.can_fetch() {
+            return Err(BbError::NotAvailableOffline);
+        }
+        let media_url = self.media_url(recording)?;
+        if size == 0 || offset >= self.get_size(item)? as u64 {
+            return Ok(vec![]);
+        }
+
+        let range = format!("bytes={offset}-{}", offset + size as u64 - 1);
+        self.echo360_call(
+            &recording.launch_url,
+            "GET",
+            |_| media_url.into(),
+            &[("Range", &range)],
+            |response| {
+                // A server that ignores the range sends the whole video, so skip to the part
+                // that was asked for
+                let skip = if response.status() == 206 { 0 } else { offset };
+                let mut reader = response.into_reader();
+                std::io::copy(&mut (&mut reader).take(skip), &mut std::io::sink())?;
+                let mut bytes = Vec::with_capacity(size);
+                reader.take(size as u64).read_to_end(&mut bytes)?;
+                Ok(bytes)
+            },
+        )
+    }
+
+    /// Lists where every course is, along with its item.
+    pub fn courses(&self) -> Result<Vec<CourseLocation>, BbError> {
+        fn find_courses(item: Item, path: Vec<String>, courses: &mut Vec<CourseLocation>) {
+            match item {
+                Item::Course(_) => courses.push((path, item)),
+                Item::SynthesizedDirectory(directory) => {
+                    for child in directory.contents {
+                        let name = match &child {
+                            Item::Course(course) => course.name.clone(),
+                            Item::SynthesizedDirectory(directory) => directory.name.clone(),
+                            _ => continue,
+                        };
+                        let mut child_path = path.clone();
+                        child_path.push(name);
+                        find_courses(child, child_path, courses);
+                    }
+                }
+                _ => {}
+            }
         }
-    }
-
-    fn get_page(&self, page: BbPage) -> Result<String, BbError> {
-        self.agent
-            .get(&page.url())
-            .set("Cookie", &self.cookies)
-            .call()
-            .map_err(|err| BbError::FailedToGetPage(page.clone(), Box::new(err)))?
-            .into_string()
-            .map_err(|err| BbError::FailedToReadPageContents(page, err))
-    }
 
-    fn get_me(&self) -> Result<User, BbError> {
-        let json = self.get_page(BbPage::Me)?;
-        serde_json::from_str(&json).map_err(BbError::FailedToParseMe)
+        let mut courses = vec![];
+        find_courses(self.get_root()?, vec![], &mut courses);
+        Ok(courses)
     }
 
-    fn get_download_file_name(&self, url: &str) -> Result<String, BbError> {
-        let url = &format!("{}{}", BB_BASE_URL, url);
-        let response = self
-            .agent
-            .head(url)
-            .set("Cookie", &self.cookies)
-            .call()
-            .map_err(|e| BbError::FailedToGetHeaders(Box::new(e)))?;
+    fn fetch_children(&self, item: &Item) -> Result<Vec<Item>, BbError> {
+        if self.get_type(item) != ItemType::Directory {
+            return Err(BbError::NotADirectory(Box::new(item.clone())));
+        }
 
-        let last_component: String = response.get_url().split('/').last().unwrap().into();
-        let file_name = last_component.split('?').next().unwrap();
-        Ok(PctStr::new(file_name)
-            .map(PctStr::decode)
-            .unwrap_or(file_name.to_owned()))
+        match item {
+            Item::Course(course) => Ok(Self::course_children(
+                course,
+                self.get_course_contents(course)?,
+            )),
+            Item::CourseItem(course_item) => {
+                if let Some(launch_url) = course_item.recordings_launch() {
+                    return self.get_recordings(launch_url);
+                }
+                let folder_contents = match &course_item.content {
+                    Some(CourseItemContent::FolderUrl(url)) => {
+                        self.get_directory_contents(url.clone())?
+                    }
+                    _ => vec![],
+                };
+                Ok(self.course_item_children(course_item, folder_contents))
+            }
+            Item::SynthesizedDirectory(directory) => Ok(directory.contents.clone()),
+            Item::Submissions(assignment) => Ok(self.submissions_children(assignment)),
+            Item::Description(_)
+            | Item::LocalFile(_)
+            | Item::Link(_)
+            | Item::Recording(_)
+            | Item::Receipt(_) => Err(BbError::NotADirectory(Box::new(item.clone()))),
+        }
     }
 
-    fn get_courses(&self) -> Result<Vec<Course>, BbError> {
-        let user_id = self.get_me()?.id;
-        let json = self.get_page(BbPage::CourseList { user_id })?;
-        let memberships_data: CourseMemberships =
-            serde_json::from_str(&json).map_err(BbError::FailedToParseMemberships)?;
-        Ok(memberships_data
-            .results
-            .into_iter()
-            .filter(|course_entry| {
-                self.all_courses || {
-                    let now = OffsetDateTime::now_utc();
-                    if let Some(CourseTerm {
-                        start_date: Some(start),
-                        end_date: Some(end),
-                    }) = course_entry.course.term
-                    {
-                        start <= now && now <= end
-                    } else {
-                        false
-                    }
-                }
+    /// The course and content IDs of an item to change, if its capabilities allow the change.
+    fn editable_ids<'a>(
+        &self,
+        item: &'a Item,
+        allowed: impl Fn(Capabilities) -> bool,
+    ) -> Result<(&'a str, &'a str), BbError> {
+        match item {
+            Item::CourseItem(CourseItem {
+                course_id: Some(course_id),
+                id: Some(id),
+                ..
             })
-            .map(|course_entry| course_entry.into())
-            .collect())
+            | Item::Description(CourseItem {
+                course_id: Some(course_id),
+                id: Some(id),
+                ..
+            }) if allowed(self.get_capabilities(item)) => match self.can_fetch() {
+                true => Ok((course_id, id)),
+                false => Err(BbError::NotAvailableOffline),
+            },
+            _ => Err(BbError::NotWritable(Box::new(item.clone()))),
+        }
     }
 
-    fn get_course_contents(&self, course: &Course) -> Result<Vec<CourseItem>, BbError> {
-        let html = self.get_page(BbPage::Course {
-            id: course.id.clone(),
-        })?;
-        Ok(Self::parse_course_sidebar(&html)
-            .unwrap_or_default()
-            .into_iter()
-            .collect())
+    /// Only the receipt is listed, since Blackboard doesn't offer the submitted files back.
+    fn submissions_children(&self, assignment: &CourseItem) -> Vec<Item> {
+        match self.receipts.lock().unwrap().contains_key(assignment) {
+            true => vec![Item::Receipt(assignment.clone())],
+            false => vec![],
+        }
     }
 
-    /// url should be from a CourseItemContent::Folder
-    fn get_directory_contents(&self, url: String) -> Result<Vec<CourseItem>, BbError> {
-        let html = self.get_page(BbPage::Folder { url })?;
-        Ok(Self::parse_folder_contents(&html)?.into_iter().collect())
+    fn receipt(&self, assignment: &CourseItem) -> String {
+        let receipts = self.receipts.lock().unwrap();
+        receipts.get(assignment).cloned().unwrap_or_default()
     }
 
-    fn get_course_item_size(&self, item: &CourseItem) -> Result<usize, BbError> {
-        Ok(match &item.content {
-            Some(content) => match content {
-                CourseItemContent::FileUrl(url) => {
-                    let url = &format!("{}{}", BB_BASE_URL, url);
-                    let response = self
-                        .agent
-                        .head(url)
-                        .set("Cookie", &self.cookies)
-                        .call()
-                        .map_err(|err| BbError::FailedToGetHeaders(Box::new(err)))?;
-                    response
-                        .header("Content-Length")
-                        .ok_or(BbError::MissingContentLengthHeader)?
-                        .parse()
-                        .map_err(BbError::InvalidContentLengthHeader)?
-                }
-                CourseItemContent::FolderUrl(_) => unreachable!(),
-                CourseItemContent::Link(url) => create_link_file(url).len(),
-            },
-            None => match &item.description {
-                Some(desc) => desc.len(),
-                None => 0,
-            },
-        })
+    fn course_children(course: &Course, contents: Vec<CourseItem>) -> Vec<Item> {
+        let link = format!("/ultra/courses/{}/cl/outline", course.id);
+
+        let mut items: Vec<_> = contents.into_iter().map(Item::CourseItem).collect();
+
+        items.push(Item::new_link("Blackboard", &link));
+
+        items
     }
 
-    fn get_course_item_contents(&self, item: &CourseItem) -> Result<Vec<u8>, BbError> {
-        let mut cache = self.cache.lock().unwrap();
-        if cache.contains_key(item) {
-            return Ok(cache[item].clone());
-        }
-        let bytes = match &item.content {
-            Some(content) => match content {
-                CourseItemContent::FileUrl(url) => {
-                    let url = &format!("{}{}", BB_BASE_URL, url);
-                    let response = self
-                        .agent
-                        .get(url)
-                        .set("Cookie", &self.cookies)
-                        .call()
-                        .map_err(|_| BbError::FailedToGetContents(item.clone(), None))?; // TODO: Reach inside and check the error type
-                    let mut bytes = Vec::new();
-                    response
-                        .into_reader()
-                        .read_to_end(&mut bytes)
-                        .map_err(|e| BbError::FailedToGetContents(item.clone(), Some(e)))?;
-                    bytes
-                }
-                //CourseItemContent::FolderUrl(_) => unreachable!(),
-                CourseItemContent::FolderUrl(_) => vec![],
-                CourseItemContent::Link(url) => create_link_file(url).bytes().collect(),
-            },
-            None => match &item.description {
-                Some(desc) => desc.bytes().collect(),
-                None => vec![],
-            },
+    /// `folder_contents` should be what's listed in the item's folder, if it's a folder
+    fn course_item_children(
+        &self,
+        course_item: &CourseItem,
+        folder_contents: Vec<CourseItem>,
+    ) -> Vec<Item> {
+        let mut items: Vec<Item> = match &course_item.content {
+            Some(CourseItemContent::Link(link)) => {
+                vec![Item::new_link(&course_item.name, link)]
+            }
+            Some(CourseItemContent::FileUrl(url)) => {
+                vec![Item::CourseItem(CourseItem {
+                    name: course_item.name.clone(),
+                    content: Some(CourseItemContent::FileUrl(url.clone())),
+                    description: None,
+                    attachments: vec![],
+                    id: course_item.id.clone(),
+                    course_id: course_item.course_id.clone(),
+                    listed_in: course_item.listed_in.clone(),
+                    hidden: course_item.hidden,
+                    unavailable: course_item.unavailable,
+                    created: course_item.created,
+                    due: course_item.due,
+                    attached: true,
+                })]
+            }
+            Some(CourseItemContent::FolderUrl(_)) => {
+                folder_contents.into_iter().map(Item::CourseItem).collect()
+            }
+            None => vec![],
         };
-        cache.insert(item.clone(), bytes.clone());
-        Ok(bytes)
+
+        items.append(&mut course_item.attachments_as_items());
+
+        items.extend(course_item.maybe_new_description_file());
+
+        let embedded = self.embedded_files(course_item);
+        items.extend(
+            course_item
+                .unattached(&embedded)
+                .map(|path| course_item.attachment(path)),
+        );
+
+        items.extend(course_item.maybe_new_link());
+
+        if course_item.assignment_url().is_some() {
+            items.push(Item::Submissions(course_item.clone()));
+        }
+
+        let link = course_item.get_blackboard_link();
+
+        items.push(Item::new_link("Blackboard", &link));
+
+        items
     }
 
     pub(crate) fn parse_course_sidebar(html: &str) -> anyhow::Result<Vec<CourseItem>> {
@@ -430,17 +1805,44 @@ impl BbScrapeClient {
                     false => CourseItemContent::Link(url),
                 };
                 let name = a.text();
+                let id = match &content {
+                    CourseItemContent::FolderUrl(url) => query_param(url, "content_id"),
+                    CourseItemContent::FileUrl(_) | CourseItemContent::Link(_) => None,
+                };
 
                 Ok(CourseItem {
                     name,
                     content: Some(content),
                     description: None,
                     attachments: vec![],
+                    id,
+                    course_id: None,
+                    listed_in: None,
+                    hidden: false,
+                    unavailable: false,
+                    created: None,
+                    due: None,
+                    attached: false,
                 })
             })
             .collect::<anyhow::Result<Vec<_>>>()
     }
 
+    pub(crate) fn parse_announcements(html: &str) -> Vec<Announcement> {
+        // Courses without any announcements don't have an announcementList at all
+        let Some(list) = Soup::new(html).attr("id", "announcementList").find() else {
+            return vec![];
+        };
+        list.tag("li")
+            .find_all()
+            .filter_map(|elem| {
+                let id = elem.get("id")?;
+                let title = elem.tag("h3").find()?.text().trim().to_owned();
+                Some(Announcement { id, title })
+            })
+            .collect()
+    }
+
     pub fn parse_folder_contents(html: &str) -> Result<Vec<CourseItem>, BbError> {
         Soup::new(html)
             .tag("ul")
@@ -451,7 +1853,7 @@ impl BbScrapeClient {
             )))?
             .children()
             .map(|elem| {
-                let attachments: Vec<_> = elem
+                let mut attachments: Vec<_> = elem
                     .attr("class", "attachments")
                     .find_all()
                     .flat_map(|elem| -> Vec<String> {
@@ -479,18 +1881,7 @@ impl BbScrapeClient {
                     .tag("div")
                     .attr("class", "vtbegenerated")
                     .find()
-                    .map(|elem| {
-                        // terrible code warning!
-                        let html = elem.display();
-                        let re = Regex::new("(?s)<script.*?>.*?</script>").unwrap();
-                        let html = re.replace_all(&html, "");
-                        let re = Regex::new("<br></br>").unwrap();
-                        let html = re.replace_all(&html, "\n");
-                        let re = Regex::new("<br>").unwrap();
-                        let html = re.replace_all(&html, "\n");
-                        Soup::new(&html).text().trim().into()
-                    })
-                    .filter(|s: &String| !s.is_empty());
+                    .and_then(|elem| description::clean(&elem.display()));
 
                 /*
                 let icon = elem
@@ -506,16 +1897,32 @@ impl BbScrapeClient {
                 let re = Regex::new("/").unwrap();
                 let title = re.replace_all(&title, r"-").into();
 
+                let id = elem
+                    .get("id")
+                    .and_then(|id| id.strip_prefix("contentListItem:").map(Into::into));
+
+                // An item that's only a file is shown as that file, rather than as a folder with
+                // the same file in it
+                let content = match (link, attachments.len()) {
+                    (None, 1) => attachments.pop(),
+                    (link, _) => link,
+                };
+
                 Ok(CourseItem {
                     name: title,
-                    content: if attachments.len() == 1 && link.is_none() {
-                        Some(attachments[0].clone())
-                    } else {
-                        link
-                    }
-                    .map(CourseItemContent::from_url),
+                    content: content.map(CourseItemContent::from_url),
                     description,
                     attachments,
+                    id,
+                    course_id: None,
+                    listed_in: None,
+                    // The page only says these in the user's language, so they're filled in
+                    // from the API instead
+                    hidden: false,
+                    unavailable: false,
+                    created: None,
+                    due: None,
+                    attached: false,
                 })
             })
             .filter(|r| r.is_ok())
@@ -528,32 +1935,176 @@ impl BbScrapeClient {
 pub enum BbError {
     FailedToGetPage(BbPage, Box<ureq::Error>),
     FailedToReadPageContents(BbPage, std::io::Error),
-    FailedToGetContents(CourseItem, Option<std::io::Error>),
+    FailedToGetContents(Box<CourseItem>, Box<ureq::Error>),
+    FailedToReadContents(Box<CourseItem>, std::io::Error),
     FailedToGetHeaders(Box<ureq::Error>),
     MissingContentLengthHeader,
     InvalidContentLengthHeader(ParseIntError),
     FailedToWebScrapeFolder(anyhow::Error),
     FailedToParseMemberships(serde_json::Error),
     FailedToParseMe(serde_json::Error),
-    NotAFile(Item),
+    NotAFile(Box<Item>),
+    NotADirectory(Box<Item>),
+    NotAvailableOffline,
+    /// The session cookie is no longer accepted, so bbfs needs to be restarted to log in again
+    SessionExpired,
+    /// Blackboard refused access to the URL
+    PermissionDenied(String),
+    /// Blackboard has nothing at the URL, e.g. because it was removed since it was listed
+    NotFound(String),
+    /// Blackboard asked us to slow down, optionally saying for how long
+    RateLimited(Option<Duration>),
+    /// The request to the URL took too long
+    Timeout(String),
+    /// Blackboard responded to the URL with an error status that has no more specific variant
+    UnexpectedStatus(String, u16),
+    /// The async request to the URL couldn't be made or its response couldn't be read
+    #[cfg(feature = "async")]
+    FailedToRequest(String, Box<reqwest::Error>),
+    /// The LTI launch at the URL didn't open the tool, for the given reason
+    FailedToLaunch(String, String),
+    /// The request to an external tool at the URL failed
+    FailedToGetTool(String, Box<ureq::Error>),
+    FailedToReadTool(String, std::io::Error),
+    FailedToParseSyllabus(String),
+    /// The file with the given name couldn't be submitted to an assignment, for the given reason
+    FailedToSubmit(String, String),
+    /// The item can't be changed that way
+    NotWritable(Box<Item>),
+    /// Nothing can be changed that way
+    Unsupported(Unsupported),
+    /// The request to change course content at the URL failed
+    FailedToEdit(String, Box<ureq::Error>),
+    FailedToReadEdit(String, std::io::Error),
+}
+
+impl BbError {
+    /// The error for an error status that has a more specific variant, if it does.
+    fn from_status(status: u16, retry_after: Option<Duration>, url: &str) -> Option<BbError> {
+        Some(match status {
+            401 => BbError::SessionExpired,
+            403 => BbError::PermissionDenied(url.into()),
+            404 | 410 => BbError::NotFound(url.into()),
+            429 => BbError::RateLimited(retry_after),
+            _ => return None,
+        })
+    }
+
+    /// Classifies a failed request to `url`, falling back to `request` or `read` for errors that
+    /// don't have a more specific variant.
+    fn from_request(
+        err: RequestError,
+        url: &str,
+        request: impl FnOnce(Box<ureq::Error>) -> BbError,
+        read: impl FnOnce(std::io::Error) -> BbError,
+    ) -> BbError {
+        let err = match err {
+            RequestError::Request(err) => err,
+            RequestError::Read(err) if err.kind() == std::io::ErrorKind::TimedOut => {
+                return BbError::Timeout(url.into())
+            }
+            RequestError::Read(err) => return read(err),
+        };
+        match &*err {
+            ureq::Error::Status(status, response) => {
+                let retry_after = response
+                    .header("Retry-After")
+                    .and_then(|seconds| seconds.parse().ok())
+                    .map(Duration::from_secs);
+                match BbError::from_status(*status, retry_after, url) {
+                    Some(specific) => specific,
+                    None => request(err),
+                }
+            }
+            ureq::Error::Transport(transport)
+                if transport
+                    .source()
+                    .and_then(|source| source.downcast_ref::<std::io::Error>())
+                    .is_some_and(|err| {
+                        matches!(
+                            err.kind(),
+                            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+                        )
+                    }) =>
+            {
+                BbError::Timeout(url.into())
+            }
+            ureq::Error::Transport(_) => request(err),
+        }
+    }
+
+    #[cfg(feature = "async")]
+    fn from_async_request(err: request::AsyncRequestError, url: &str) -> BbError {
+        use request::AsyncRequestError;
+        match err {
+            AsyncRequestError::Status(status, retry_after) => {
+                BbError::from_status(status, retry_after, url)
+                    .unwrap_or(BbError::UnexpectedStatus(url.into(), status))
+            }
+            AsyncRequestError::Http(err) if err.is_timeout() => BbError::Timeout(url.into()),
+            AsyncRequestError::Http(err) => BbError::FailedToRequest(url.into(), err),
+            AsyncRequestError::Timeout => BbError::Timeout(url.into()),
+        }
+    }
+
+    /// Whether the error was caused by failing to reach Blackboard, rather than by Blackboard
+    /// itself.
+    fn is_network_error(&self) -> bool {
+        match self {
+            BbError::FailedToGetPage(_, err)
+            | BbError::FailedToGetContents(_, err)
+            | BbError::FailedToGetHeaders(err)
+            | BbError::FailedToGetTool(_, err)
+            | BbError::FailedToEdit(_, err) => matches!(**err, ureq::Error::Transport(_)),
+            BbError::FailedToReadPageContents(_, _)
+            | BbError::FailedToReadContents(_, _)
+            | BbError::FailedToReadTool(_, _)
+            | BbError::FailedToReadEdit(_, _)
+            | BbError::Timeout(_) => true,
+            #[cfg(feature = "async")]
+            BbError::FailedToRequest(_, _) => true,
+            _ => false,
+        }
+    }
 }
 
 #[cfg(unix)]
 impl From<BbError> for nix::errno::Errno {
     fn from(error: BbError) -> nix::errno::Errno {
-        eprintln!("{:?}", error);
-        // TODO: Choose errnos more carefully
+        use nix::errno::Errno;
         match error {
-            BbError::FailedToGetPage(_, _)
-            | BbError::FailedToGetContents(_, _)
-            | BbError::FailedToGetHeaders(_) => nix::errno::Errno::ENETRESET,
+            BbError::FailedToGetPage(_, err)
+            | BbError::FailedToGetContents(_, err)
+            | BbError::FailedToGetHeaders(err)
+            | BbError::FailedToGetTool(_, err)
+            | BbError::FailedToEdit(_, err) => match *err {
+                ureq::Error::Status(_, _) => Errno::EIO,
+                ureq::Error::Transport(_) => Errno::ENETRESET,
+            },
             BbError::FailedToReadPageContents(_, _)
-            | BbError::MissingContentLengthHeader
+            | BbError::FailedToReadContents(_, _)
+            | BbError::FailedToReadTool(_, _)
+            | BbError::FailedToReadEdit(_, _) => Errno::ECONNRESET,
+            BbError::MissingContentLengthHeader
             | BbError::InvalidContentLengthHeader(_)
             | BbError::FailedToWebScrapeFolder(_)
             | BbError::FailedToParseMemberships(_)
-            | BbError::FailedToParseMe(_) => nix::errno::Errno::EIO,
-            BbError::NotAFile(_) => nix::errno::Errno::EISDIR,
+            | BbError::FailedToParseMe(_)
+            | BbError::FailedToLaunch(_, _)
+            | BbError::FailedToParseSyllabus(_)
+            | BbError::FailedToSubmit(_, _) => Errno::EIO,
+            BbError::NotAFile(_) => Errno::EISDIR,
+            BbError::NotADirectory(_) => Errno::ENOTDIR,
+            BbError::NotWritable(_) => Errno::EROFS,
+            BbError::Unsupported(_) => Errno::ENOTSUP,
+            BbError::NotAvailableOffline => Errno::ENETDOWN,
+            BbError::SessionExpired | BbError::PermissionDenied(_) => Errno::EACCES,
+            BbError::NotFound(_) => Errno::ENOENT,
+            BbError::RateLimited(_) => Errno::EAGAIN,
+            BbError::Timeout(_) => Errno::ETIMEDOUT,
+            BbError::UnexpectedStatus(_, _) => Errno::EIO,
+            #[cfg(feature = "async")]
+            BbError::FailedToRequest(_, _) => Errno::ENETRESET,
         }
     }
 }
@@ -562,133 +2113,476 @@ impl From<BbError> for nix::errno::Errno {
 impl From<BbError> for winapi::shared::ntdef::NTSTATUS {
     fn from(error: BbError) -> winapi::shared::ntdef::NTSTATUS {
         use winapi::shared::ntstatus;
-        eprintln!("{:?}", error);
         match error {
             BbError::FailedToGetPage(_, _)
             | BbError::FailedToGetContents(_, _)
-            | BbError::FailedToGetHeaders(_) => ntstatus::STATUS_UNEXPECTED_NETWORK_ERROR,
-            BbError::FailedToReadPageContents(_, _)
-            | BbError::MissingContentLengthHeader
+            | BbError::FailedToGetHeaders(_)
+            | BbError::FailedToReadPageContents(_, _)
+            | BbError::FailedToReadContents(_, _)
+            | BbError::FailedToGetTool(_, _)
+            | BbError::FailedToReadTool(_, _)
+            | BbError::FailedToEdit(_, _)
+            | BbError::FailedToReadEdit(_, _) => ntstatus::STATUS_UNEXPECTED_NETWORK_ERROR,
+            BbError::MissingContentLengthHeader
             | BbError::InvalidContentLengthHeader(_)
             | BbError::FailedToWebScrapeFolder(_)
             | BbError::FailedToParseMemberships(_)
-            | BbError::FailedToParseMe(_) => ntstatus::STATUS_FILE_NOT_AVAILABLE,
+            | BbError::FailedToParseMe(_)
+            | BbError::FailedToLaunch(_, _)
+            | BbError::FailedToParseSyllabus(_) => ntstatus::STATUS_FILE_NOT_AVAILABLE,
             BbError::NotAFile(_) => ntstatus::STATUS_FILE_IS_A_DIRECTORY,
+            BbError::NotADirectory(_) => ntstatus::STATUS_NOT_A_DIRECTORY,
+            BbError::NotWritable(_) => ntstatus::STATUS_MEDIA_WRITE_PROTECTED,
+            BbError::Unsupported(_) => ntstatus::STATUS_NOT_SUPPORTED,
+            BbError::FailedToSubmit(_, _) => ntstatus::STATUS_UNSUCCESSFUL,
+            BbError::NotAvailableOffline => ntstatus::STATUS_NETWORK_UNREACHABLE,
+            BbError::SessionExpired | BbError::PermissionDenied(_) => {
+                ntstatus::STATUS_ACCESS_DENIED
+            }
+            BbError::NotFound(_) => ntstatus::STATUS_OBJECT_NAME_NOT_FOUND,
+            BbError::RateLimited(_) => ntstatus::STATUS_RETRY,
+            BbError::Timeout(_) => ntstatus::STATUS_IO_TIMEOUT,
+            BbError::UnexpectedStatus(_, _) => ntstatus::STATUS_UNEXPECTED_NETWORK_ERROR,
+            #[cfg(feature = "async")]
+            BbError::FailedToRequest(_, _) => ntstatus::STATUS_UNEXPECTED_NETWORK_ERROR,
         }
     }
 }
 
 impl Display for BbError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // TODO: Possibly create nicer descriptions
-        f.write_fmt(format_args!("{:?}", self))
+        match self {
+            BbError::FailedToGetPage(page, err) => {
+                write!(f, "failed to get {}: {err}", page.url())
+            }
+            BbError::FailedToReadPageContents(page, err) => {
+                write!(f, "failed to read {}: {err}", page.url())
+            }
+            BbError::FailedToGetContents(item, err) => {
+                write!(f, "failed to download {}: {err}", item.name)
+            }
+            BbError::FailedToReadContents(item, err) => {
+                write!(f, "failed to read download of {}: {err}", item.name)
+            }
+            BbError::FailedToGetHeaders(err) => write!(f, "failed to get file details: {err}"),
+            BbError::MissingContentLengthHeader => {
+                write!(f, "Blackboard didn't say how large the file is")
+            }
+            BbError::InvalidContentLengthHeader(err) => {
+                write!(f, "Blackboard gave an invalid file size: {err}")
+            }
+            BbError::FailedToWebScrapeFolder(err) => {
+                write!(f, "failed to understand folder page: {err}")
+            }
+            BbError::FailedToParseMemberships(err) => {
+                write!(f, "failed to parse course memberships: {err}")
+            }
+            BbError::FailedToParseMe(err) => write!(f, "failed to parse user details: {err}"),
+            BbError::NotAFile(_) => write!(f, "expected a file but found a directory or link"),
+            BbError::NotADirectory(_) => write!(f, "expected a directory but found a file or link"),
+            BbError::NotWritable(_) => write!(f, "this can't be changed that way"),
+            BbError::Unsupported(err) => write!(f, "{err}"),
+            BbError::NotAvailableOffline => {
+                write!(f, "not available offline since it was never downloaded")
+            }
+            BbError::SessionExpired => write!(
+                f,
+                "the Blackboard session has expired, so bbfs needs to be restarted to log in again"
+            ),
+            BbError::PermissionDenied(url) => write!(f, "not allowed to access {url}"),
+            BbError::NotFound(url) => write!(f, "{url} doesn't exist on Blackboard"),
+            BbError::RateLimited(Some(retry_after)) => write!(
+                f,
+                "Blackboard is rate limiting requests, try again in {} seconds",
+                retry_after.as_secs()
+            ),
+            BbError::RateLimited(None) => write!(f, "Blackboard is rate limiting requests"),
+            BbError::Timeout(url) => write!(f, "timed out requesting {url}"),
+            BbError::UnexpectedStatus(url, status) => {
+                write!(f, "Blackboard responded to {url} with status code {status}")
+            }
+            #[cfg(feature = "async")]
+            BbError::FailedToRequest(url, err) => write!(f, "failed to request {url}: {err}"),
+            BbError::FailedToLaunch(url, reason) => write!(f, "failed to launch {url}: {reason}"),
+            BbError::FailedToGetTool(url, err) => write!(f, "failed to get {url}: {err}"),
+            BbError::FailedToReadTool(url, err) => write!(f, "failed to read {url}: {err}"),
+            BbError::FailedToParseSyllabus(err) => {
+                write!(
+                    f,
+                    "failed to understand the list of lecture recordings: {err}"
+                )
+            }
+            BbError::FailedToSubmit(name, reason) => {
+                write!(f, "failed to submit {name}: {reason}")
+            }
+            BbError::FailedToEdit(url, err) => write!(f, "failed to change {url}: {err}"),
+            BbError::FailedToReadEdit(url, err) => {
+                write!(f, "failed to read the response to changing {url}: {err}")
+            }
+        }
     }
 }
 
 impl Error for BbError {}
 
+impl From<Unsupported> for BbError {
+    fn from(err: Unsupported) -> Self {
+        BbError::Unsupported(err)
+    }
+}
+
 impl BbClient for BbScrapeClient {
     type Item = Item;
     type Error = BbError;
 
     fn get_root(&self) -> Result<Self::Item, BbError> {
-        Ok(Item::SynthesizedDirectory(SynthesizedDirectory {
-            name: "root".into(),
-            contents: self.get_courses()?.into_iter().map(Item::Course).collect(),
-        }))
+        let root = self.fetch_or_restore(
+            || Ok(Self::root(self.get_courses()?)),
+            Snapshot::record_root,
+            Snapshot::root,
+        )?;
+        Ok(self.add_local_files(root))
     }
 
-    fn get_children(&self, path: Vec<&Item>) -> Result<Vec<Item>, BbError> {
-        if let Some(item) = path.last() {
-            if self.get_type(item) != ItemType::Directory {
-                unreachable!();
-            }
-
-            match item {
-                Item::Course(course) => {
-                    let link = format!("/ultra/courses/{}/cl/outline", course.id);
-
-                    let mut items: Vec<_> = self
-                        .get_course_contents(course)?
-                        .into_iter()
-                        .map(Item::CourseItem)
-                        .collect();
-
-                    items.push(Item::make_link_file("Blackboard", &link));
+    fn get_children(&self, item: &Item) -> Result<Vec<Item>, BbError> {
+        match item {
+            Item::SynthesizedDirectory(directory) => Ok(directory.contents.clone()),
+            // Only ever known while mounted, so there's nothing to snapshot
+            Item::Submissions(assignment) => Ok(self.submissions_children(assignment)),
+            _ => self.fetch_or_restore(
+                || {
+                    let children = self.fetch_children(item)?;
+                    self.refresh_file_heads(&children);
+                    Ok(children)
+                },
+                |snapshot, children| snapshot.record_children(item, children),
+                |snapshot| snapshot.children(item),
+            ),
+        }
+    }
 
-                    Ok(items)
+    fn get_size(&self, item: &Item) -> Result<usize, BbError> {
+        match item {
+            Item::Course(_)
+            | Item::SynthesizedDirectory(_)
+            | Item::Link(_)
+            | Item::Submissions(_) => Err(BbError::NotAFile(Box::new(item.clone()))),
+            Item::Description(course_item) => Ok(self.render_description(course_item).len()),
+            Item::Receipt(assignment) => Ok(self.receipt(assignment).len()),
+            Item::LocalFile(file) => Ok(file.contents().len()),
+            Item::Recording(recording) => {
+                let sizes = &self.recording_sizes;
+                if let Some(size) = sizes.lock().unwrap().get(&recording.page_url) {
+                    return Ok(*size);
                 }
-                Item::CourseItem(course_item) => {
-                    let mut items: Vec<Item> = match &course_item.content {
-                        Some(CourseItemContent::Link(link)) => {
-                            vec![Item::make_link_file(&course_item.name, link)]
-                        }
-                        Some(CourseItemContent::FileUrl(url)) => {
-                            vec![Item::CourseItem(CourseItem {
-                                name: course_item.name.clone(),
-                                content: Some(CourseItemContent::FileUrl(url.clone())),
-                                description: None,
-                                attachments: vec![],
-                            })]
-                        }
-                        Some(CourseItemContent::FolderUrl(url)) => self
-                            .get_directory_contents(url.clone())?
-                            .into_iter()
-                            .map(Item::CourseItem)
-                            .collect(),
-                        None => vec![],
-                    };
+                let size = self.fetch_or_restore(
+                    || self.get_recording_size(recording),
+                    |snapshot, size| snapshot.record_size(item, *size),
+                    |snapshot| snapshot.size(item),
+                )?;
+                sizes.lock().unwrap().insert(recording.page_url.clone(), size);
+                Ok(size)
+            }
+            Item::CourseItem(course_item @ CourseItem {
+                content: Some(CourseItemContent::FileUrl(_)),
+                ..
+            }) => self.fetch_or_restore(
+                || self.get_course_item_size(course_item),
+                |snapshot, size| snapshot.record_size(item, *size),
+                |snapshot| snapshot.size(item),
+            ),
+            Item::CourseItem(course_item) => self.get_course_item_size(course_item),
+        }
+    }
+
+    fn get_contents(&self, item: &Item) -> Result<Vec<u8>, BbError> {
+        match item {
+            Item::Course(_)
+            | Item::SynthesizedDirectory(_)
+            | Item::Link(_)
+            | Item::Submissions(_) => Err(BbError::NotAFile(Box::new(item.clone()))),
+            Item::Description(course_item) => Ok(self.render_description(course_item).into_bytes()),
+            Item::Receipt(assignment) => Ok(self.receipt(assignment).into_bytes()),
+            Item::LocalFile(file) => Ok(file.contents()),
+            Item::Recording(recording) => {
+                self.get_recording_range(item, recording, 0, self.get_size(item)?)
+            }
+            Item::CourseItem(course_item @ CourseItem {
+                content: Some(CourseItemContent::FileUrl(_)),
+                ..
+            }) => self.fetch_or_restore(
+                || self.get_course_item_contents(course_item),
+                // The contents are recorded as they're downloaded
+                |_, _| {},
+                |snapshot| snapshot.contents(item),
+            ),
+            Item::CourseItem(course_item) => self.get_course_item_contents(course_item),
+        }
+    }
 
-                    items.append(&mut course_item.attachments_as_items());
+    fn get_revision(&self, item: &Item) -> Result<Revision, BbError> {
+        match item {
+            Item::CourseItem(CourseItem {
+                content: Some(CourseItemContent::FileUrl(url)),
+                ..
+            }) => self.fetch_or_restore(
+                || Ok(self.validated_revision(item, self.file_head(url)?.revision)),
+                |snapshot, revision| snapshot.record_revision(item, revision),
+                |snapshot| snapshot.revision(item),
+            ),
+            _ => Ok(Revision {
+                size: self.get_size(item)?,
+                ..Default::default()
+            }),
+        }
+    }
 
-                    items.extend(course_item.maybe_new_description_file());
+    fn get_url(&self, item: &Item) -> Option<String> {
+        let url = match item {
+            Item::Course(course) => format!("/ultra/courses/{}/cl/outline", course.id),
+            Item::CourseItem(CourseItem {
+                content:
+                    Some(
+                        CourseItemContent::FileUrl(url)
+                        | CourseItemContent::FolderUrl(url)
+                        | CourseItemContent::Link(url),
+                    ),
+                ..
+            }) => url.clone(),
+            Item::Link(link) => link.url.clone(),
+            Item::Recording(recording) => recording.page_url.clone(),
+            Item::Submissions(assignment) | Item::Receipt(assignment) => {
+                assignment.assignment_url()?.into()
+            }
+            Item::CourseItem(_)
+            | Item::Description(_)
+            | Item::SynthesizedDirectory(_)
+            | Item::LocalFile(_) => return None,
+        };
+        Some(if url.starts_with('/') {
+            format!("{BB_BASE_URL}{url}")
+        } else {
+            url
+        })
+    }
 
-                    items.extend(course_item.maybe_new_link_file());
+    fn get_link_target(&self, item: &Item) -> Option<String> {
+        match item.launch_url().and_then(|url| self.resolved_launch(url)) {
+            Some(launch) => Some(launch.url),
+            None => self.get_url(item),
+        }
+    }
 
-                    let link = course_item.get_blackboard_link(path[path.len() - 2]);
+    fn get_range(&self, item: &Item, offset: u64, size: usize) -> Result<Vec<u8>, BbError> {
+        match item {
+            Item::Recording(recording) => self.get_recording_range(item, recording, offset, size),
+            _ => Ok(bbfs_api::range_of(&self.get_contents(item)?, offset, size).to_vec()),
+        }
+    }
 
-                    items.push(Item::make_link_file("Blackboard", &link));
+    fn is_streamed(&self, item: &Item) -> bool {
+        matches!(item, Item::Recording(_))
+    }
 
-                    Ok(items)
+    fn get_capabilities(&self, item: &Item) -> Capabilities {
+        let course_item = match item {
+            Item::Submissions(_) => {
+                return Capabilities {
+                    create_file: true,
+                    ..Default::default()
                 }
-                Item::SynthesizedDirectory(directory) => Ok(directory.contents.clone()),
-                Item::SynthesizedFile(_) => unreachable!(),
             }
-        } else {
-            Ok(self.get_courses()?.into_iter().map(Item::Course).collect())
+            Item::CourseItem(course_item) | Item::Description(course_item) => course_item,
+            _ => return Capabilities::default(),
+        };
+        let editable = !course_item.attached
+            && course_item.id.is_some()
+            && (course_item.course_id.as_ref())
+                .is_some_and(|id| self.editable_courses.lock().unwrap().contains(id));
+        if !editable {
+            return Capabilities::default();
+        }
+        // Items in the course menu are only changed from the menu itself, and only items shown
+        // as directories are named by their titles alone
+        let listed = course_item.listed_in.is_some();
+        let folder = matches!(course_item.content, Some(CourseItemContent::FolderUrl(_)));
+        let ty = self.get_type(item);
+        match item {
+            Item::Description(_) => Capabilities {
+                set_description: listed,
+                ..Default::default()
+            },
+            _ => Capabilities {
+                create_file: folder,
+                create_folder: folder,
+                rename: listed && ty == ItemType::Directory,
+                delete: listed,
+                set_description: listed && ty == ItemType::File && course_item.content.is_none(),
+            },
         }
     }
 
-    fn get_size(&self, item: &Item) -> Result<usize, BbError> {
-        match item {
-            Item::Course(_) | Item::SynthesizedDirectory(_) => Err(BbError::NotAFile(item.clone())),
-            Item::SynthesizedFile(file) => Ok(file.contents.len()),
-            Item::CourseItem(course_item) => self.get_course_item_size(course_item),
+    fn create_file(&self, dir: &Item, name: &str, contents: &[u8]) -> Result<(), BbError> {
+        let Item::Submissions(assignment) = dir else {
+            let (course_id, folder_id) = self.editable_ids(dir, |can| can.create_file)?;
+            return editing::create_file(
+                &self.requester,
+                &self.cookies,
+                course_id,
+                folder_id,
+                name,
+                contents,
+            );
+        };
+        if !self.can_fetch() {
+            return Err(BbError::NotAvailableOffline);
         }
+        let url = assignment
+            .assignment_url()
+            .ok_or_else(|| BbError::NotWritable(Box::new(dir.clone())))?;
+        let confirmation = submission::submit(
+            &self.requester,
+            &format!("{BB_BASE_URL}{url}"),
+            &self.cookies,
+            name,
+            contents,
+        )?;
+        let submitted = OffsetDateTime::now_utc()
+            .format(&Rfc2822)
+            .unwrap_or_default();
+        let receipt = format!(
+            "{name} ({} bytes) submitted to {} at {submitted}\n{confirmation}\n\n",
+            contents.len(),
+            assignment.name
+        );
+        self.receipts
+            .lock()
+            .unwrap()
+            .entry(assignment.clone())
+            .or_default()
+            .push_str(&receipt);
+        Ok(())
     }
 
-    fn get_contents(&self, item: &Item) -> Result<Vec<u8>, BbError> {
+    fn create_folder(&self, dir: &Item, name: &str) -> Result<(), BbError> {
+        let (course_id, folder_id) = self.editable_ids(dir, |can| can.create_folder)?;
+        editing::create_folder(&self.requester, &self.cookies, course_id, folder_id, name)
+    }
+
+    fn rename(&self, item: &Item, name: &str) -> Result<(), BbError> {
+        let (course_id, content_id) = self.editable_ids(item, |can| can.rename)?;
+        let fields = serde_json::json!({ "title": name });
+        editing::update(
+            &self.requester,
+            &self.cookies,
+            course_id,
+            content_id,
+            fields,
+        )
+    }
+
+    fn delete(&self, item: &Item) -> Result<(), BbError> {
+        let (course_id, content_id) = self.editable_ids(item, |can| can.delete)?;
+        editing::delete(&self.requester, &self.cookies, course_id, content_id)
+    }
+
+    fn set_description(&self, item: &Item, description: &str) -> Result<(), BbError> {
+        let (course_id, content_id) = self.editable_ids(item, |can| can.set_description)?;
+        let body = match item {
+            Item::CourseItem(course_item) | Item::Description(course_item) => {
+                // Embedded files are linked to by the names they're listed beside it by
+                let embedded = self.embedded_files(course_item);
+                self.description_format.html_of(description, |path| {
+                    let url = embedded.iter().find(|url| {
+                        (self.get_name(&course_item.attachment(url))).is_ok_and(|name| name == path)
+                    })?;
+                    Some(format!("{BB_BASE_URL}{url}"))
+                })
+            }
+            _ => self.description_format.html_of(description, |_| None),
+        };
+        let fields = serde_json::json!({ "body": body });
+        editing::update(
+            &self.requester,
+            &self.cookies,
+            course_id,
+            content_id,
+            fields,
+        )
+    }
+
+    fn get_metadata(&self, item: &Item) -> Result<Metadata, BbError> {
+        let revision = match self.get_type(item) {
+            ItemType::File => Some(self.get_revision(item)?),
+            ItemType::Directory | ItemType::Link => None,
+        };
+        let head = match item {
+            // Not worth failing over, since everything else is already known
+            Item::CourseItem(CourseItem {
+                content: Some(CourseItemContent::FileUrl(file)),
+                ..
+            }) if !self.is_offline() => self.file_head(file).ok(),
+            _ => None,
+        };
+        Ok(self.metadata(item, revision, head))
+    }
+
+    fn get_content_key(&self, item: &Item) -> Option<String> {
         match item {
-            Item::Course(_) | Item::SynthesizedDirectory(_) => Err(BbError::NotAFile(item.clone())),
-            Item::SynthesizedFile(file) => Ok(file.contents.as_bytes().to_vec()),
-            Item::CourseItem(course_item) => self.get_course_item_contents(course_item),
+            Item::CourseItem(
+                course_item @ CourseItem {
+                    content: Some(CourseItemContent::FileUrl(url)),
+                    ..
+                },
+            ) => match self.is_offline() {
+                true => self.known_content_key(course_item, url),
+                false => Some(self.content_key(course_item, url)),
+            },
+            _ => None,
         }
     }
 
+    fn get_cache_size(&self) -> u64 {
+        // Anything still being downloaded isn't stored yet
+        let in_memory: usize = (self.cache.lock().unwrap().values())
+            .filter_map(|cached| Some(cached.try_lock().ok()?.as_ref()?.len()))
+            .sum();
+        in_memory as u64
+            + self
+                .snapshot
+                .as_ref()
+                .map_or(0, |snapshot| snapshot.contents_size())
+    }
+
+    fn get_id(&self, item: &Item) -> String {
+        item.id()
+    }
+
     fn get_type(&self, item: &Item) -> ItemType {
         match item {
-            Item::Course(_) | Item::SynthesizedDirectory(_) => ItemType::Directory,
-            Item::SynthesizedFile(_) => ItemType::File,
+            Item::Course(_) | Item::SynthesizedDirectory(_) | Item::Submissions(_) => {
+                ItemType::Directory
+            }
+            Item::Description(_) | Item::LocalFile(_) | Item::Recording(_) | Item::Receipt(_) => {
+                ItemType::File
+            }
+            Item::Link(_) => ItemType::Link,
             Item::CourseItem(course_item) => {
-                if !course_item.attachments.is_empty()
+                let attached = (course_item.attachments.iter())
+                    .any(|attachment| !course_item.is_made_of(attachment));
+                if attached
+                    || course_item.recordings_launch().is_some()
+                    || course_item.assignment_url().is_some()
                     || (course_item.description.is_some() && course_item.content.is_some())
+                    || (course_item.unattached(&self.embedded_files(course_item)))
+                        .next()
+                        .is_some()
                 {
                     ItemType::Directory
                 } else {
                     match course_item.content {
-                        Some(CourseItemContent::FileUrl(_)) | Some(CourseItemContent::Link(_)) => {
-                            ItemType::File
-                        }
+                        Some(CourseItemContent::FileUrl(_)) => ItemType::File,
+                        Some(CourseItemContent::Link(_)) => ItemType::Link,
                         Some(CourseItemContent::FolderUrl(_)) => ItemType::Directory,
                         None => ItemType::File,
                     }
@@ -699,24 +2593,37 @@ impl BbClient for BbScrapeClient {
 
     fn get_name(&self, item: &Item) -> Result<String, BbError> {
         Ok(match item {
-            Item::Course(course) => course.short_name.clone(),
+            Item::Course(course) => course.name.clone(),
             Item::SynthesizedDirectory(directory) => directory.name.clone(),
-            Item::SynthesizedFile(file) => file.name.clone(),
+            Item::Description(course_item) => {
+                format!(
+                    "{}.{}",
+                    course_item.name,
+                    self.description_format.extension()
+                )
+            }
+            Item::LocalFile(file) => file.name.clone(),
+            Item::Link(link) => link.name.clone(),
+            Item::Recording(recording) => recording.video_name(),
+            Item::Submissions(_) => "Submit".into(),
+            Item::Receipt(_) => "receipt.txt".into(),
             Item::CourseItem(course_item) => {
                 if self.get_type(item) == ItemType::Directory {
                     course_item.name.clone()
                 } else {
                     match &course_item.content {
-                        Some(CourseItemContent::FileUrl(file)) => {
-                            self.get_download_file_name(file)?
-                        }
-                        Some(CourseItemContent::FolderUrl(_)) => course_item.name.clone(),
-                        Some(CourseItemContent::Link(_)) => {
-                            format!("{}.{}", course_item.name, LINK_FILE_EXT)
+                        Some(CourseItemContent::FileUrl(file)) => self.fetch_or_restore(
+                            || Ok(self.file_head(file)?.name),
+                            |snapshot, name| snapshot.record_name(item, name),
+                            |snapshot| snapshot.name(item),
+                        )?,
+                        Some(CourseItemContent::FolderUrl(_) | CourseItemContent::Link(_)) => {
+                            course_item.name.clone()
                         }
                         None => {
                             if course_item.description.is_some() {
-                                format!("{}.txt", course_item.name)
+                                let extension = self.description_format.extension();
+                                format!("{}.{extension}", course_item.name)
                             } else {
                                 course_item.name.clone()
                             }
@@ -727,3 +2634,168 @@ impl BbClient for BbScrapeClient {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn course_item(name: &str, content: CourseItemContent) -> CourseItem {
+        CourseItem {
+            name: name.into(),
+            content: Some(content),
+            description: None,
+            attachments: vec![],
+            id: Some("_1234567_1".into()),
+            course_id: Some("_123456_1".into()),
+            listed_in: None,
+            hidden: false,
+            unavailable: false,
+            created: None,
+            due: None,
+            attached: false,
+        }
+    }
+
+    #[test]
+    fn embedded_files_are_listed_beside_the_description_unless_attached() {
+        let client = BbScrapeClient::new(String::new(), false);
+        let item = CourseItem {
+            description: Some(
+                r#"<p><img src="/bbcswebdav/diagram.png"><a href="/bbcswebdav/a.pdf">A</a></p>"#
+                    .into(),
+            ),
+            attachments: vec!["/bbcswebdav/a.pdf".into()],
+            ..course_item("Week 1", CourseItemContent::Link("/page".into()))
+        };
+        assert_eq!(
+            client.get_type(&Item::CourseItem(item.clone())),
+            ItemType::Directory
+        );
+
+        let files: Vec<_> = client
+            .course_item_children(&item, vec![])
+            .into_iter()
+            .filter_map(|child| match child {
+                Item::CourseItem(CourseItem {
+                    content: Some(CourseItemContent::FileUrl(url)),
+                    ..
+                }) => Some(url),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(files, ["/bbcswebdav/a.pdf", "/bbcswebdav/diagram.png"]);
+    }
+
+    #[test]
+    fn descriptions_are_parsed_again_once_relisted() {
+        let client = BbScrapeClient::new(String::new(), false);
+        let item = CourseItem {
+            description: Some(r#"<img src="/bbcswebdav/old.png">"#.into()),
+            ..course_item("Week 1", CourseItemContent::Link("/page".into()))
+        };
+        assert_eq!(*client.embedded_files(&item), ["/bbcswebdav/old.png"]);
+
+        let edited = CourseItem {
+            description: Some(r#"<img src="/bbcswebdav/new.png">"#.into()),
+            ..item
+        };
+        client.forget_files(&[Item::CourseItem(edited.clone())]);
+        assert!(client.embedded_files.lock().unwrap().is_empty());
+        assert_eq!(*client.embedded_files(&edited), ["/bbcswebdav/new.png"]);
+    }
+
+    #[test]
+    fn content_keys_are_remembered_once_found() {
+        let dir = std::env::temp_dir().join(format!("bbfs-content-keys-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let url = "/bbcswebdav/pid-1/xid-1";
+        let resolved = "https://learn.uq.edu.au/bbcswebdav/xid-1_1/notes.pdf";
+        let item = course_item("notes.pdf", CourseItemContent::FileUrl(url.into()));
+        let elsewhere = CourseItem {
+            id: Some("_7654321_1".into()),
+            ..item.clone()
+        };
+        {
+            let client = BbScrapeClient::new(String::new(), false)
+                .with_snapshot(Snapshot::open(&dir).unwrap());
+            client.record_content_key(&item, url, resolved);
+            // Found without asking Blackboard again, even where the file is attached elsewhere
+            for item in [&item, &elsewhere] {
+                let key = client.get_content_key(&Item::CourseItem(item.clone()));
+                assert_eq!(key.as_deref(), Some(resolved));
+            }
+        }
+
+        let offline = BbScrapeClient::new(String::new(), false)
+            .with_snapshot(Snapshot::open(&dir).unwrap())
+            .with_offline(true);
+        let key = offline.get_content_key(&Item::CourseItem(item));
+        assert_eq!(key.as_deref(), Some(resolved));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn launch_links_are_found_by_their_blackboard_path() {
+        let path = "/webapps/blackboard/execute/blti/launchLink?course_id=_123456_1";
+        let client = BbScrapeClient::new(String::new(), false);
+        for url in [path.to_string(), format!("{BB_BASE_URL}{path}")] {
+            let item = Item::new_link("Gradescope", &url);
+            assert_eq!(item.launch_url(), Some(path));
+            assert_eq!(client.get_url(&item), Some(format!("{BB_BASE_URL}{path}")));
+        }
+    }
+
+    #[test]
+    fn ids_stay_the_same_as_items_change() {
+        let item = course_item("Week 1", CourseItemContent::FolderUrl("/week1".into()));
+        let changed = CourseItem {
+            name: "Week 1 (updated)".into(),
+            description: Some("<p>Slides</p>".into()),
+            attachments: vec!["/bbcswebdav/slides.pdf".into()],
+            hidden: true,
+            ..item.clone()
+        };
+        assert_eq!(
+            Item::CourseItem(item.clone()).id(),
+            Item::CourseItem(changed).id()
+        );
+        assert_ne!(
+            Item::CourseItem(item.clone()).id(),
+            Item::Description(item).id()
+        );
+    }
+
+    #[test]
+    fn items_snapshotted_before_their_metadata_was_scraped_keep_their_ids() {
+        let snapshotted: Item = serde_json::from_str(
+            r#"{"CourseItem": {
+                "name": "Week 1",
+                "content": {"FolderUrl": "/week1"},
+                "id": "_1234567_1",
+                "course_id": "_123456_1"
+            }}"#,
+        )
+        .unwrap();
+        let scraped = CourseItem {
+            listed_in: Some("/course".into()),
+            unavailable: true,
+            ..course_item("Week 1", CourseItemContent::FolderUrl("/week1".into()))
+        };
+        assert_eq!(snapshotted.id(), Item::CourseItem(scraped).id());
+    }
+
+    #[test]
+    fn attached_files_are_told_apart_by_url() {
+        let item = course_item(
+            "Slides",
+            CourseItemContent::FileUrl("/bbcswebdav/a.pdf".into()),
+        );
+        let ids = [
+            Item::CourseItem(item.clone()).id(),
+            item.attachment("/bbcswebdav/a.pdf").id(),
+            item.attachment("/bbcswebdav/b.pdf").id(),
+        ];
+        assert_ne!(ids[0], ids[1]);
+        assert_ne!(ids[1], ids[2]);
+    }
+}
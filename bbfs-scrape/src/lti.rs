@@ -0,0 +1,98 @@
+//! LTI launches, which are how Blackboard opens external tools such as Gradescope, Ed Discussion
+//! and Echo360. Blackboard serves a page with a form that the browser submits as soon as it
+//! loads, and the tool then redirects to its own page for the course.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use soup::prelude::*;
+
+use crate::description;
+
+/// Where an LTI launch ended up, and what it told the tool.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResolvedLaunch {
+    /// The tool's page for the course, which can be opened directly
+    pub(crate) url: String,
+    /// The parameters sent to the tool, without the ones that only apply to a single launch
+    pub(crate) parameters: Vec<(String, String)>,
+}
+
+/// A form that a page submits as soon as it loads.
+pub(crate) struct LaunchForm {
+    pub(crate) action: String,
+    pub(crate) fields: Vec<(String, String)>,
+}
+
+impl LaunchForm {
+    /// The fields that describe the launch, leaving out the OAuth signature and what it signs
+    /// once, which would be useless to anyone reading them later.
+    pub(crate) fn parameters(&self) -> Vec<(String, String)> {
+        self.fields
+            .iter()
+            .filter(|(name, _)| {
+                !matches!(
+                    name.as_str(),
+                    "oauth_signature" | "oauth_nonce" | "oauth_timestamp"
+                )
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Whether a URL is a Blackboard page that launches an external tool.
+pub(crate) fn is_launch_url(url: &str) -> bool {
+    let launch = Regex::new("/webapps/blackboard/execute/(blti/)?launch").unwrap();
+    description::blackboard_path(url).is_some_and(|path| launch.is_match(path))
+}
+
+/// Reads the form that a launch page submits. Blackboard's own launch page is trusted to submit
+/// its form, but pages along the way are only followed if they say they submit theirs.
+pub(crate) fn parse_launch_form(html: &str, submitted: bool) -> Option<LaunchForm> {
+    if !submitted && !html.contains(".submit()") {
+        return None;
+    }
+    let soup = Soup::new(html);
+    let form = soup.tag("form").find_all().find(|form| {
+        form.get("action")
+            .is_some_and(|action| action.starts_with("http"))
+    })?;
+    let fields = form
+        .tag("input")
+        .find_all()
+        .filter_map(|input| Some((input.get("name")?, input.get("value").unwrap_or_default())))
+        .collect();
+    Some(LaunchForm {
+        action: form.get("action")?,
+        fields,
+    })
+}
+
+/// The scheme and host of a URL, like https://echo360.net.au.
+pub(crate) fn origin(url: &str) -> Option<&str> {
+    Regex::new("^https?://[^/?#]+")
+        .unwrap()
+        .find(url)
+        .map(|origin| origin.as_str())
+}
+
+/// Adds the cookies set by a response to `jar`, replacing any of the same name.
+pub(crate) fn add_cookies(jar: &mut Vec<(String, String)>, set_cookies: Vec<&str>) {
+    for set_cookie in set_cookies {
+        let pair = set_cookie.split(';').next().unwrap_or_default();
+        let Some((name, value)) = pair.split_once('=') else {
+            continue;
+        };
+        let name = name.trim();
+        jar.retain(|(existing, _)| existing != name);
+        jar.push((name.into(), value.trim().into()));
+    }
+}
+
+/// The Cookie header that sends every cookie in `jar`.
+pub(crate) fn cookie_header(jar: &[(String, String)]) -> String {
+    jar.iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
@@ -0,0 +1,267 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::CourseMembershipDetails;
+
+/// The number of characters of a course's `courseId` that make up its course code, e.g. the
+/// `CSSE2310` in `CSSE2310S_1234_12345`.
+const COURSE_CODE_LEN: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    /// The course code, e.g. CSSE2310
+    Code,
+    /// The full course ID, e.g. CSSE2310S_1234_12345
+    Id,
+    /// The human-readable course name
+    DisplayName,
+    /// The term the course runs in, derived from its start date, e.g. 2023-07
+    Term,
+    /// The year the course's term starts in
+    Year,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "code" => Self::Code,
+            "id" => Self::Id,
+            "display_name" => Self::DisplayName,
+            "term" => Self::Term,
+            "year" => Self::Year,
+            _ => return None,
+        })
+    }
+
+    fn render(&self, course: &CourseMembershipDetails) -> String {
+        let start_date = course.term.as_ref().and_then(|term| term.start_date);
+        match self {
+            Self::Code => course.short_name.chars().take(COURSE_CODE_LEN).collect(),
+            Self::Id => course.short_name.clone(),
+            Self::DisplayName => course.display_name.clone(),
+            Self::Term => start_date
+                .map(|date| format!("{}-{:02}", date.year(), u8::from(date.month())))
+                .unwrap_or_else(|| "No Term".into()),
+            Self::Year => start_date
+                .map(|date| date.year().to_string())
+                .unwrap_or_else(|| "No Term".into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+/// A template describing where each course is placed within the filesystem root.
+///
+/// Fields are written as `{field}` and `/` separates directories, so `{term}/{code}` groups
+/// courses into a directory per term. The supported fields are `code`, `id`, `display_name`,
+/// `term` and `year`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CourseNameTemplate {
+    components: Vec<Vec<Segment>>,
+}
+
+impl CourseNameTemplate {
+    /// Renders the path components of the course's directory, relative to the filesystem root.
+    pub fn render(&self, course: &CourseMembershipDetails) -> Vec<String> {
+        self.components
+            .iter()
+            .map(|segments| {
+                let name: String = segments
+                    .iter()
+                    .map(|segment| match segment {
+                        Segment::Literal(literal) => literal.clone(),
+                        // Replace / with - so that field values can't introduce extra directories
+                        Segment::Field(field) => field.render(course).replace('/', "-"),
+                    })
+                    .collect();
+                let name = name.trim();
+                if name.is_empty() || name == "." || name == ".." {
+                    course.short_name.replace('/', "-")
+                } else {
+                    name.into()
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for CourseNameTemplate {
+    fn default() -> Self {
+        Self {
+            components: vec![vec![Segment::Field(Field::Code)]],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    EmptyComponent,
+    UnknownField(String),
+    UnclosedField,
+    UnmatchedBrace,
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EmptyComponent => write!(f, "template contains an empty path component"),
+            Self::UnknownField(name) => write!(
+                f,
+                "unknown field {{{name}}} (expected one of {{code}}, {{id}}, {{display_name}}, \
+                 {{term}} or {{year}})"
+            ),
+            Self::UnclosedField => write!(f, "template contains an unclosed {{"),
+            Self::UnmatchedBrace => write!(f, "template contains an unmatched }}"),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+impl FromStr for CourseNameTemplate {
+    type Err = TemplateError;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let components = template
+            .split('/')
+            .map(|component| {
+                let mut segments = vec![];
+                let mut rest = component;
+                while !rest.is_empty() {
+                    match rest.find(['{', '}']) {
+                        Some(start) if rest[start..].starts_with('}') => {
+                            return Err(TemplateError::UnmatchedBrace)
+                        }
+                        Some(start) => {
+                            let end = rest[start..]
+                                .find('}')
+                                .ok_or(TemplateError::UnclosedField)?
+                                + start;
+                            let name = &rest[start + 1..end];
+                            let field = Field::from_name(name)
+                                .ok_or_else(|| TemplateError::UnknownField(name.into()))?;
+                            if start > 0 {
+                                segments.push(Segment::Literal(rest[..start].into()));
+                            }
+                            segments.push(Segment::Field(field));
+                            rest = &rest[end + 1..];
+                        }
+                        None => {
+                            segments.push(Segment::Literal(rest.into()));
+                            rest = "";
+                        }
+                    }
+                }
+                if segments.is_empty() {
+                    Err(TemplateError::EmptyComponent)
+                } else {
+                    Ok(segments)
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { components })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn course(term_start: Option<&str>) -> CourseMembershipDetails {
+        serde_json::from_value(serde_json::json!({
+            "courseId": "CSSE2310S_7360_62015",
+            "displayName": "Computer Systems Principles and Programming",
+            "term": term_start.map(|start| {
+                serde_json::json!({ "startDate": start, "endDate": null })
+            }),
+        }))
+        .unwrap()
+    }
+
+    fn render(template: &str, course: &CourseMembershipDetails) -> Vec<String> {
+        template
+            .parse::<CourseNameTemplate>()
+            .unwrap()
+            .render(course)
+    }
+
+    #[test]
+    fn default_is_the_course_code() {
+        let course = course(None);
+        assert_eq!(CourseNameTemplate::default().render(&course), ["CSSE2310"]);
+        assert_eq!(render("{code}", &course), ["CSSE2310"]);
+    }
+
+    #[test]
+    fn renders_every_field() {
+        let course = course(Some("2023-07-24T00:00:00.000Z"));
+        assert_eq!(
+            render("{code} {id} {display_name} {term} {year}", &course),
+            [
+                "CSSE2310 CSSE2310S_7360_62015 Computer Systems Principles and Programming \
+              2023-07 2023"
+            ]
+        );
+    }
+
+    #[test]
+    fn fields_without_a_term_say_so() {
+        let course = course(None);
+        assert_eq!(render("{term}", &course), ["No Term"]);
+        assert_eq!(render("{year}", &course), ["No Term"]);
+    }
+
+    #[test]
+    fn slashes_separate_directories() {
+        let course = course(Some("2023-07-24T00:00:00.000Z"));
+        assert_eq!(
+            render("{year}/Semester {term}/{code} - {display_name}", &course),
+            [
+                "2023",
+                "Semester 2023-07",
+                "CSSE2310 - Computer Systems Principles and Programming"
+            ]
+        );
+    }
+
+    #[test]
+    fn field_values_cant_add_directories() {
+        let mut course = course(None);
+        course.display_name = "Networks/Security".into();
+        assert_eq!(render("{display_name}", &course), ["Networks-Security"]);
+    }
+
+    #[test]
+    fn empty_and_dot_names_fall_back_to_the_id() {
+        let mut course = course(None);
+        course.display_name = " ".into();
+        assert_eq!(render("{display_name}", &course), ["CSSE2310S_7360_62015"]);
+        course.display_name = "..".into();
+        assert_eq!(render("{display_name}", &course), ["CSSE2310S_7360_62015"]);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(matches!(
+            "{code}/{semester}".parse::<CourseNameTemplate>(),
+            Err(TemplateError::UnknownField(name)) if name == "semester"
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        let parse = |template: &str| template.parse::<CourseNameTemplate>();
+        assert!(matches!(parse("{code"), Err(TemplateError::UnclosedField)));
+        assert!(matches!(parse("code}"), Err(TemplateError::UnmatchedBrace)));
+        assert!(matches!(
+            parse("{code}//{id}"),
+            Err(TemplateError::EmptyComponent)
+        ));
+        assert!(matches!(parse(""), Err(TemplateError::EmptyComponent)));
+    }
+}
@@ -0,0 +1,463 @@
+use std::future::Future;
+use std::io;
+
+use async_trait::async_trait;
+use bbfs_api::{AsyncBbClient, BbClient, Capabilities, ItemType, Metadata, Revision};
+use reqwest::Method;
+use tracing::debug;
+
+use crate::{
+    details, BbError, BbPage, BbScrapeClient, CourseItem, CourseItemContent, FileHead, Item,
+    PlacedCourse, Snapshot, User, BB_BASE_URL,
+};
+
+impl BbScrapeClient {
+    /// Like `fetch_or_restore`, but records what was fetched on the blocking thread pool, since
+    /// writing the snapshot can wait on the disk.
+    async fn fetch_or_restore_async<T: Send + 'static>(
+        &self,
+        fetch: impl Future<Output = Result<T, BbError>>,
+        record: impl FnOnce(&Snapshot, &T) + Send + 'static,
+        restore: impl FnOnce(&Snapshot) -> Option<T>,
+    ) -> Result<T, BbError> {
+        let fetched = match (self.can_fetch(), self.snapshot.clone()) {
+            (true, Some(snapshot)) => match fetch.await {
+                Ok(value) => Some(Ok(Self::in_background(move || {
+                    record(&snapshot, &value);
+                    value
+                })
+                .await)),
+                Err(err) => Some(Err(err)),
+            },
+            (true, None) => Some(fetch.await),
+            (false, _) => None,
+        };
+        // Already recorded above
+        self.record_or_restore(fetched, |_, _| {}, restore)
+    }
+
+    /// Runs blocking work, such as writing to the snapshot, without holding up other tasks.
+    async fn in_background<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> T {
+        tokio::task::spawn_blocking(work)
+            .await
+            .expect("blocking snapshot work shouldn't panic")
+    }
+
+    /// Answers the way the blocking client does, letting the runtime move the other tasks off
+    /// this worker thread while its requests are made. It borrows the client, so unlike the
+    /// snapshot work it can't be handed to the blocking thread pool.
+    fn blocking<T>(work: impl FnOnce() -> T) -> T {
+        tokio::task::block_in_place(work)
+    }
+
+    async fn get_page_async(&self, page: BbPage) -> Result<String, BbError> {
+        let url = page.url();
+        let response = self
+            .async_requester
+            .call(Method::GET, &url, &self.cookies, &[])
+            .await
+            .map_err(|err| BbError::from_async_request(err, &url))?;
+        Self::check_session(&response.url)?;
+        String::from_utf8(response.body).map_err(|err| {
+            BbError::FailedToReadPageContents(page, io::Error::new(io::ErrorKind::InvalidData, err))
+        })
+    }
+
+    async fn get_courses_async(&self) -> Result<Vec<PlacedCourse>, BbError> {
+        let json = self.get_page_async(BbPage::Me).await?;
+        let user: User = serde_json::from_str(&json).map_err(BbError::FailedToParseMe)?;
+        let json = self
+            .get_page_async(BbPage::CourseList { user_id: user.id })
+            .await?;
+        self.parse_courses(&json)
+    }
+
+    /// url should be from a CourseItemContent::FileUrl
+    async fn file_head_async(&self, url: &str) -> Result<FileHead, BbError> {
+        let known = self.known_head(url);
+        if let Some(head) = known.lock().unwrap().clone() {
+            return Ok(head);
+        }
+
+        let full_url = format!("{BB_BASE_URL}{url}");
+        let response = self
+            .async_requester
+            .call(Method::HEAD, &full_url, &self.cookies, &[])
+            .await
+            .map_err(|err| BbError::from_async_request(err, &full_url))?;
+        Self::check_session(&response.url)?;
+        let head = Self::parse_file_head(&response.url, |name| response.header(name))?;
+        // The lock can't be held while waiting on the request, so whichever finishes first wins
+        let head = known.lock().unwrap().get_or_insert(head).clone();
+        Ok(head)
+    }
+
+    /// Fetches the metadata of every file in a freshly listed directory at once, leaving the
+    /// requester to limit how many of the requests are in flight.
+    async fn refresh_file_heads_async(&self, items: &[Item]) {
+        let urls = self.forget_files(items);
+        if !self.prefetch_metadata {
+            return;
+        }
+        futures::future::join_all(urls.into_iter().map(|url| async move {
+            // Errors will be hit again and reported when the file is looked at
+            if let Err(err) = self.file_head_async(url).await {
+                debug!("failed to prefetch metadata of {url}: {err}");
+            }
+        }))
+        .await;
+    }
+
+    async fn fetch_children_async(&self, item: &Item) -> Result<Vec<Item>, BbError> {
+        match item {
+            Item::Course(course) => {
+                let html = self
+                    .get_page_async(BbPage::Course {
+                        id: course.id.clone(),
+                    })
+                    .await?;
+                Ok(Self::course_children(
+                    course,
+                    Self::course_contents(course, &html),
+                ))
+            }
+            Item::CourseItem(course_item) if course_item.recordings_launch().is_some() => {
+                Self::blocking(|| self.fetch_children(item))
+            }
+            Item::CourseItem(course_item)
+                if BbClient::get_type(self, item) == ItemType::Directory =>
+            {
+                let folder_contents = match &course_item.content {
+                    Some(CourseItemContent::FolderUrl(url)) => {
+                        let html = self
+                            .get_page_async(BbPage::Folder { url: url.clone() })
+                            .await?;
+                        let mut items = Self::directory_contents(url, &html)?;
+                        self.add_folder_details_async(url, &mut items).await;
+                        items
+                    }
+                    _ => vec![],
+                };
+                Ok(self.course_item_children(course_item, folder_contents))
+            }
+            _ => Err(BbError::NotADirectory(Box::new(item.clone()))),
+        }
+    }
+
+    /// Like `add_folder_details`, but without blocking.
+    async fn add_folder_details_async(&self, url: &str, items: &mut [CourseItem]) {
+        let Some(page) = Self::folder_details_page(url) else {
+            return;
+        };
+        let details = match self.get_page_async(page).await {
+            Ok(json) => Self::folder_details(&json),
+            Err(err) => Err(err),
+        };
+        let details = match details {
+            Ok(details) => details,
+            Err(err) => {
+                debug!("failed to get the details of the items in {url}: {err}");
+                return;
+            }
+        };
+        for (item, details) in Self::with_details(items, &details) {
+            let page = (details.grade_column_id.as_ref())
+                .and_then(|column_id| Self::grade_column_page(item, column_id));
+            let due = match page {
+                Some(page) => match self.get_page_async(page).await {
+                    Ok(json) => details::parse_due(&json).ok().flatten(),
+                    Err(_) => None,
+                },
+                None => None,
+            };
+            item.add_details(details, due);
+        }
+    }
+
+    /// Downloads a file, or if a copy is already in the snapshot, only checks that it's still up
+    /// to date.
+    ///
+    /// url should be from a CourseItemContent::FileUrl
+    async fn download_file_async(&self, item: &CourseItem, url: &str) -> Result<Vec<u8>, BbError> {
+        let url = &format!("{BB_BASE_URL}{url}");
+        let cached = self.cached_download(item);
+        let response = self
+            .async_requester
+            .call(
+                Method::GET,
+                url,
+                &self.cookies,
+                &Self::conditional_headers(&cached),
+            )
+            .await
+            .map_err(|err| BbError::from_async_request(err, url))?;
+        Self::check_session(&response.url)?;
+
+        let etag = response.header("ETag").map(String::from);
+        let last_modified = response.header("Last-Modified").map(String::from);
+        let snapshot = self.snapshot.clone();
+        let (item, url) = (item.clone(), url.clone());
+        Ok(Self::in_background(move || {
+            Self::finish_download(
+                snapshot.as_deref(),
+                &item,
+                &url,
+                cached,
+                response.status == 304,
+                response.body,
+                etag,
+                last_modified,
+            )
+        })
+        .await)
+    }
+
+    async fn get_course_item_contents_async(
+        &self,
+        item: &CourseItem,
+        url: &str,
+    ) -> Result<Vec<u8>, BbError> {
+        let key = self.content_key_async(item, url).await;
+        let cached = self.cached_contents(&key);
+        // Not held while downloading, since it would be held across an await
+        let known = cached.lock().unwrap().clone();
+        if let Some(bytes) = known {
+            return Ok(bytes);
+        }
+        // Stored under its key in the snapshot as well, which may have been found through the
+        // same file attached elsewhere. It's only the URL if that couldn't be found.
+        if key != url {
+            self.record_content_key(item, url, &key);
+        }
+        let bytes = self.download_file_async(item, url).await?;
+        *cached.lock().unwrap() = Some(bytes.clone());
+        Ok(bytes)
+    }
+
+    /// url should be from a CourseItemContent::FileUrl
+    async fn content_key_async(&self, item: &CourseItem, url: &str) -> String {
+        if let Some(key) = self.known_content_key(item, url) {
+            return key;
+        }
+        match self.file_head_async(url).await {
+            Ok(head) => {
+                self.record_content_key(item, url, &head.resolved_url);
+                head.resolved_url
+            }
+            Err(_) => url.into(),
+        }
+    }
+}
+
+/// Only files and the listings of courses and folders are fetched asynchronously; everything else,
+/// including the few requests made to Echo360 and every change made to Blackboard, is answered the
+/// same way as by the blocking client, on a thread that's free to block.
+#[async_trait]
+impl AsyncBbClient for BbScrapeClient {
+    type Item = Item;
+    type Error = BbError;
+
+    async fn get_root(&self) -> Result<Item, BbError> {
+        let root = self
+            .fetch_or_restore_async(
+                async { Ok(Self::root(self.get_courses_async().await?)) },
+                Snapshot::record_root,
+                Snapshot::root,
+            )
+            .await?;
+        Ok(self.add_local_files(root))
+    }
+
+    async fn get_children(&self, item: &Item) -> Result<Vec<Item>, BbError> {
+        match item {
+            Item::Course(_) | Item::CourseItem(_) => {
+                self.fetch_or_restore_async(
+                    async {
+                        let children = self.fetch_children_async(item).await?;
+                        self.refresh_file_heads_async(&children).await;
+                        Ok(children)
+                    },
+                    {
+                        let item = item.clone();
+                        move |snapshot, children| snapshot.record_children(&item, children)
+                    },
+                    |snapshot| snapshot.children(item),
+                )
+                .await
+            }
+            _ => Self::blocking(|| BbClient::get_children(self, item)),
+        }
+    }
+
+    async fn get_size(&self, item: &Item) -> Result<usize, BbError> {
+        match item {
+            Item::CourseItem(CourseItem {
+                content: Some(CourseItemContent::FileUrl(url)),
+                ..
+            }) => {
+                self.fetch_or_restore_async(
+                    async { Ok(self.file_head_async(url).await?.revision.size) },
+                    {
+                        let item = item.clone();
+                        move |snapshot, size| snapshot.record_size(&item, *size)
+                    },
+                    |snapshot| snapshot.size(item),
+                )
+                .await
+            }
+            _ => Self::blocking(|| BbClient::get_size(self, item)),
+        }
+    }
+
+    async fn get_contents(&self, item: &Item) -> Result<Vec<u8>, BbError> {
+        match item {
+            Item::CourseItem(
+                course_item @ CourseItem {
+                    content: Some(CourseItemContent::FileUrl(url)),
+                    ..
+                },
+            ) => {
+                self.fetch_or_restore_async(
+                    self.get_course_item_contents_async(course_item, url),
+                    // The contents are recorded as they're downloaded
+                    |_, _| {},
+                    |snapshot| snapshot.contents(item),
+                )
+                .await
+            }
+            _ => Self::blocking(|| BbClient::get_contents(self, item)),
+        }
+    }
+
+    fn get_type(&self, item: &Item) -> ItemType {
+        BbClient::get_type(self, item)
+    }
+
+    async fn get_name(&self, item: &Item) -> Result<String, BbError> {
+        match item {
+            Item::CourseItem(CourseItem {
+                content: Some(CourseItemContent::FileUrl(url)),
+                ..
+            }) if BbClient::get_type(self, item) == ItemType::File => {
+                self.fetch_or_restore_async(
+                    async { Ok(self.file_head_async(url).await?.name) },
+                    {
+                        let item = item.clone();
+                        move |snapshot, name| snapshot.record_name(&item, name)
+                    },
+                    |snapshot| snapshot.name(item),
+                )
+                .await
+            }
+            _ => Self::blocking(|| BbClient::get_name(self, item)),
+        }
+    }
+
+    fn get_id(&self, item: &Item) -> String {
+        BbClient::get_id(self, item)
+    }
+
+    async fn get_revision(&self, item: &Item) -> Result<Revision, BbError> {
+        match item {
+            Item::CourseItem(CourseItem {
+                content: Some(CourseItemContent::FileUrl(url)),
+                ..
+            }) => {
+                self.fetch_or_restore_async(
+                    async {
+                        let revision = self.file_head_async(url).await?.revision;
+                        Ok(self.validated_revision(item, revision))
+                    },
+                    {
+                        let item = item.clone();
+                        move |snapshot, revision| snapshot.record_revision(&item, revision)
+                    },
+                    |snapshot| snapshot.revision(item),
+                )
+                .await
+            }
+            _ => Self::blocking(|| BbClient::get_revision(self, item)),
+        }
+    }
+
+    fn get_url(&self, item: &Item) -> Option<String> {
+        BbClient::get_url(self, item)
+    }
+
+    async fn get_link_target(&self, item: &Item) -> Option<String> {
+        Self::blocking(|| BbClient::get_link_target(self, item))
+    }
+
+    async fn get_range(&self, item: &Item, offset: u64, size: usize) -> Result<Vec<u8>, BbError> {
+        match item {
+            Item::Recording(_) => Self::blocking(|| BbClient::get_range(self, item, offset, size)),
+            _ => {
+                let contents = AsyncBbClient::get_contents(self, item).await?;
+                Ok(bbfs_api::range_of(&contents, offset, size).to_vec())
+            }
+        }
+    }
+
+    fn is_streamed(&self, item: &Item) -> bool {
+        BbClient::is_streamed(self, item)
+    }
+
+    fn get_capabilities(&self, item: &Item) -> Capabilities {
+        BbClient::get_capabilities(self, item)
+    }
+
+    async fn create_file(&self, dir: &Item, name: &str, contents: &[u8]) -> Result<(), BbError> {
+        Self::blocking(|| BbClient::create_file(self, dir, name, contents))
+    }
+
+    async fn create_folder(&self, dir: &Item, name: &str) -> Result<(), BbError> {
+        Self::blocking(|| BbClient::create_folder(self, dir, name))
+    }
+
+    async fn rename(&self, item: &Item, name: &str) -> Result<(), BbError> {
+        Self::blocking(|| BbClient::rename(self, item, name))
+    }
+
+    async fn delete(&self, item: &Item) -> Result<(), BbError> {
+        Self::blocking(|| BbClient::delete(self, item))
+    }
+
+    async fn set_description(&self, item: &Item, description: &str) -> Result<(), BbError> {
+        Self::blocking(|| BbClient::set_description(self, item, description))
+    }
+
+    async fn get_metadata(&self, item: &Item) -> Result<Metadata, BbError> {
+        let revision = match BbClient::get_type(self, item) {
+            ItemType::File => Some(AsyncBbClient::get_revision(self, item).await?),
+            ItemType::Directory | ItemType::Link => None,
+        };
+        let head = match item {
+            // Not worth failing over, since everything else is already known
+            Item::CourseItem(CourseItem {
+                content: Some(CourseItemContent::FileUrl(file)),
+                ..
+            }) if !self.is_offline() => self.file_head_async(file).await.ok(),
+            _ => None,
+        };
+        Ok(self.metadata(item, revision, head))
+    }
+
+    async fn get_content_key(&self, item: &Item) -> Option<String> {
+        match item {
+            Item::CourseItem(
+                course_item @ CourseItem {
+                    content: Some(CourseItemContent::FileUrl(url)),
+                    ..
+                },
+            ) => match self.is_offline() {
+                true => self.known_content_key(course_item, url),
+                false => Some(self.content_key_async(course_item, url).await),
+            },
+            _ => None,
+        }
+    }
+
+    fn get_cache_size(&self) -> u64 {
+        BbClient::get_cache_size(self)
+    }
+}
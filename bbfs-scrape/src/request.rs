@@ -0,0 +1,461 @@
+use std::io;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::{debug_span, warn};
+use ureq::{Agent, AgentBuilder, Response};
+
+/// How requests to Blackboard are made: how long to wait for them, how to retry them, and how
+/// hard Blackboard may be hit.
+#[derive(Clone, Debug)]
+pub struct RequestPolicy {
+    /// How long to wait to connect, and for each read or write once connected
+    pub timeout: Duration,
+    /// How many times to retry a GET or HEAD request after a failure that might be transient
+    pub retries: u32,
+    /// How long to wait before the first retry, doubling for each retry after that
+    pub initial_backoff: Duration,
+    /// The longest to wait between retries
+    pub max_backoff: Duration,
+    /// The longest to wait when Blackboard says how long to wait with `Retry-After`, which is
+    /// usually longer than any backoff since it's rate limiting us
+    pub max_retry_after: Duration,
+    /// The most requests that may be in progress at once
+    pub max_concurrent_requests: usize,
+    /// The most requests that may be started each second, or `None` for no limit
+    pub max_requests_per_second: Option<u32>,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+            max_retry_after: Duration::from_secs(120),
+            max_concurrent_requests: 4,
+            max_requests_per_second: Some(10),
+        }
+    }
+}
+
+impl RequestPolicy {
+    /// How long to wait before the next retry: as long as Blackboard asked, if it did, or
+    /// otherwise the current backoff.
+    fn retry_delay(&self, retry_after: Option<Duration>, backoff: Duration) -> Duration {
+        match retry_after {
+            Some(retry_after) => retry_after.min(self.max_retry_after),
+            None => backoff.min(self.max_backoff),
+        }
+    }
+}
+
+/// Why a request failed, after any retries.
+#[derive(Debug)]
+pub(crate) enum RequestError {
+    /// The request couldn't be made or Blackboard responded with an error status
+    Request(Box<ureq::Error>),
+    /// The response body couldn't be read
+    Read(io::Error),
+}
+
+impl RequestError {
+    fn is_transient(&self) -> bool {
+        match self {
+            RequestError::Request(err) => match **err {
+                ureq::Error::Status(status, _) => matches!(status, 429 | 500 | 502 | 503 | 504),
+                ureq::Error::Transport(_) => true,
+            },
+            RequestError::Read(_) => true,
+        }
+    }
+
+    /// How long Blackboard asked us to wait before trying again, if it did.
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            RequestError::Request(err) => match &**err {
+                ureq::Error::Status(_, response) => response
+                    .header("Retry-After")
+                    .and_then(|seconds| seconds.parse().ok())
+                    .map(Duration::from_secs),
+                ureq::Error::Transport(_) => None,
+            },
+            RequestError::Read(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::Request(err) => write!(f, "{err}"),
+            RequestError::Read(err) => write!(f, "failed to read response: {err}"),
+        }
+    }
+}
+
+/// Reserves the earliest time the next request may start under the rate limit, or returns `None`
+/// if there isn't one.
+fn reserve_start(policy: &RequestPolicy, next_start: &Mutex<Instant>) -> Option<Instant> {
+    let rate = policy.max_requests_per_second.filter(|rate| *rate > 0)?;
+    let mut next_start = next_start.lock().unwrap();
+    let start = (*next_start).max(Instant::now());
+    *next_start = start + Duration::from_secs(1) / rate;
+    Some(start)
+}
+
+/// Makes every request to Blackboard, applying a [`RequestPolicy`].
+pub(crate) struct Requester {
+    agent: Agent,
+    /// Used for the requests whose redirects need to be followed by hand
+    redirectless_agent: Agent,
+    policy: RequestPolicy,
+    in_flight: Mutex<usize>,
+    request_finished: Condvar,
+    /// The earliest time the next request may start
+    next_start: Mutex<Instant>,
+}
+
+/// A slot among the requests allowed in progress at once, which is given up when dropped.
+struct Permit<'a>(&'a Requester);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.0.in_flight.lock().unwrap() -= 1;
+        self.0.request_finished.notify_one();
+    }
+}
+
+impl Requester {
+    pub(crate) fn new(policy: RequestPolicy) -> Self {
+        let builder = || {
+            AgentBuilder::new()
+                .timeout_connect(policy.timeout)
+                .timeout_read(policy.timeout)
+                .timeout_write(policy.timeout)
+        };
+        Self {
+            agent: builder().build(),
+            redirectless_agent: builder().redirects(0).build(),
+            policy,
+            in_flight: Mutex::new(0),
+            request_finished: Condvar::new(),
+            next_start: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub(crate) fn max_concurrent_requests(&self) -> usize {
+        self.policy.max_concurrent_requests.max(1)
+    }
+
+    fn acquire(&self) -> Permit<'_> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while *in_flight >= self.max_concurrent_requests() {
+            in_flight = self.request_finished.wait(in_flight).unwrap();
+        }
+        *in_flight += 1;
+        Permit(self)
+    }
+
+    fn wait_for_turn(&self) {
+        if let Some(start) = reserve_start(&self.policy, &self.next_start) {
+            thread::sleep(start.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    /// Makes a request with the given extra headers and reads its response with `read`, retrying
+    /// GET and HEAD requests that fail in a way that might be transient.
+    pub(crate) fn call<T>(
+        &self,
+        method: &str,
+        url: &str,
+        cookies: &str,
+        headers: &[(&str, &str)],
+        read: impl Fn(Response) -> io::Result<T>,
+    ) -> Result<T, RequestError> {
+        let _span = debug_span!("http", method, url).entered();
+        let retries = if matches!(method, "GET" | "HEAD") {
+            self.policy.retries
+        } else {
+            0
+        };
+
+        let mut backoff = self.policy.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            let result = {
+                let _permit = self.acquire();
+                self.wait_for_turn();
+                headers
+                    .iter()
+                    .fold(
+                        self.agent.request(method, url).set("Cookie", cookies),
+                        |request, (name, value)| request.set(name, value),
+                    )
+                    .call()
+                    .map_err(|err| RequestError::Request(Box::new(err)))
+                    .and_then(|response| read(response).map_err(RequestError::Read))
+            };
+            match result {
+                Err(err) if attempt < retries && err.is_transient() => {
+                    let delay = self.policy.retry_delay(err.retry_after(), backoff);
+                    warn!("retrying in {delay:?} after {err}");
+                    thread::sleep(delay);
+                    backoff = (backoff * 2).min(self.policy.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Sends `body` with the given extra headers, which should include its content type, without
+    /// retrying since sending it again might not be safe.
+    pub(crate) fn send<T>(
+        &self,
+        method: &str,
+        url: &str,
+        cookies: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+        read: impl FnOnce(Response) -> io::Result<T>,
+    ) -> Result<T, RequestError> {
+        let _span = debug_span!("http", method, url).entered();
+        let _permit = self.acquire();
+        self.wait_for_turn();
+        headers
+            .iter()
+            .fold(
+                self.agent.request(method, url).set("Cookie", cookies),
+                |request, (name, value)| request.set(name, value),
+            )
+            .send_bytes(body)
+            .map_err(|err| RequestError::Request(Box::new(err)))
+            .and_then(|response| read(response).map_err(RequestError::Read))
+    }
+
+    /// Makes a single request without following any redirect it gets, so that the cookies set
+    /// along the way can be collected, posting `form` if the method is POST.
+    pub(crate) fn send_without_redirects(
+        &self,
+        method: &str,
+        url: &str,
+        cookies: &str,
+        form: &[(&str, &str)],
+    ) -> Result<Response, RequestError> {
+        let _span = debug_span!("http", method, url).entered();
+        let _permit = self.acquire();
+        self.wait_for_turn();
+        let request = self
+            .redirectless_agent
+            .request(method, url)
+            .set("Cookie", cookies);
+        match method {
+            "POST" => request.send_form(form),
+            _ => request.call(),
+        }
+        .map_err(|err| RequestError::Request(Box::new(err)))
+    }
+}
+
+/// Why an async request failed, after any retries.
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub(crate) enum AsyncRequestError {
+    /// Blackboard responded with an error status, along with how long it asked us to wait before
+    /// trying again, if it did
+    Status(u16, Option<Duration>),
+    /// The request couldn't be made or the response body couldn't be read
+    Http(Box<reqwest::Error>),
+    /// Blackboard took longer than the timeout to respond or to send more of the body
+    Timeout,
+}
+
+#[cfg(feature = "async")]
+impl AsyncRequestError {
+    fn is_transient(&self) -> bool {
+        match self {
+            AsyncRequestError::Status(status, _) => matches!(status, 429 | 500 | 502 | 503 | 504),
+            AsyncRequestError::Http(err) => !err.is_builder() && !err.is_redirect(),
+            AsyncRequestError::Timeout => true,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl std::fmt::Display for AsyncRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AsyncRequestError::Status(status, _) => write!(f, "status code {status}"),
+            AsyncRequestError::Http(err) => write!(f, "{err}"),
+            AsyncRequestError::Timeout => write!(f, "timed out"),
+        }
+    }
+}
+
+/// A successful response whose body has been read in full.
+#[cfg(feature = "async")]
+pub(crate) struct AsyncResponse {
+    pub(crate) status: u16,
+    /// Where the request ended up after following any redirects
+    pub(crate) url: String,
+    headers: reqwest::header::HeaderMap,
+    pub(crate) body: Vec<u8>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncResponse {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+}
+
+/// Makes requests to Blackboard without blocking, applying the same [`RequestPolicy`] as
+/// [`Requester`].
+#[cfg(feature = "async")]
+pub(crate) struct AsyncRequester {
+    client: reqwest::Client,
+    policy: RequestPolicy,
+    permits: tokio::sync::Semaphore,
+    next_start: Mutex<Instant>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncRequester {
+    pub(crate) fn new(policy: RequestPolicy) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(policy.timeout)
+            .build()
+            .expect("the HTTP client should always be buildable");
+        Self {
+            client,
+            permits: tokio::sync::Semaphore::new(policy.max_concurrent_requests.max(1)),
+            policy,
+            next_start: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        cookies: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<AsyncResponse, AsyncRequestError> {
+        let request = headers.iter().fold(
+            self.client.request(method, url).header("Cookie", cookies),
+            |request, (name, value)| request.header(*name, *value),
+        );
+        // Like the blocking agent, time out waiting for each part of the response rather than
+        // the whole download
+        let timeout = self.policy.timeout;
+        let mut response = tokio::time::timeout(timeout, request.send())
+            .await
+            .map_err(|_| AsyncRequestError::Timeout)?
+            .map_err(|err| AsyncRequestError::Http(Box::new(err)))?;
+
+        let status = response.status().as_u16();
+        if status >= 400 {
+            let retry_after = response
+                .headers()
+                .get("Retry-After")
+                .and_then(|seconds| seconds.to_str().ok()?.parse().ok())
+                .map(Duration::from_secs);
+            return Err(AsyncRequestError::Status(status, retry_after));
+        }
+
+        let mut body = vec![];
+        while let Some(chunk) = tokio::time::timeout(timeout, response.chunk())
+            .await
+            .map_err(|_| AsyncRequestError::Timeout)?
+            .map_err(|err| AsyncRequestError::Http(Box::new(err)))?
+        {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(AsyncResponse {
+            status,
+            url: response.url().to_string(),
+            headers: response.headers().clone(),
+            body,
+        })
+    }
+
+    /// Makes a request with the given extra headers and reads its whole response, retrying GET
+    /// and HEAD requests that fail in a way that might be transient.
+    pub(crate) async fn call(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        cookies: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<AsyncResponse, AsyncRequestError> {
+        use tracing::Instrument;
+
+        let span = debug_span!("http", method = %method, url);
+        let retries = if method == reqwest::Method::GET || method == reqwest::Method::HEAD {
+            self.policy.retries
+        } else {
+            0
+        };
+
+        async {
+            let mut backoff = self.policy.initial_backoff;
+            let mut attempt = 0;
+            loop {
+                let result = {
+                    let _permit = self
+                        .permits
+                        .acquire()
+                        .await
+                        .expect("the semaphore is never closed");
+                    if let Some(start) = reserve_start(&self.policy, &self.next_start) {
+                        tokio::time::sleep_until(start.into()).await;
+                    }
+                    self.send(method.clone(), url, cookies, headers).await
+                };
+                match result {
+                    Err(err) if attempt < retries && err.is_transient() => {
+                        let retry_after = match err {
+                            AsyncRequestError::Status(_, retry_after) => retry_after,
+                            _ => None,
+                        };
+                        let delay = self.policy.retry_delay(retry_after, backoff);
+                        warn!("retrying in {delay:?} after {err}");
+                        tokio::time::sleep(delay).await;
+                        backoff = (backoff * 2).min(self.policy.max_backoff);
+                        attempt += 1;
+                    }
+                    result => return result,
+                }
+            }
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_is_waited_out_past_the_backoff() {
+        let policy = RequestPolicy::default();
+        let backoff = Duration::from_secs(1);
+        assert_eq!(policy.retry_delay(None, backoff), backoff);
+        assert_eq!(
+            policy.retry_delay(None, Duration::from_secs(60)),
+            policy.max_backoff
+        );
+        assert_eq!(
+            policy.retry_delay(Some(Duration::from_secs(30)), backoff),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            policy.retry_delay(Some(Duration::from_secs(3600)), backoff),
+            policy.max_retry_after
+        );
+    }
+}
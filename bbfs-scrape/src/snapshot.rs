@@ -0,0 +1,488 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use bbfs_api::Revision;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::{Item, ResolvedLaunch};
+
+const TREE_FILE: &str = "tree.json";
+/// Held while the tree is written, since the CLI's subcommands can open the same snapshot while
+/// it's mounted
+const LOCK_FILE: &str = "tree.lock";
+const FILES_DIR: &str = "files";
+
+/// The least time between writes of the tree while it keeps changing, so that listing a large
+/// course doesn't rewrite it for every folder. Whatever changed since is written when the snapshot
+/// is flushed or dropped.
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Writes `contents` to `path` through a temporary file beside it, so that a crash can't leave it
+/// half-written. The temporary file is only ever used by this write, since other threads and
+/// processes can be writing the same file at once.
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    static NEXT_TEMP: AtomicU64 = AtomicU64::new(0);
+    let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+    temp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        NEXT_TEMP.fetch_add(1, Ordering::SeqCst)
+    ));
+    let temp_path = path.with_file_name(temp_name);
+    let mut file = File::create(&temp_path)?;
+    let written = file.write_all(contents).and_then(|()| file.sync_all());
+    match written.and_then(|()| fs::rename(&temp_path, path)) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let _ = fs::remove_file(temp_path);
+            Err(err)
+        }
+    }
+}
+
+/// Everything that has been learned about a file item, so that it can be listed while offline.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct FileRecord {
+    name: Option<String>,
+    size: Option<usize>,
+    /// The validators of the downloaded contents, used to check whether they're still current
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// When the downloaded contents were last confirmed to match Blackboard
+    validated_at: Option<SystemTime>,
+    /// What the contents are stored under, which is shared by every item with the same file
+    content_key: Option<String>,
+}
+
+impl FileRecord {
+    fn forget_validators(&mut self) {
+        self.etag = None;
+        self.last_modified = None;
+        self.validated_at = None;
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Listing {
+    items: Vec<Item>,
+    listed_at: SystemTime,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Tree {
+    root: Option<Item>,
+    children: HashMap<String, Listing>,
+    files: HashMap<String, FileRecord>,
+    /// Where each LTI launch ended up, by its URL
+    #[serde(default)]
+    launches: HashMap<String, ResolvedLaunch>,
+}
+
+/// A persistent record of the tree discovered from Blackboard and the file contents downloaded
+/// from it, which is used to serve the filesystem while offline.
+pub struct Snapshot {
+    dir: PathBuf,
+    tree: Mutex<Tree>,
+    /// Whether the tree has changed since it was last written
+    dirty: AtomicBool,
+    /// When the tree was last written, which is locked while it's being written
+    saved_at: Mutex<Instant>,
+}
+
+impl Snapshot {
+    /// Opens the snapshot stored in `dir`, starting an empty one if none exists yet.
+    pub fn open(dir: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(dir.join(FILES_DIR))?;
+        let tree = match fs::read(dir.join(TREE_FILE)) {
+            Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|err| {
+                warn!("discarding unreadable snapshot: {err}");
+                Tree::default()
+            }),
+            Err(_) => Tree::default(),
+        };
+        Ok(Self {
+            dir,
+            tree: Mutex::new(tree),
+            dirty: AtomicBool::new(false),
+            saved_at: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Returns whether a snapshot has been recorded in `dir`.
+    pub fn exists(dir: &Path) -> bool {
+        dir.join(TREE_FILE).is_file()
+    }
+
+    fn key(item: &Item) -> String {
+        item.id()
+    }
+
+    /// Where the file's contents are stored, which is named after its content key if it's known
+    /// so that a file attached in several places is only stored once. Otherwise, such as in
+    /// older snapshots, it's named after the item.
+    fn content_path(&self, item: &Item) -> PathBuf {
+        let content_key = self.content_key(item);
+        let name = match content_key {
+            Some(content_key) => Sha256::digest(content_key)
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+            None => Self::key(item),
+        };
+        self.dir.join(FILES_DIR).join(name)
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Tree) -> bool) {
+        if f(&mut self.tree.lock().unwrap()) {
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+        // Another thread that's already writing the tree will pick up this change
+        if let Ok(saved_at) = self.saved_at.try_lock() {
+            if saved_at.elapsed() >= SAVE_INTERVAL {
+                self.save(saved_at);
+            }
+        }
+    }
+
+    /// Writes the tree if it's changed since it was last written.
+    pub fn flush(&self) {
+        self.save(self.saved_at.lock().unwrap());
+    }
+
+    fn save(&self, mut saved_at: std::sync::MutexGuard<Instant>) {
+        if !self.dirty.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        // Only serializing holds up the threads recording into the tree, not writing it out
+        let json = serde_json::to_vec(&*self.tree.lock().unwrap());
+        match json.map_err(Into::into).and_then(|json| self.write(&json)) {
+            Ok(()) => *saved_at = Instant::now(),
+            Err(err) => {
+                warn!("failed to save snapshot: {err}");
+                self.dirty.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn write(&self, json: &[u8]) -> std::io::Result<()> {
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.dir.join(LOCK_FILE))?;
+        // Released as the lock file is closed
+        lock.lock()?;
+        write_atomically(&self.dir.join(TREE_FILE), json)
+    }
+
+    pub fn root(&self) -> Option<Item> {
+        self.tree.lock().unwrap().root.clone()
+    }
+
+    pub fn record_root(&self, root: &Item) {
+        self.update(|tree| {
+            tree.root = Some(root.clone());
+            true
+        })
+    }
+
+    pub fn children(&self, item: &Item) -> Option<Vec<Item>> {
+        let tree = self.tree.lock().unwrap();
+        tree.children
+            .get(&Self::key(item))
+            .map(|listing| listing.items.clone())
+    }
+
+    pub fn record_children(&self, item: &Item, children: &[Item]) {
+        self.update(|tree| {
+            tree.children.insert(
+                Self::key(item),
+                Listing {
+                    items: children.to_vec(),
+                    listed_at: SystemTime::now(),
+                },
+            );
+            true
+        })
+    }
+
+    pub fn name(&self, item: &Item) -> Option<String> {
+        let tree = self.tree.lock().unwrap();
+        tree.files
+            .get(&Self::key(item))
+            .and_then(|record| record.name.clone())
+    }
+
+    pub fn record_name(&self, item: &Item, name: &str) {
+        self.update(|tree| {
+            let record = tree.files.entry(Self::key(item)).or_default();
+            let changed = record.name.as_deref() != Some(name);
+            record.name = Some(name.into());
+            changed
+        })
+    }
+
+    pub fn size(&self, item: &Item) -> Option<usize> {
+        let size = {
+            let tree = self.tree.lock().unwrap();
+            tree.files
+                .get(&Self::key(item))
+                .and_then(|record| record.size)
+        };
+        size.or_else(|| {
+            fs::metadata(self.content_path(item))
+                .ok()
+                .map(|metadata| metadata.len() as usize)
+        })
+    }
+
+    /// Records a file's size without downloading it. If it's changed, the downloaded contents are
+    /// out of date, so their validators are cleared to have them downloaded again in full.
+    pub fn record_size(&self, item: &Item, size: usize) {
+        self.update(|tree| {
+            let record = tree.files.entry(Self::key(item)).or_default();
+            if record.size == Some(size) {
+                return false;
+            }
+            record.size = Some(size);
+            record.forget_validators();
+            true
+        })
+    }
+
+    /// Records the revision Blackboard reports for a file without downloading it. As with
+    /// [`Snapshot::record_size`], the downloaded contents' validators are cleared once they no
+    /// longer match it.
+    pub fn record_revision(&self, item: &Item, revision: &Revision) {
+        self.update(|tree| {
+            let record = tree.files.entry(Self::key(item)).or_default();
+            let downloaded = record.etag.is_some() || record.last_modified.is_some();
+            let stale = downloaded
+                && (record.etag != revision.etag || record.last_modified != revision.last_modified);
+            if record.size == Some(revision.size) && !stale {
+                return false;
+            }
+            record.size = Some(revision.size);
+            record.forget_validators();
+            true
+        })
+    }
+
+    /// The revision of the file whose contents were downloaded, if they were.
+    pub fn revision(&self, item: &Item) -> Option<Revision> {
+        let size = self.size(item)?;
+        let tree = self.tree.lock().unwrap();
+        let record = tree
+            .files
+            .get(&Self::key(item))
+            .cloned()
+            .unwrap_or_default();
+        Some(Revision {
+            size,
+            etag: record.etag,
+            last_modified: record.last_modified,
+            validated_at: record.validated_at,
+        })
+    }
+
+    pub fn content_key(&self, item: &Item) -> Option<String> {
+        let tree = self.tree.lock().unwrap();
+        tree.files
+            .get(&Self::key(item))
+            .and_then(|record| record.content_key.clone())
+    }
+
+    pub fn record_content_key(&self, item: &Item, content_key: &str) {
+        self.update(|tree| {
+            let record = tree.files.entry(Self::key(item)).or_default();
+            let changed = record.content_key.as_deref() != Some(content_key);
+            record.content_key = Some(content_key.into());
+            changed
+        })
+    }
+
+    pub fn launch(&self, url: &str) -> Option<ResolvedLaunch> {
+        self.tree.lock().unwrap().launches.get(url).cloned()
+    }
+
+    pub fn record_launch(&self, url: &str, launch: &ResolvedLaunch) {
+        self.update(|tree| {
+            tree.launches.insert(url.into(), launch.clone());
+            true
+        })
+    }
+
+    pub fn contents(&self, item: &Item) -> Option<Vec<u8>> {
+        fs::read(self.content_path(item)).ok()
+    }
+
+    /// How many bytes of file contents are stored on disk.
+    pub fn contents_size(&self) -> u64 {
+        fs::read_dir(self.dir.join(FILES_DIR))
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok()?.metadata().ok())
+                    .map(|metadata| metadata.len())
+                    .sum()
+            })
+            .unwrap_or(0)
+    }
+
+    pub fn record_contents(
+        &self,
+        item: &Item,
+        contents: &[u8],
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) {
+        if let Err(err) = write_atomically(&self.content_path(item), contents) {
+            warn!("failed to save file contents to snapshot: {err}");
+            return;
+        }
+        self.update(|tree| {
+            let record = tree.files.entry(Self::key(item)).or_default();
+            record.size = Some(contents.len());
+            record.etag = etag.map(Into::into);
+            record.last_modified = last_modified.map(Into::into);
+            record.validated_at = Some(SystemTime::now());
+            true
+        })
+    }
+
+    /// Records that the downloaded contents were just confirmed to still be current.
+    pub fn record_validated(&self, item: &Item) {
+        self.update(|tree| {
+            tree.files.entry(Self::key(item)).or_default().validated_at = Some(SystemTime::now());
+            true
+        })
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bbfs-snapshot-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn writes_are_put_off_until_flushed() {
+        let dir = temp_dir("flush");
+        let snapshot = Snapshot::open(&dir).unwrap();
+        snapshot.record_root(&Item::new_link("root", "/"));
+        assert!(!Snapshot::exists(&dir));
+
+        snapshot.flush();
+        assert!(Snapshot::exists(&dir));
+        assert!(Snapshot::open(&dir).unwrap().root().is_some());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dropping_writes_what_changed() {
+        let dir = temp_dir("drop");
+        let folder = Item::new_link("folder", "/folder");
+        let children = [Item::new_link("a", "/a"), Item::new_link("b", "/b")];
+        {
+            let snapshot = Snapshot::open(&dir).unwrap();
+            snapshot.record_children(&folder, &children);
+            snapshot.record_name(&children[0], "a.pdf");
+        }
+        let snapshot = Snapshot::open(&dir).unwrap();
+        assert_eq!(snapshot.children(&folder).unwrap().len(), 2);
+        assert_eq!(snapshot.name(&children[0]).as_deref(), Some("a.pdf"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn snapshots_open_at_once_write_without_clashing() {
+        let dir = temp_dir("shared");
+        let mounted = Snapshot::open(&dir).unwrap();
+        let subcommand = Snapshot::open(&dir).unwrap();
+        mounted.record_root(&Item::new_link("root", "/"));
+        subcommand.record_root(&Item::new_link("root", "/"));
+        mounted.flush();
+        subcommand.flush();
+
+        assert!(Snapshot::open(&dir).unwrap().root().is_some());
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, [FILES_DIR, TREE_FILE, LOCK_FILE]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_new_revision_clears_the_downloads_validators() {
+        let dir = temp_dir("revision");
+        let snapshot = Snapshot::open(&dir).unwrap();
+        let file = Item::new_link("file", "/file");
+        snapshot.record_contents(&file, b"old", Some("\"1\""), None);
+
+        let unchanged = snapshot.revision(&file).unwrap();
+        snapshot.record_revision(&file, &unchanged);
+        assert_eq!(
+            snapshot.revision(&file).unwrap().etag.as_deref(),
+            Some("\"1\"")
+        );
+
+        let changed = Revision {
+            size: 3,
+            etag: Some("\"2\"".into()),
+            ..Default::default()
+        };
+        snapshot.record_revision(&file, &changed);
+        let revision = snapshot.revision(&file).unwrap();
+        assert_eq!((revision.size, revision.etag), (3, None));
+        assert_eq!(revision.validated_at, None);
+
+        snapshot.record_size(&file, 5);
+        assert_eq!(snapshot.size(&file), Some(5));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn files_with_the_same_content_key_are_stored_once() {
+        let dir = temp_dir("content-key");
+        let snapshot = Snapshot::open(&dir).unwrap();
+        let files = [Item::new_link("a", "/a"), Item::new_link("b", "/b")];
+        for file in &files {
+            snapshot.record_content_key(file, "/bbcswebdav/xid-1");
+        }
+        snapshot.record_contents(&files[0], b"shared", None, None);
+        snapshot.record_contents(&files[1], b"shared", None, None);
+
+        assert_eq!(
+            snapshot.contents(&files[1]).as_deref(),
+            Some(&b"shared"[..])
+        );
+        assert_eq!(fs::read_dir(dir.join(FILES_DIR)).unwrap().count(), 1);
+        assert_eq!(snapshot.contents_size(), 6);
+
+        let other = Item::new_link("c", "/c");
+        snapshot.record_contents(&other, b"other", None, None);
+        assert_eq!(snapshot.contents(&other).as_deref(), Some(&b"other"[..]));
+        assert_eq!(snapshot.content_key(&other), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
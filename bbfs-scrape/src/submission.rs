@@ -0,0 +1,270 @@
+//! Submitting files to assignments, through the same upload page that Blackboard shows in the
+//! browser.
+
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use soup::prelude::*;
+
+use crate::request::{RequestError, Requester};
+use crate::{lti, BbError};
+
+/// The form on an assignment's upload page that submissions are posted through.
+struct UploadForm {
+    action: String,
+    fields: Vec<(String, String)>,
+    /// The name of the input that the file is uploaded as
+    file_field: String,
+}
+
+fn parse_upload_form(html: &str) -> Option<UploadForm> {
+    let soup = Soup::new(html);
+    let form = soup.tag("form").find_all().find(|form| {
+        form.get("enctype")
+            .is_some_and(|enctype| enctype.contains("multipart"))
+            || form
+                .get("action")
+                .is_some_and(|action| action.contains("uploadAssignment"))
+    })?;
+    let inputs: Vec<_> = form.tag("input").find_all().collect();
+    let of_type = |input: &&_, ty: &str| {
+        NodeExt::get(*input, "type").is_some_and(|input_type| input_type.eq_ignore_ascii_case(ty))
+    };
+    let fields = inputs
+        .iter()
+        .filter(|input| of_type(input, "hidden"))
+        .filter_map(|input| Some((input.get("name")?, input.get("value").unwrap_or_default())))
+        .collect();
+    let file_field = inputs
+        .iter()
+        .filter(|input| of_type(input, "file"))
+        .find_map(|input| input.get("name"))
+        .unwrap_or_else(|| "newFile_LocalFile0".into());
+    Some(UploadForm {
+        action: form.get("action")?,
+        fields,
+        file_field,
+    })
+}
+
+/// Makes a value safe to put in a quoted multipart header parameter.
+fn quote(value: &str) -> String {
+    value.replace('"', "%22").replace(['\r', '\n'], " ")
+}
+
+/// Encodes a form along with a file as multipart/form-data, returning the body and its content
+/// type.
+pub(crate) fn multipart(
+    fields: &[(String, String)],
+    file_field: &str,
+    file_name: &str,
+    contents: &[u8],
+) -> (Vec<u8>, String) {
+    // Derived from the contents, so that it's all but certain not to appear in them
+    let digest: String = Sha256::digest(contents)
+        .iter()
+        .take(16)
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    let boundary = format!("bbfs-{digest}");
+
+    let mut body = vec![];
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{value}\r\n",
+                quote(name)
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            quote(file_field),
+            quote(file_name)
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(contents);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    (body, format!("multipart/form-data; boundary={boundary}"))
+}
+
+/// Finds Blackboard's confirmation of a submission on the page it responded with, or the reason
+/// it gave for refusing it.
+fn parse_confirmation(html: &str) -> Result<String, String> {
+    let soup = Soup::new(html);
+    let whitespace = Regex::new(r"\s+").unwrap();
+    let message = |id: &str| {
+        soup.attr("id", id)
+            .find()
+            .map(|elem| whitespace.replace_all(elem.text().trim(), " ").into_owned())
+    };
+    match message("goodMsg1") {
+        Some(confirmation) => Ok(confirmation),
+        None => {
+            Err(message("badMsg1")
+                .unwrap_or_else(|| "Blackboard didn't confirm the submission".into()))
+        }
+    }
+}
+
+/// Submits a file to the assignment whose upload page is at `page_url`, returning Blackboard's
+/// confirmation. Nothing else ties this to Blackboard, so it can be pointed at a stand-in server.
+pub(crate) fn submit(
+    requester: &Requester,
+    page_url: &str,
+    cookies: &str,
+    file_name: &str,
+    contents: &[u8],
+) -> Result<String, BbError> {
+    let failed = |reason: String| BbError::FailedToSubmit(file_name.into(), reason);
+    let request_error = |err: RequestError, url: &str| {
+        BbError::from_request(
+            err,
+            url,
+            |err| failed(err.to_string()),
+            |err| failed(err.to_string()),
+        )
+    };
+
+    let (final_url, html) = requester
+        .call("GET", page_url, cookies, &[], |response| {
+            Ok((response.get_url().to_owned(), response.into_string()?))
+        })
+        .map_err(|err| request_error(err, page_url))?;
+    // An expired session gets redirected to the login page
+    let origin = lti::origin(page_url).unwrap_or_default();
+    if lti::origin(&final_url) != Some(origin) {
+        return Err(BbError::SessionExpired);
+    }
+    let form = parse_upload_form(&html).ok_or_else(|| {
+        failed("Blackboard didn't offer to take a submission, so it may be closed".into())
+    })?;
+
+    let action = match form.action.starts_with('/') {
+        true => format!("{origin}{}", form.action),
+        false => form.action,
+    };
+    // The attachment is described the way Blackboard's upload widget describes a local file
+    let attachment = [
+        ("newFile_attachmentType", "L"),
+        ("newFile_fileId", "new"),
+        ("newFile_linkTitle", file_name),
+        ("dispatch", "submit"),
+    ];
+    let mut fields = form.fields;
+    fields.retain(|(name, _)| !attachment.iter().any(|(field, _)| field == name));
+    fields.extend(
+        attachment
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string())),
+    );
+    let (body, content_type) = multipart(&fields, &form.file_field, file_name, contents);
+
+    let html = requester
+        .send(
+            "POST",
+            &action,
+            cookies,
+            &[("Content-Type", &content_type)],
+            &body,
+            |response| response.into_string(),
+        )
+        .map_err(|err| request_error(err, &action))?;
+    parse_confirmation(&html).map_err(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+
+    use crate::request::RequestPolicy;
+
+    use super::*;
+
+    const UPLOAD_PAGE: &str = r#"<form method="post" enctype="multipart/form-data"
+        action="/webapps/assignment/uploadAssignment?action=submit">
+        <input type="hidden" name="course_id" value="_1_1">
+        <input type="hidden" name="blackboard.platform.security.NonceUtil.nonce" value="abc">
+        <input type="file" name="newFile_LocalFile0">
+        <input type="text" name="studentSubmission.text">
+        </form>"#;
+
+    /// A request the stub server received, as its method, URL and body.
+    type Received = (String, String, String);
+
+    /// Serves each page in turn to the requests made of it, returning the server's origin and
+    /// what it was sent.
+    fn serve(pages: Vec<&'static str>) -> (String, Receiver<Received>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let origin = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for page in pages {
+                let mut request = server.recv().unwrap();
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                let received = (request.method().to_string(), request.url().into(), body);
+                sender.send(received).unwrap();
+                request
+                    .respond(tiny_http::Response::from_string(page))
+                    .unwrap();
+            }
+        });
+        (origin, receiver)
+    }
+
+    fn submit_to(origin: &str) -> Result<String, BbError> {
+        let requester = Requester::new(RequestPolicy {
+            retries: 0,
+            ..Default::default()
+        });
+        let page_url = format!("{origin}/webapps/assignment/uploadAssignment?content_id=_2_1");
+        submit(&requester, &page_url, "", "essay.txt", b"my essay")
+    }
+
+    #[test]
+    fn files_are_posted_through_the_upload_form() {
+        let confirmation = r#"<div id="goodMsg1">  Success:
+            essay.txt submitted. </div>"#;
+        let (origin, received) = serve(vec![UPLOAD_PAGE, confirmation]);
+
+        assert_eq!(submit_to(&origin).unwrap(), "Success: essay.txt submitted.");
+        let (method, _, _) = received.recv().unwrap();
+        assert_eq!(method, "GET");
+        let (method, url, body) = received.recv().unwrap();
+        assert_eq!(method, "POST");
+        assert_eq!(url, "/webapps/assignment/uploadAssignment?action=submit");
+        for part in [
+            "name=\"course_id\"\r\n\r\n_1_1\r\n",
+            "name=\"blackboard.platform.security.NonceUtil.nonce\"\r\n\r\nabc\r\n",
+            "name=\"newFile_linkTitle\"\r\n\r\nessay.txt\r\n",
+            "name=\"newFile_LocalFile0\"; filename=\"essay.txt\"",
+            "\r\n\r\nmy essay\r\n",
+        ] {
+            assert!(body.contains(part), "{part:?} isn't in {body:?}");
+        }
+        assert!(!body.contains("studentSubmission.text"));
+    }
+
+    #[test]
+    fn refusals_are_reported_with_blackboards_reason() {
+        let refusal = r#"<div id="badMsg1">The due date has passed.</div>"#;
+        let (origin, _received) = serve(vec![UPLOAD_PAGE, refusal]);
+        assert!(matches!(
+            submit_to(&origin),
+            Err(BbError::FailedToSubmit(name, reason))
+                if name == "essay.txt" && reason == "The due date has passed."
+        ));
+
+        let (origin, received) = serve(vec!["<p>Submissions are closed.</p>"]);
+        assert!(matches!(
+            submit_to(&origin),
+            Err(BbError::FailedToSubmit(_, _))
+        ));
+        assert_eq!(received.iter().count(), 1);
+    }
+}
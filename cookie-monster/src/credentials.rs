@@ -0,0 +1,268 @@
+//! Where the headless auth flow gets a user's credentials and Duo passcodes from, so that it can
+//! run without anyone at the terminal, such as from cron or systemd.
+
+use std::io::{prelude::*, stdin, stdout, IsTerminal};
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use hmac::{Hmac, Mac};
+use rpassword::read_password;
+use sha1::Sha1;
+
+pub const USERNAME_VAR: &str = "BBFS_USERNAME";
+pub const PASSWORD_VAR: &str = "BBFS_PASSWORD";
+pub const TOTP_SECRET_VAR: &str = "BBFS_TOTP_SECRET";
+
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+    /// The base32 secret of a Duo passcode generator, such as the one a password manager is given
+    /// when it's added to Duo as a third-party authenticator
+    pub totp_secret: Option<String>,
+}
+
+/// Whichever credentials a source had.
+#[derive(Default)]
+struct Found {
+    username: Option<String>,
+    password: Option<String>,
+    totp_secret: Option<String>,
+}
+
+impl Found {
+    /// Fills in whatever's missing from `other`.
+    fn or(self, other: Found) -> Found {
+        Found {
+            username: self.username.or(other.username),
+            password: self.password.or(other.password),
+            totp_secret: self.totp_secret.or(other.totp_secret),
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.username.is_some() && self.password.is_some()
+    }
+
+    /// Reads `key: value` or `key = value` lines, along with an `otpauth://` URI for the TOTP
+    /// secret. If `password_first` is set, the first line is the password, the way `pass` stores
+    /// it.
+    fn parse(text: &str, password_first: bool) -> Found {
+        let mut credentials = Found::default();
+        for (i, line) in text.lines().enumerate() {
+            // Passwords can have anything in them, including spaces at either end and what
+            // separates keys from values
+            if i == 0 && password_first {
+                credentials.password = Some(line.strip_suffix('\r').unwrap_or(line).into());
+                continue;
+            }
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with("otpauth://") {
+                credentials.totp_secret = url::Url::parse(line).ok().and_then(|uri| {
+                    uri.query_pairs()
+                        .find(|(key, _)| key == "secret")
+                        .map(|(_, secret)| secret.into_owned())
+                });
+                continue;
+            }
+            let Some((key, value)) = line.split_once([':', '=']) else {
+                continue;
+            };
+            let value = Some(value.trim().to_string());
+            match key.trim().to_ascii_lowercase().as_str() {
+                "username" | "user" | "login" => credentials.username = value,
+                "password" => credentials.password = value,
+                "totp" | "totp_secret" | "otp" => credentials.totp_secret = value,
+                _ => {}
+            }
+        }
+        credentials
+    }
+}
+
+/// Where credentials are looked for, in order: the environment, then the credentials file, then
+/// the credentials command, and then the terminal if there's one to ask at.
+#[derive(Clone, Debug, Default)]
+pub struct CredentialSources {
+    pub file: Option<PathBuf>,
+    /// A shell command that prints the credentials, such as `pass show uq`
+    pub command: Option<String>,
+}
+
+impl CredentialSources {
+    pub fn credentials(&self) -> anyhow::Result<Credentials> {
+        let mut credentials = Found {
+            username: std::env::var(USERNAME_VAR).ok(),
+            password: std::env::var(PASSWORD_VAR).ok(),
+            totp_secret: std::env::var(TOTP_SECRET_VAR).ok(),
+        };
+        if let Some(file) = self.file.as_ref().filter(|_| !credentials.is_complete()) {
+            let text = std::fs::read_to_string(file)
+                .map_err(|err| anyhow!("failed to read {}: {err}", file.display()))?;
+            credentials = credentials.or(Found::parse(&text, false));
+        }
+        if let Some(command) = self.command.as_ref().filter(|_| !credentials.is_complete()) {
+            credentials = credentials.or(Found::parse(&run(command)?, true));
+        }
+        let username = match credentials.username {
+            Some(username) => username,
+            None => prompt("Username: ")?,
+        };
+        let password = match credentials.password {
+            Some(password) => password,
+            None => {
+                ask_at_terminal("Password: ")?;
+                read_password()?
+            }
+        };
+        Ok(Credentials {
+            username,
+            password,
+            totp_secret: credentials.totp_secret,
+        })
+    }
+}
+
+fn run(command: &str) -> anyhow::Result<String> {
+    let output = Command::new("sh")
+        .args(["-c", command])
+        .output()
+        .map_err(|err| anyhow!("failed to run credentials command: {err}"))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "credentials command failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Shows a prompt, failing instead if there's nobody at the terminal to answer it.
+fn ask_at_terminal(prompt: &str) -> anyhow::Result<()> {
+    if !stdin().is_terminal() {
+        return Err(anyhow!(
+            "no credentials were configured, and there's no terminal to ask for them at"
+        ));
+    }
+    print!("{prompt}");
+    let _ = stdout().flush();
+    Ok(())
+}
+
+pub(crate) fn prompt(prompt: &str) -> anyhow::Result<String> {
+    ask_at_terminal(prompt)?;
+    let mut answer = String::new();
+    stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+fn decode_base32(encoded: &str) -> Option<Vec<u8>> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut bytes = vec![];
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in encoded.bytes().filter(|c| !matches!(c, b' ' | b'-' | b'=')) {
+        let value = ALPHABET.iter().position(|a| *a == c.to_ascii_uppercase())?;
+        // Only the bits that haven't been pushed yet are kept
+        buffer = ((buffer << 5) | value as u32) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// The passcode for the counter, as described by RFC 4226.
+fn hotp(key: &[u8], counter: u64, digits: u32) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[19] & 0xf) as usize;
+    let code = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+    let width = digits as usize;
+    format!("{:0width$}", code % 10u32.pow(digits))
+}
+
+/// The passcode for the 30 second step that the time falls in, as described by RFC 6238.
+fn totp_at(key: &[u8], seconds_since_epoch: u64, digits: u32) -> String {
+    hotp(key, seconds_since_epoch / 30, digits)
+}
+
+/// The 6 digit passcode for the current 30 second step, which is what Duo expects from a
+/// third-party authenticator.
+pub fn totp(secret: &str) -> anyhow::Result<String> {
+    let key = decode_base32(secret).ok_or(anyhow!("the TOTP secret isn't valid base32"))?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    Ok(totp_at(&key, now, 6))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA-1 secret from RFC 6238's test vectors, in base32.
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn secrets_are_decoded_from_base32() {
+        assert_eq!(
+            decode_base32(RFC_SECRET).as_deref(),
+            Some(&b"12345678901234567890"[..])
+        );
+        assert_eq!(
+            decode_base32("gezd gnbv-gy3t qojq====").as_deref(),
+            Some(&b"1234567890"[..])
+        );
+        assert_eq!(decode_base32("not base32!"), None);
+    }
+
+    #[test]
+    fn passcodes_match_the_rfc_6238_test_vectors() {
+        let key = decode_base32(RFC_SECRET).unwrap();
+        for (time, passcode) in [
+            (59, "94287082"),
+            (1111111109, "07081804"),
+            (1111111111, "14050471"),
+            (1234567890, "89005924"),
+            (2000000000, "69279037"),
+            (20000000000, "65353130"),
+        ] {
+            assert_eq!(totp_at(&key, time, 8), passcode, "at {time}");
+            assert_eq!(totp_at(&key, time, 6), passcode[2..], "at {time}");
+        }
+    }
+
+    #[test]
+    fn files_are_read_as_keys_and_values() {
+        let found = Found::parse(
+            "# UQ\nusername: s1234567\npassword = p:a=ss\n\
+             otpauth://totp/UQ:s1234567?secret=JBSWY3DPEHPK3PXP&issuer=UQ\nother: x\n",
+            false,
+        );
+        assert_eq!(found.username.as_deref(), Some("s1234567"));
+        assert_eq!(found.password.as_deref(), Some("p:a=ss"));
+        assert_eq!(found.totp_secret.as_deref(), Some("JBSWY3DPEHPK3PXP"));
+
+        let found = Found::parse("user=s1234567\ntotp: JBSWY3DPEHPK3PXP\n", false);
+        assert_eq!(found.totp_secret.as_deref(), Some("JBSWY3DPEHPK3PXP"));
+        assert!(!found.is_complete());
+    }
+
+    #[test]
+    fn commands_print_the_password_first() {
+        let found = Found::parse("  pass: word = 1  \r\nlogin: s1234567\n", true);
+        assert_eq!(found.password.as_deref(), Some("  pass: word = 1  "));
+        assert_eq!(found.username.as_deref(), Some("s1234567"));
+        assert!(found.is_complete());
+
+        let merged = Found::parse("password: from env", false).or(found);
+        assert_eq!(merged.password.as_deref(), Some("from env"));
+        assert_eq!(merged.username.as_deref(), Some("s1234567"));
+    }
+}
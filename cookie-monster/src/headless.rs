@@ -1,17 +1,81 @@
+use crate::credentials::{prompt, totp, CredentialSources};
 use crate::CookieMonster;
 use anyhow::anyhow;
-use etcetera::{choose_base_strategy, BaseStrategy};
 use fantoccini::{elements::Element, Client, ClientBuilder, Locator};
 use futures::{future::FutureExt, pin_mut, select};
-use rpassword::read_password;
-use std::io::{prelude::*, stdin, stdout};
+use std::fmt::Display;
+use std::io::prelude::*;
 use std::path::Path;
 use std::process::Command;
+use std::time::Duration;
 use url::Url;
 
-pub struct HeadlessCookieMonster;
+/// Why signing in failed, for which `bbfs` exits with distinct codes so that scripts can tell
+/// what went wrong.
+#[derive(Debug)]
+pub enum AuthError {
+    IncorrectPassword,
+    IncorrectPasscode,
+    TimedOut(Duration),
+}
+
+impl AuthError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AuthError::IncorrectPassword => 2,
+            AuthError::IncorrectPasscode => 3,
+            AuthError::TimedOut(_) => 4,
+        }
+    }
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::IncorrectPassword => write!(f, "Incorrect username or password"),
+            AuthError::IncorrectPasscode => write!(f, "Incorrect passcode"),
+            AuthError::TimedOut(timeout) => {
+                write!(f, "Signing in took longer than {}s", timeout.as_secs())
+            }
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Signs in through a headless Firefox, with credentials from the environment, a file, a command
+/// or the terminal.
+pub struct HeadlessCookieMonster {
+    credentials: CredentialSources,
+    timeout: Duration,
+}
+
+impl Default for HeadlessCookieMonster {
+    fn default() -> Self {
+        Self {
+            credentials: CredentialSources::default(),
+            timeout: Duration::from_secs(300),
+        }
+    }
+}
 
 impl HeadlessCookieMonster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_credentials(mut self, credentials: CredentialSources) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// How long signing in can take, including waiting for a Duo push to be approved, before
+    /// giving up.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
     async fn wait_for_completion(client: &Client) -> anyhow::Result<()> {
         Ok(client
             .wait()
@@ -60,11 +124,17 @@ impl HeadlessCookieMonster {
             .await?)
     }
 
-    async fn complete_auth<DuoF: Fn(&str), PasscodeF: Fn() -> String>(
+    /// The passcode is asked for on a blocking thread, since that can mean waiting on the
+    /// terminal, so that the timeout can still fire while it's being waited on.
+    async fn complete_auth<DuoF, PasscodeF>(
         client: &Client,
         handle_duo_code: DuoF,
         get_passcode: PasscodeF,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<()>
+    where
+        DuoF: Fn(&str),
+        PasscodeF: FnOnce() -> anyhow::Result<String> + Send + 'static,
+    {
         let duo_task = Self::wait_for_duo_code(client).fuse();
         let passcode_task = Self::wait_for_passcode(client).fuse();
         let completion_task = Self::wait_for_completion(client).fuse();
@@ -82,7 +152,7 @@ impl HeadlessCookieMonster {
                 Self::wait_for_completion(client).await
             }
             passcode_element = passcode_task => {
-                let passcode = get_passcode();
+                let passcode = tokio::task::spawn_blocking(get_passcode).await??;
                 passcode_element?.send_keys(&passcode).await?;
 
                 let verify_button = client
@@ -105,149 +175,173 @@ impl HeadlessCookieMonster {
                         Self::wait_for_completion(client).await
                     }
                     _ = completion_task => Ok(()),
-                    _ = passcode_failure_task => {
-                        Err(anyhow!("Incorrect passcode"))
-                    }
+                    _ = passcode_failure_task => Err(AuthError::IncorrectPasscode.into()),
                 }
             }
             _ = completion_task => Ok(()),
-            _ = failure_task => {
-                Err(anyhow!("Incorrect username or password"))
-            }
+            _ = failure_task => Err(AuthError::IncorrectPassword.into()),
         }
     }
 
-    fn eat_user_cookies<DuoF: Fn(&str), PasscodeF: Fn() -> String>(
-        username: &str,
-        password: &str,
-        handle_duo_code: DuoF,
-        get_passcode: PasscodeF,
-    ) -> anyhow::Result<String> {
-        // Ensure that webdriver is installed
-        let strategy = choose_base_strategy().unwrap();
-        let data_dir = {
-            let mut data_dir = strategy.data_dir();
-            data_dir.push("blackboardfs");
-            std::fs::create_dir_all(&data_dir).expect("failed to create data dir");
-            data_dir
-        };
-
+    /// Installs geckodriver into the data directory if it isn't already, and runs it.
+    fn run_driver(data_dir: &Path) -> anyhow::Result<std::process::Child> {
         webdriver_install::Driver::Gecko
-            .install_into(data_dir.clone())
-            .map_err(|err| anyhow!(format!("failed to install gecko webdriver: {:?}", err)))?;
+            .install_into(data_dir.to_path_buf())
+            .map_err(|err| anyhow!("failed to install gecko webdriver: {err:?}"))?;
 
-        let mut driver_path = data_dir;
-        driver_path.push("geckodriver");
-
-        // Run the webdriver
-        let mut driver = Command::new(driver_path)
+        let mut driver = Command::new(data_dir.join("geckodriver"))
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped())
             .spawn()
-            .expect("failed to run geckodriver");
+            .map_err(|err| anyhow!("failed to run geckodriver: {err}"))?;
 
+        // It's ready once it says what it's listening on
         let driver_stdout = driver.stdout.as_mut().unwrap();
-        let reader = std::io::BufReader::new(driver_stdout);
-        reader
-            .lines()
-            .next()
-            .expect("expected first line of webdriver output")
-            .expect("expected first line of webdriver output");
+        let ready = std::io::BufReader::new(driver_stdout).lines().next();
+        if !matches!(ready, Some(Ok(_))) {
+            Self::stop_driver(driver);
+            return Err(anyhow!("geckodriver exited before it started"));
+        }
+        Ok(driver)
+    }
+
+    fn stop_driver(mut driver: std::process::Child) {
+        if let Err(err) = driver.kill() {
+            tracing::warn!("failed to kill webdriver: {err}");
+        }
+        // Reaped so that it doesn't linger as a zombie
+        let _ = driver.wait();
+    }
+
+    async fn connect() -> anyhow::Result<Client> {
+        let mut firefox_options = serde_json::Map::<String, serde_json::Value>::new();
+        firefox_options.insert("args".into(), vec!["-headless"].into());
+        let mut capabilities = serde_json::Map::<String, serde_json::Value>::new();
+        capabilities.insert(
+            "moz:firefoxOptions".into(),
+            serde_json::Value::Object(firefox_options),
+        );
+
+        ClientBuilder::native()
+            .capabilities(capabilities)
+            .connect("http://localhost:4444")
+            .await
+            .map_err(|err| anyhow!("failed to connect to WebDriver: {err}"))
+    }
+
+    async fn sign_in<DuoF, PasscodeF>(
+        c: &Client,
+        username: &str,
+        password: &str,
+        handle_duo_code: DuoF,
+        get_passcode: PasscodeF,
+    ) -> anyhow::Result<String>
+    where
+        DuoF: Fn(&str),
+        PasscodeF: FnOnce() -> anyhow::Result<String> + Send + 'static,
+    {
+        c.goto("https://learn.uq.edu.au").await?;
+
+        let username_field = c
+            .wait()
+            .forever()
+            .for_element(Locator::Id("username"))
+            .await?;
+        let password_field = c
+            .wait()
+            .forever()
+            .for_element(Locator::Id("password"))
+            .await?;
+        let submit_button = c
+            .wait()
+            .forever()
+            .for_element(Locator::Css("#loginuserpass .button"))
+            .await?;
+
+        username_field.send_keys(username).await?;
+        password_field.send_keys(password).await?;
+        submit_button.click().await?;
+
+        Self::complete_auth(c, handle_duo_code, get_passcode).await?;
+
+        let megacookie = c
+            .get_all_cookies()
+            .await?
+            .into_iter()
+            .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+            .reduce(|mut megacookie, cookie| {
+                megacookie.push(';');
+                megacookie.push_str(&cookie);
+                megacookie
+            })
+            .unwrap_or_default();
+
+        Ok(megacookie)
+    }
+
+    fn eat_user_cookies<DuoF, PasscodeF>(
+        &self,
+        data_dir: &Path,
+        username: &str,
+        password: &str,
+        handle_duo_code: DuoF,
+        get_passcode: PasscodeF,
+    ) -> anyhow::Result<String>
+    where
+        DuoF: Fn(&str),
+        PasscodeF: FnOnce() -> anyhow::Result<String> + Send + 'static,
+    {
+        let driver = Self::run_driver(data_dir)?;
 
         let result = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .build()
-            .unwrap()
-            .block_on(async {
-                let mut firefox_options = serde_json::Map::<String, serde_json::Value>::new();
-                firefox_options.insert("args".into(), vec!["-headless"].into());
-                let mut capabilities = serde_json::Map::<String, serde_json::Value>::new();
-                capabilities.insert(
-                    "moz:firefoxOptions".into(),
-                    serde_json::Value::Object(firefox_options),
-                );
-
-                let c = ClientBuilder::native()
-                    .capabilities(capabilities)
-                    .connect("http://localhost:4444")
-                    .await
-                    .expect("failed to connect to WebDriver");
-
-                // first, go to the Wikipedia page for Foobar
-                c.goto("https://learn.uq.edu.au").await?;
-
-                let username_field = c
-                    .wait()
-                    .forever()
-                    .for_element(Locator::Id("username"))
-                    .await?;
-                let password_field = c
-                    .wait()
-                    .forever()
-                    .for_element(Locator::Id("password"))
-                    .await?;
-                let submit_button = c
-                    .wait()
-                    .forever()
-                    .for_element(Locator::Css("#loginuserpass .button"))
-                    .await?;
-
-                username_field.send_keys(username).await?;
-                password_field.send_keys(password).await?;
-                submit_button.click().await?;
-
-                match Self::complete_auth(&c, handle_duo_code, get_passcode).await {
-                    Ok(()) => {}
-                    Err(err) => {
-                        c.close().await?;
-                        return Err(err);
+            .map_err(anyhow::Error::from)
+            .and_then(|runtime| {
+                let result = runtime.block_on(async {
+                    let c = Self::connect().await?;
+                    let signed_in =
+                        Self::sign_in(&c, username, password, handle_duo_code, get_passcode);
+                    let result = tokio::time::timeout(self.timeout, signed_in)
+                        .await
+                        .unwrap_or(Err(AuthError::TimedOut(self.timeout).into()));
+                    // Closed however signing in ended, so that Firefox isn't left running
+                    if let Err(err) = c.close().await {
+                        tracing::warn!("failed to close the WebDriver session: {err}");
                     }
-                }
-
-                let megacookie = c
-                    .get_all_cookies()
-                    .await?
-                    .into_iter()
-                    .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
-                    .reduce(|mut megacookie, cookie| {
-                        megacookie.push(';');
-                        megacookie.push_str(&cookie);
-                        megacookie
-                    })
-                    .unwrap_or_default();
-
-                c.close().await?;
-
-                Ok(megacookie)
+                    result
+                });
+                // A passcode prompt that timed out is still waiting on the terminal, and it
+                // would hold up dropping the runtime until it's answered
+                runtime.shutdown_background();
+                result
             });
 
-        driver.kill().expect("failed to kill webdriver");
+        Self::stop_driver(driver);
 
         result
     }
 }
 
 impl CookieMonster for HeadlessCookieMonster {
-    fn authenticate(&self, _data_dir: &Path) -> anyhow::Result<String> {
-        print!("Username: ");
-        let _ = stdout().flush();
-        let mut username = "".into();
-        stdin().read_line(&mut username).expect("expected username");
-
-        print!("Password: ");
-        let _ = stdout().flush();
-        let password = read_password().expect("expected password");
+    fn authenticate(&self, data_dir: &Path) -> anyhow::Result<String> {
+        let credentials = self.credentials.credentials()?;
 
         let handle_duo_code = |duo_code: &str| println!("Your duo code is {duo_code}");
 
-        let get_passcode = || {
-            print!("Passcode: ");
-            let _ = stdout().flush();
-            let mut passcode = "".into();
-            stdin().read_line(&mut passcode).expect("expected passcode");
-            passcode.trim().to_string()
+        // A passcode is generated if the secret behind them is known, and asked for otherwise
+        let totp_secret = credentials.totp_secret.clone();
+        let get_passcode = move || match totp_secret {
+            Some(secret) => totp(&secret),
+            None => prompt("Passcode: "),
         };
 
-        Self::eat_user_cookies(&username, &password, handle_duo_code, get_passcode)
+        self.eat_user_cookies(
+            data_dir,
+            &credentials.username,
+            &credentials.password,
+            handle_duo_code,
+            get_passcode,
+        )
     }
 }
@@ -7,10 +7,12 @@ use std::path::Path;
 use anyhow::anyhow;
 use wry::webview::Url;
 
+pub mod credentials;
 pub mod headless;
 pub mod webview;
 
-pub use headless::HeadlessCookieMonster;
+pub use credentials::CredentialSources;
+pub use headless::{AuthError, HeadlessCookieMonster};
 pub use webview::WebViewCookieMonster;
 
 // TODO: If bbfs-scrape starts depending directly on cookie-monster, it should use this constant
@@ -6,6 +6,7 @@ use std::{
 };
 
 use anyhow::anyhow;
+use tracing::error;
 
 use wry::{
     application::{
@@ -123,10 +124,10 @@ impl WebViewCookieMonster {
                 let res = File::create(cookie_file);
                 if let Ok(mut file) = res {
                     if file.write_all(megacookie.as_bytes()).is_err() {
-                        eprintln!("Failed to write cookie");
+                        error!("failed to write cookie");
                     }
                 } else {
-                    eprintln!("Failed to create file: {:?}", res.err().unwrap());
+                    error!("failed to create cookie file: {}", res.err().unwrap());
                 }
 
                 webview.load_url("https://macos-done");
@@ -276,7 +277,7 @@ impl CookieMonster for WebViewCookieMonster {
                     // Only Linux gets here
                     if let Ok(mut file) = File::create(cookie_file) {
                         if file.write_all(cookie.as_bytes()).is_err() {
-                            eprintln!("failed to write cookie");
+                            error!("failed to write cookie");
                         }
                     }
                     drop(webview.take().expect("WebView should only be dropped once"));